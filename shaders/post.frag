@@ -1,13 +1,189 @@
 #version 450
 
+#define TONE_MAPPER_NONE 0
+#define TONE_MAPPER_REINHARD 1
+#define TONE_MAPPER_ACES_FITTED 2
+#define TONE_MAPPER_AGX 3
+#define TONE_MAPPER_FILMIC 4
+
+// 0 shows the radiance, the others show an AOV (`Aov` + 1)
+#define VIEW_RADIANCE 0
+#define VIEW_ALBEDO 1
+#define VIEW_NORMAL 2
+#define VIEW_DEPTH 3
+#define VIEW_POSITION 4
+#define VIEW_OBJECT_ID 5
+#define VIEW_MATERIAL_ID 6
+
+// see `AUX_*` in `ray_tracing.comp`
+#define AUX_ALBEDO 0
+#define AUX_NORMAL_DEPTH 1
+#define AUX_POSITION 3
+#define AUX_IDS 4
+
 layout (location = 0) in vec2 v_texcoords;
 
 layout (location = 0) out vec4 frag_color;
 
 layout (location = 0) uniform sampler2D traced_img;
+layout (binding = 1) uniform sampler2DArray aux_img;
+
+// keep in sync with `DisplayConfig::apply`
+layout(std140, binding = 3) uniform PostUniform {
+    mat3 white_balance;
+    float exposure_scale;
+    int tone_mapper;
+    int srgb;
+    int view;
+    // object highlighted in the window, -1 for none
+    int selected_object;
+    float _pu_pad0;
+    vec2 _pu_pad;
+};
+
+const vec3 HIGHLIGHT_COLOR = vec3(1.0, 0.6, 0.1);
+
+// ACES fitted by Stephen Hill
+vec3 aces_fitted(vec3 color) {
+    const mat3 input_mat = mat3(
+        0.59719, 0.07600, 0.02840,
+        0.35458, 0.90834, 0.13383,
+        0.04823, 0.01566, 0.83777
+    );
+    const mat3 output_mat = mat3(
+        1.60475, -0.10208, -0.00327,
+        -0.53108, 1.10813, -0.07276,
+        -0.07367, -0.00605, 1.07602
+    );
+    color = input_mat * color;
+    vec3 a = color * (color + 0.0245786) - 0.000090537;
+    vec3 b = color * (0.983729 * color + 0.432951) + 0.238081;
+    return output_mat * (a / b);
+}
+
+// AgX base look, with the polynomial fit of the contrast curve by Benjamin Wrensch
+vec3 agx(vec3 color) {
+    const float min_ev = -12.47393;
+    const float max_ev = 4.026069;
+    const mat3 inset_mat = mat3(
+        0.84247906, 0.042328242, 0.042375655,
+        0.0784336, 0.87846864, 0.0784336,
+        0.079223745, 0.07916613, 0.879143
+    );
+    const mat3 outset_mat = mat3(
+        1.196879, -0.052896852, -0.052971636,
+        -0.09802088, 1.1519031, -0.09804345,
+        -0.09902974, -0.098961177, 1.1510737
+    );
+    vec3 x = inset_mat * color;
+    x = (clamp(log2(max(x, vec3(1e-10))), min_ev, max_ev) - min_ev) / (max_ev - min_ev);
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    x = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+        - 0.00232;
+    // the curve outputs display encoded values, return to linear for the sRGB encoding
+    return pow(max(outset_mat * x, vec3(0.0)), vec3(2.2));
+}
+
+// Uncharted 2 filmic curve by John Hable
+vec3 filmic_curve(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+vec3 filmic(vec3 color) {
+    const float white = 11.2;
+    const float exposure_bias = 2.0;
+    return filmic_curve(color * exposure_bias) / filmic_curve(vec3(white));
+}
+
+vec3 srgb_encode(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+// distinct colors for neighbouring ids, black for -1
+vec3 id_color(float id) {
+    if (id < 0.0) {
+        return vec3(0.0);
+    }
+    uint h = uint(id) * 2654435769u;
+    h ^= h >> 16;
+    h *= 2246822519u;
+    h ^= h >> 13;
+    return vec3((h >> 24) & 255u, (h >> 16) & 255u, (h >> 8) & 255u) / 255.0;
+}
+
+vec3 aov_color(vec2 texcoords) {
+    // ids must not be interpolated
+    ivec2 pixel = ivec2(texcoords * vec2(textureSize(aux_img, 0).xy));
+    if (view == VIEW_ALBEDO) {
+        vec3 albedo = texelFetch(aux_img, ivec3(pixel, AUX_ALBEDO), 0).rgb;
+        return srgb != 0 ? srgb_encode(clamp(albedo, 0.0, 1.0)) : albedo;
+    } else if (view == VIEW_NORMAL) {
+        vec4 normal_depth = texelFetch(aux_img, ivec3(pixel, AUX_NORMAL_DEPTH), 0);
+        return normal_depth.w < 0.0 ? vec3(0.0) : normal_depth.xyz * 0.5 + 0.5;
+    } else if (view == VIEW_DEPTH) {
+        float depth = texelFetch(aux_img, ivec3(pixel, AUX_NORMAL_DEPTH), 0).w;
+        return vec3(depth < 0.0 ? 0.0 : 1.0 / (1.0 + depth));
+    } else if (view == VIEW_POSITION) {
+        // a grid of unit cells
+        vec4 position = texelFetch(aux_img, ivec3(pixel, AUX_POSITION), 0);
+        return fract(position.xyz) * position.w;
+    } else {
+        vec2 ids = texelFetch(aux_img, ivec3(pixel, AUX_IDS), 0).xy;
+        return id_color(view == VIEW_OBJECT_ID ? ids.x : ids.y);
+    }
+}
+
+bool is_selected(ivec2 pixel) {
+    ivec2 size = textureSize(aux_img, 0).xy;
+    pixel = clamp(pixel, ivec2(0), size - 1);
+    return int(texelFetch(aux_img, ivec3(pixel, AUX_IDS), 0).x) == selected_object;
+}
+
+// tints the selected object and outlines it where its neighbours are other objects
+vec3 highlight(vec2 texcoords, vec3 color) {
+    if (selected_object < 0) {
+        return color;
+    }
+    ivec2 pixel = ivec2(texcoords * vec2(textureSize(aux_img, 0).xy));
+    if (!is_selected(pixel)) {
+        return color;
+    }
+    bool edge = !is_selected(pixel + ivec2(1, 0)) || !is_selected(pixel - ivec2(1, 0))
+        || !is_selected(pixel + ivec2(0, 1)) || !is_selected(pixel - ivec2(0, 1));
+    return edge ? HIGHLIGHT_COLOR : mix(color, HIGHLIGHT_COLOR, 0.25);
+}
 
 void main() {
-    vec4 color = texture(traced_img, v_texcoords);
-    frag_color = color;
-    // frag_color = vec4(v_texcoords.x, v_texcoords.y, 0.5, 1.0);
+    if (view != VIEW_RADIANCE) {
+        frag_color = vec4(highlight(v_texcoords, aov_color(v_texcoords)), 1.0);
+        return;
+    }
+
+    vec3 color = texture(traced_img, v_texcoords).rgb;
+    color = white_balance * color * exposure_scale;
+
+    if (tone_mapper == TONE_MAPPER_REINHARD) {
+        color = color / (1.0 + color);
+    } else if (tone_mapper == TONE_MAPPER_ACES_FITTED) {
+        color = aces_fitted(color);
+    } else if (tone_mapper == TONE_MAPPER_AGX) {
+        color = agx(color);
+    } else if (tone_mapper == TONE_MAPPER_FILMIC) {
+        color = filmic(color);
+    }
+
+    color = clamp(color, 0.0, 1.0);
+    if (srgb != 0) {
+        color = srgb_encode(color);
+    }
+    frag_color = vec4(highlight(v_texcoords, color), 1.0);
 }
\ No newline at end of file
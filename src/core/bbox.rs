@@ -39,7 +39,7 @@ impl Bbox {
             0.0
         } else {
             let diff = self.p_max - self.p_min;
-            diff.x * diff.y * diff.z
+            2.0 * (diff.x * diff.y + diff.y * diff.z + diff.z * diff.x)
         }
     }
 
@@ -55,3 +55,49 @@ fn min_point3(a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) -> cgmath::Point3<
 fn max_point3(a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) -> cgmath::Point3<f32> {
     cgmath::Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::*;
+
+    #[test]
+    fn from_points_is_tight() {
+        let bbox = Bbox::from_points(&[
+            Point3::new(1.0, -2.0, 3.0),
+            Point3::new(-1.0, 4.0, 0.5),
+            Point3::new(0.0, 0.0, 5.0),
+        ]);
+        assert_eq!(bbox.p_min, Point3::new(-1.0, -2.0, 0.5));
+        assert_eq!(bbox.p_max, Point3::new(1.0, 4.0, 5.0));
+        assert_eq!(bbox.centroid(), Point3::new(0.0, 1.0, 2.75));
+    }
+
+    #[test]
+    fn empty_box() {
+        let empty = Bbox::empty();
+        assert!(empty.is_empty());
+        assert_eq!(empty.surface_area(), 0.0);
+
+        let point = Bbox::from_points(&[Point3::new(1.0, 2.0, 3.0)]);
+        assert!(!point.is_empty());
+        assert_eq!(point.surface_area(), 0.0);
+
+        let merged = empty.merge(point);
+        assert_eq!(merged.p_min, point.p_min);
+        assert_eq!(merged.p_max, point.p_max);
+    }
+
+    #[test]
+    fn merge_and_surface_area() {
+        let a = Bbox::from_points(&[Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)]);
+        let b = Bbox::from_points(&[Point3::new(2.0, 0.0, 0.0), Point3::new(3.0, 1.0, 1.0)]);
+        assert_eq!(a.surface_area(), 6.0);
+
+        let merged = a.merge(b);
+        assert_eq!(merged.p_min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(merged.p_max, Point3::new(3.0, 1.0, 1.0));
+        assert_eq!(merged.surface_area(), 14.0);
+    }
+}
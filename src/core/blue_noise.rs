@@ -0,0 +1,187 @@
+use super::rand_hash;
+
+/// Side length of the blue noise mask, same as `BLUE_NOISE_SIZE` in `ray_tracing.comp`
+pub const BLUE_NOISE_SIZE: u32 = 64;
+
+/// Tileable blue noise mask made with the void-and-cluster method (Ulichney 1993),
+/// its values are uniformly distributed in (0, 1)
+pub struct BlueNoise {
+    /// rows from the first to the last one like `imageLoad` coordinates
+    pub values: Vec<f32>,
+}
+
+/// Gaussian energy of a binary pattern on the torus
+struct EnergyField {
+    pattern: Vec<bool>,
+    energy: Vec<f32>,
+    kernel: Vec<f32>,
+}
+
+impl BlueNoise {
+    pub fn new() -> Self {
+        let size = BLUE_NOISE_SIZE as usize;
+        let count = size * size;
+        let mut ranks = vec![0; count];
+
+        // initial pattern with about 10% of the pixels set, relaxed until the tightest
+        // cluster is also the largest void
+        let initial: Vec<_> = (0..count as u32)
+            .map(|i| rand_hash(i) < u32::MAX / 10)
+            .collect();
+        let mut prototype = EnergyField::new(initial);
+        loop {
+            let cluster = prototype.tightest_cluster();
+            prototype.toggle(cluster);
+            let void = prototype.largest_void();
+            if void == cluster {
+                prototype.toggle(cluster);
+                break;
+            }
+            prototype.toggle(void);
+        }
+        let ones = prototype.pattern.iter().filter(|&&p| p).count();
+
+        // phase 1: remove the tightest clusters of the prototype
+        let mut field = EnergyField::new(prototype.pattern.clone());
+        for rank in (0..ones).rev() {
+            let cluster = field.tightest_cluster();
+            field.toggle(cluster);
+            ranks[cluster] = rank;
+        }
+
+        // phase 2: fill the largest voids up to half of the pixels
+        let mut field = prototype;
+        for rank in ones..count / 2 {
+            let void = field.largest_void();
+            field.toggle(void);
+            ranks[void] = rank;
+        }
+
+        // phase 3: the unset pixels are the minority now, so fill the tightest clusters of them
+        let mut field = EnergyField::new(field.pattern.iter().map(|&p| !p).collect());
+        for rank in count / 2..count {
+            let cluster = field.tightest_cluster();
+            field.toggle(cluster);
+            ranks[cluster] = rank;
+        }
+
+        let values = ranks
+            .into_iter()
+            .map(|rank| (rank as f32 + 0.5) / count as f32)
+            .collect();
+        Self { values }
+    }
+
+    pub fn value(&self, x: u32, y: u32) -> f32 {
+        self.values[((y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE) as usize]
+    }
+}
+
+impl Default for BlueNoise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnergyField {
+    const SIGMA: f32 = 1.5;
+
+    fn new(pattern: Vec<bool>) -> Self {
+        let size = BLUE_NOISE_SIZE as usize;
+        let toroidal = |d: usize| d.min(size - d) as f32;
+        let kernel = (0..size * size)
+            .map(|i| {
+                let (dx, dy) = (toroidal(i % size), toroidal(i / size));
+                (-(dx * dx + dy * dy) / (2.0 * Self::SIGMA * Self::SIGMA)).exp()
+            })
+            .collect();
+
+        let mut field = Self {
+            pattern: vec![false; size * size],
+            energy: vec![0.0; size * size],
+            kernel,
+        };
+        for (index, set) in pattern.into_iter().enumerate() {
+            if set {
+                field.toggle(index);
+            }
+        }
+        field
+    }
+
+    fn toggle(&mut self, index: usize) {
+        let size = BLUE_NOISE_SIZE as usize;
+        self.pattern[index] = !self.pattern[index];
+        let sign = if self.pattern[index] { 1.0 } else { -1.0 };
+        let (px, py) = (index % size, index / size);
+        for y in 0..size {
+            let dy = (y + size - py) % size;
+            for x in 0..size {
+                let dx = (x + size - px) % size;
+                self.energy[y * size + x] += sign * self.kernel[dy * size + dx];
+            }
+        }
+    }
+
+    /// Set pixel with the highest energy
+    fn tightest_cluster(&self) -> usize {
+        self.find(true, |a, b| a > b)
+    }
+
+    /// Unset pixel with the lowest energy
+    fn largest_void(&self) -> usize {
+        self.find(false, |a, b| a < b)
+    }
+
+    fn find(&self, set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best: Option<usize> = None;
+        for (index, &energy) in self.energy.iter().enumerate() {
+            if self.pattern[index] == set && best.is_none_or(|b| better(energy, self.energy[b])) {
+                best = Some(index);
+            }
+        }
+        best.expect("blue noise: pattern is full or empty")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_a_permutation_of_ranks() {
+        let noise = BlueNoise::new();
+        let count = (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as usize;
+        let mut ranks: Vec<_> = noise
+            .values
+            .iter()
+            .map(|v| (v * count as f32 - 0.5).round() as usize)
+            .collect();
+        ranks.sort_unstable();
+        assert!(ranks.into_iter().eq(0..count));
+    }
+
+    #[test]
+    fn small_blocks_are_balanced() {
+        // white noise has a standard deviation of about 0.072 for means of 16 values
+        let noise = BlueNoise::new();
+        let blocks = BLUE_NOISE_SIZE / 4;
+        let means: Vec<f32> = (0..blocks * blocks)
+            .map(|b| {
+                let (bx, by) = (b % blocks * 4, b / blocks * 4);
+                (0..16)
+                    .map(|i| noise.value(bx + i % 4, by + i / 4))
+                    .sum::<f32>()
+                    / 16.0
+            })
+            .collect();
+        let mean = means.iter().sum::<f32>() / means.len() as f32;
+        let variance =
+            means.iter().map(|m| (m - mean) * (m - mean)).sum::<f32>() / means.len() as f32;
+        assert!(
+            variance.sqrt() < 0.04,
+            "std of block means {}",
+            variance.sqrt()
+        );
+    }
+}
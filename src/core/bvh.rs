@@ -1,19 +1,39 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, ops::Range};
 
 use super::Triangle;
 use crate::{core::Bbox, uniforms};
 
 pub struct BvhAccel {
-    bvh_root: Option<Box<BvhNode>>,
+    pub(super) bvh_root: Option<Box<BvhNode>>,
 }
 
-struct BvhNode {
-    lc: Option<Box<BvhNode>>,
-    rc: Option<Box<BvhNode>>,
-    bbox: Bbox,
-    start: usize,
-    end: usize,
-    index: u32,
+/// Callbacks of `BvhAccel::traverse`, nodes are visited in the same order as `ray_tracing.comp` does
+pub trait BvhVisitor {
+    /// Whether the children or primitives of a node with this box should be visited
+    fn visit_bbox(&mut self, bbox: &Bbox) -> bool;
+
+    /// Visits primitives `range` of a leaf, returns true to stop the traversal
+    fn visit_leaf(&mut self, range: Range<usize>) -> bool;
+}
+
+pub struct BvhStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub primitive_count: usize,
+    pub depth_histogram: Vec<usize>,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub average_leaf_size: f32,
+    pub sah_cost: f32,
+}
+
+pub(super) struct BvhNode {
+    pub(super) lc: Option<Box<BvhNode>>,
+    pub(super) rc: Option<Box<BvhNode>>,
+    pub(super) bbox: Bbox,
+    pub(super) start: usize,
+    pub(super) end: usize,
+    pub(super) index: u32,
 }
 
 impl BvhAccel {
@@ -41,8 +61,15 @@ impl BvhAccel {
                 continue;
             }
 
-            let bbox = u.bbox;
-            let len_per_bucket = (bbox.p_max - bbox.p_min) / bucket_number as f32;
+            // buckets span the centroids, not the node box, so every primitive falls in one
+            let mut centroid_bbox = Bbox::empty();
+            for tri in &triangles[u.start..u.end] {
+                let centroid = tri.bbox().centroid();
+                centroid_bbox = centroid_bbox.merge(Bbox::from_points(&[centroid]));
+            }
+            let len_per_bucket = (centroid_bbox.p_max - centroid_bbox.p_min) / bucket_number as f32;
+            let bucket_of =
+                |offset: f32, len: f32| ((offset / len).max(0.0) as usize).min(bucket_number - 1);
 
             let mut boxes_x = vec![Bbox::empty(); bucket_number];
             let mut boxes_y = vec![Bbox::empty(); bucket_number];
@@ -56,44 +83,46 @@ impl BvhAccel {
                 let bbox = triangles[i].bbox();
                 let centroid = bbox.centroid();
 
-                let x = (centroid.x - bbox.p_min.x) / len_per_bucket.x;
-                if x >= 0.0 && x < bucket_number as f32 {
-                    let x = x as usize;
+                if len_per_bucket.x > 0.0 {
+                    let x = bucket_of(centroid.x - centroid_bbox.p_min.x, len_per_bucket.x);
                     boxes_x[x] = boxes_x[x].merge(bbox);
                     prim_indices_x[x].push(i);
                 }
 
-                let y = (centroid.y - bbox.p_min.y) / len_per_bucket.y;
-                if y >= 0.0 && y < bucket_number as f32 {
-                    let y = y as usize;
+                if len_per_bucket.y > 0.0 {
+                    let y = bucket_of(centroid.y - centroid_bbox.p_min.y, len_per_bucket.y);
                     boxes_y[y] = boxes_y[y].merge(bbox);
                     prim_indices_y[y].push(i);
                 }
 
-                let z = (centroid.z - bbox.p_min.z) / len_per_bucket.z;
-                if z >= 0.0 && z < bucket_number as f32 {
-                    let z = z as usize;
+                if len_per_bucket.z > 0.0 {
+                    let z = bucket_of(centroid.z - centroid_bbox.p_min.z, len_per_bucket.z);
                     boxes_z[z] = boxes_z[z].merge(bbox);
                     prim_indices_z[z].push(i);
                 }
             }
 
-            let (best_cost_x, best_split_x) = if len_per_bucket.x > 0.0001 {
+            let (best_cost_x, best_split_x) = if len_per_bucket.x > 0.0 {
                 Self::find_best_split(&boxes_x, &prim_indices_x, u.size(), bucket_number)
             } else {
                 (f32::MAX, bucket_number / 2)
             };
-            let (best_cost_y, best_split_y) = if len_per_bucket.y > 0.0001 {
+            let (best_cost_y, best_split_y) = if len_per_bucket.y > 0.0 {
                 Self::find_best_split(&boxes_y, &prim_indices_y, u.size(), bucket_number)
             } else {
                 (f32::MAX, bucket_number / 2)
             };
-            let (best_cost_z, best_split_z) = if len_per_bucket.z > 0.0001 {
+            let (best_cost_z, best_split_z) = if len_per_bucket.z > 0.0 {
                 Self::find_best_split(&boxes_z, &prim_indices_z, u.size(), bucket_number)
             } else {
                 (f32::MAX, bucket_number / 2)
             };
 
+            // all centroids coincide or no split separates them, keep the node as a leaf
+            if best_cost_x == f32::MAX && best_cost_y == f32::MAX && best_cost_z == f32::MAX {
+                continue;
+            }
+
             let (lc, rc) = if best_cost_x <= best_cost_y && best_cost_x <= best_cost_z {
                 Self::split_at(
                     best_split_x,
@@ -128,9 +157,6 @@ impl BvhAccel {
                     &mut curr_node_index,
                 )
             };
-            if lc.size() == 0 || rc.size() == 0 {
-                continue;
-            }
             u.lc = Some(lc);
             u.rc = Some(rc);
 
@@ -169,6 +195,81 @@ impl BvhAccel {
         }
     }
 
+    pub fn traverse<V: BvhVisitor>(&self, visitor: &mut V) {
+        let mut stack = match self.bvh_root.as_ref() {
+            Some(root) => vec![root],
+            None => return,
+        };
+        while let Some(u) = stack.pop() {
+            if !visitor.visit_bbox(&u.bbox) {
+                continue;
+            }
+
+            if u.is_leaf() {
+                if visitor.visit_leaf(u.start..u.end) {
+                    return;
+                }
+            } else {
+                stack.push(u.lc.as_ref().unwrap());
+                stack.push(u.rc.as_ref().unwrap());
+            }
+        }
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        const TRAVERSAL_COST: f32 = 1.0;
+        const INTERSECTION_COST: f32 = 1.0;
+
+        let mut stats = BvhStats {
+            node_count: 0,
+            leaf_count: 0,
+            primitive_count: 0,
+            depth_histogram: vec![],
+            min_leaf_size: 0,
+            max_leaf_size: 0,
+            average_leaf_size: 0.0,
+            sah_cost: 0.0,
+        };
+        let root = match self.bvh_root.as_ref() {
+            Some(root) => root,
+            None => return stats,
+        };
+
+        // SAH cost is relative to the root box; a degenerate root falls back to unit cost per node
+        let root_area = root.bbox.surface_area();
+        let relative_area = |bbox: &Bbox| {
+            if root_area > 0.0 {
+                bbox.surface_area() / root_area
+            } else {
+                1.0
+            }
+        };
+
+        stats.min_leaf_size = usize::MAX;
+        let mut stack = vec![(root, 0)];
+        while let Some((u, depth)) = stack.pop() {
+            stats.node_count += 1;
+            if u.is_leaf() {
+                stats.leaf_count += 1;
+                stats.primitive_count += u.size();
+                stats.min_leaf_size = stats.min_leaf_size.min(u.size());
+                stats.max_leaf_size = stats.max_leaf_size.max(u.size());
+                if stats.depth_histogram.len() <= depth {
+                    stats.depth_histogram.resize(depth + 1, 0);
+                }
+                stats.depth_histogram[depth] += 1;
+                stats.sah_cost += INTERSECTION_COST * u.size() as f32 * relative_area(&u.bbox);
+            } else {
+                stats.sah_cost += TRAVERSAL_COST * relative_area(&u.bbox);
+                stack.push((u.lc.as_ref().unwrap(), depth + 1));
+                stack.push((u.rc.as_ref().unwrap(), depth + 1));
+            }
+        }
+        stats.average_leaf_size = stats.primitive_count as f32 / stats.leaf_count as f32;
+
+        stats
+    }
+
     fn find_best_split(
         boxes: &Vec<Bbox>,
         prim_indices: &Vec<Vec<usize>>,
@@ -189,6 +290,10 @@ impl BvhAccel {
             boxes_r[i] = boxes_r[i].merge(boxes_r[i + 1]);
         }
         for i in 1..bucket_number {
+            // a split with an empty side doesn't make progress
+            if prim_count[i - 1] == 0 || prim_count[i - 1] == prim_total_count {
+                continue;
+            }
             let left_surface_area = boxes_l[i - 1].surface_area();
             let left_count = prim_count[i - 1] as f32;
             let right_surface_area = boxes_r[i].surface_area();
@@ -246,8 +351,43 @@ impl BvhAccel {
     }
 }
 
+impl BvhStats {
+    pub fn max_depth(&self) -> usize {
+        self.depth_histogram.len().saturating_sub(1)
+    }
+}
+
+impl std::fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "BVH statistics:")?;
+        writeln!(f, "  nodes: {}", self.node_count)?;
+        writeln!(f, "  leaves: {}", self.leaf_count)?;
+        writeln!(f, "  primitives: {}", self.primitive_count)?;
+        writeln!(
+            f,
+            "  leaf size: min {}, max {}, average {:.2}",
+            self.min_leaf_size, self.max_leaf_size, self.average_leaf_size
+        )?;
+        writeln!(f, "  SAH cost: {:.3}", self.sah_cost)?;
+        writeln!(f, "  max depth: {}", self.max_depth())?;
+        write!(f, "  leaves per depth:")?;
+        let max_count = self
+            .depth_histogram
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        for (depth, count) in self.depth_histogram.iter().enumerate() {
+            let bar_len = (count * 40).div_ceil(max_count);
+            write!(f, "\n    {:3} | {:6} {}", depth, count, "#".repeat(bar_len))?;
+        }
+        Ok(())
+    }
+}
+
 impl BvhNode {
-    fn new(start: usize, end: usize, bbox: Bbox, index: u32) -> Self {
+    pub(super) fn new(start: usize, end: usize, bbox: Bbox, index: u32) -> Self {
         Self {
             lc: None,
             rc: None,
@@ -261,7 +401,251 @@ impl BvhNode {
     fn size(&self) -> usize {
         self.end - self.start
     }
-    fn is_leaf(&self) -> bool {
+    pub(super) fn is_leaf(&self) -> bool {
         self.lc.is_none()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        core::{MeshVertex, Shape, TriangleMesh},
+        cpu::{ClosestHitVisitor, CpuGeometry, CpuTriangle, Ray},
+    };
+
+    fn make_triangles(positions: &[[Point3<f32>; 3]]) -> Vec<Triangle> {
+        let vertices = positions
+            .iter()
+            .flatten()
+            .map(|&position| MeshVertex {
+                position,
+                ..Default::default()
+            })
+            .collect();
+        let indices = (0..positions.len() as u32 * 3).collect();
+        let mesh = Rc::new(TriangleMesh::new(vertices, indices, 0));
+        (0..positions.len())
+            .map(|i| {
+                let indices = [i * 3, i * 3 + 1, i * 3 + 2];
+                Triangle::new(mesh.clone(), i as u32, indices, 0, &Matrix4::identity(), 0)
+            })
+            .collect()
+    }
+
+    fn bbox_of(points: &[(f32, f32, f32)]) -> Bbox {
+        let points: Vec<_> = points
+            .iter()
+            .map(|&(x, y, z)| Point3::new(x, y, z))
+            .collect();
+        Bbox::from_points(&points)
+    }
+
+    fn contains(outer: &Bbox, inner: &Bbox) -> bool {
+        outer.p_min.x <= inner.p_min.x
+            && outer.p_min.y <= inner.p_min.y
+            && outer.p_min.z <= inner.p_min.z
+            && outer.p_max.x >= inner.p_max.x
+            && outer.p_max.y >= inner.p_max.y
+            && outer.p_max.z >= inner.p_max.z
+    }
+
+    fn nodes(bvh: &BvhAccel) -> Vec<&BvhNode> {
+        let mut nodes = vec![];
+        let mut stack: Vec<&BvhNode> = bvh.bvh_root.iter().map(|root| root.as_ref()).collect();
+        while let Some(u) = stack.pop() {
+            nodes.push(u);
+            if !u.is_leaf() {
+                stack.push(u.lc.as_ref().unwrap());
+                stack.push(u.rc.as_ref().unwrap());
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn find_best_split_separates_clusters() {
+        let near = bbox_of(&[(0.0, 0.0, 0.0), (1.0, 1.0, 1.0)]);
+        let far = bbox_of(&[(9.0, 0.0, 0.0), (10.0, 1.0, 1.0)]);
+        let empty = Bbox::empty();
+        let boxes = vec![near, empty, empty, far];
+        let prim_indices = vec![vec![0, 1], vec![], vec![], vec![2]];
+
+        let (cost, split) = BvhAccel::find_best_split(&boxes, &prim_indices, 3, 4);
+        assert!(cost < f32::MAX);
+        assert!((1..=3).contains(&split));
+        assert_eq!(cost, near.surface_area() * 2.0 + far.surface_area());
+    }
+
+    #[test]
+    fn find_best_split_rejects_empty_sides() {
+        let bbox = bbox_of(&[(0.0, 0.0, 0.0), (1.0, 1.0, 1.0)]);
+        let boxes = vec![Bbox::empty(), bbox, Bbox::empty()];
+        let prim_indices = vec![vec![], vec![0, 1, 2], vec![]];
+
+        let (cost, _) = BvhAccel::find_best_split(&boxes, &prim_indices, 3, 3);
+        assert_eq!(cost, f32::MAX);
+    }
+
+    #[test]
+    fn split_at_partitions_primitives() {
+        let positions: Vec<_> = [5.0, 0.0, 6.0, 1.0, 7.0]
+            .iter()
+            .map(|&x| {
+                [
+                    Point3::new(x, 0.0, 0.0),
+                    Point3::new(x + 0.5, 0.0, 0.0),
+                    Point3::new(x, 1.0, 0.0),
+                ]
+            })
+            .collect();
+        let mut triangles = make_triangles(&positions);
+        let boxes = vec![
+            triangles[1].bbox().merge(triangles[3].bbox()),
+            triangles[0]
+                .bbox()
+                .merge(triangles[2].bbox())
+                .merge(triangles[4].bbox()),
+        ];
+        let mut prim_indices = vec![vec![1, 3], vec![0, 2, 4]];
+        let mut curr_node_index = 1;
+
+        let (lc, rc) = BvhAccel::split_at(
+            1,
+            2,
+            &boxes,
+            &mut prim_indices,
+            &mut triangles,
+            0,
+            5,
+            &mut curr_node_index,
+        );
+        assert_eq!((lc.start, lc.end, lc.index), (0, 2, 1));
+        assert_eq!((rc.start, rc.end, rc.index), (2, 5, 2));
+        assert_eq!(curr_node_index, 3);
+        for tri in &triangles[lc.start..lc.end] {
+            assert!(tri.bbox().p_max.x < 5.0 && contains(&lc.bbox, &tri.bbox()));
+        }
+        for tri in &triangles[rc.start..rc.end] {
+            assert!(tri.bbox().p_min.x >= 5.0 && contains(&rc.bbox, &tri.bbox()));
+        }
+    }
+
+    // coordinates on a coarse grid produce coincident centroids and flat boxes
+    fn coordinate() -> impl Strategy<Value = f32> {
+        prop_oneof![-10.0f32..10.0, (0..3).prop_map(|v| v as f32)]
+    }
+
+    fn point() -> impl Strategy<Value = Point3<f32>> {
+        (coordinate(), coordinate(), coordinate()).prop_map(|(x, y, z)| Point3::new(x, y, z))
+    }
+
+    fn triangle_positions() -> impl Strategy<Value = Vec<[Point3<f32>; 3]>> {
+        prop::collection::vec([point(), point(), point()], 1..200)
+    }
+
+    proptest! {
+        #[test]
+        fn every_triangle_is_in_exactly_one_leaf(
+            positions in triangle_positions(),
+            max_leaf_size in 1usize..8,
+            bucket_number in 2usize..32,
+        ) {
+            let mut triangles = make_triangles(&positions);
+            let bvh = BvhAccel::new(&mut triangles, max_leaf_size, bucket_number);
+
+            let mut original: Vec<_> = triangles.iter().map(|tri| tri.index).collect();
+            original.sort_unstable();
+            prop_assert_eq!(original, (0..positions.len() as u32).collect::<Vec<_>>());
+
+            let nodes = nodes(&bvh);
+            let mut ranges: Vec<_> = nodes
+                .iter()
+                .filter(|u| u.is_leaf())
+                .map(|u| (u.start, u.end))
+                .collect();
+            ranges.sort_unstable();
+            let mut next = 0;
+            for (start, end) in ranges {
+                prop_assert_eq!(start, next);
+                prop_assert!(end > start);
+                next = end;
+            }
+            prop_assert_eq!(next, triangles.len());
+
+            let mut indices: Vec<_> = nodes.iter().map(|u| u.index).collect();
+            indices.sort_unstable();
+            prop_assert_eq!(indices, (0..nodes.len() as u32).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn node_boxes_contain_their_primitives(
+            positions in triangle_positions(),
+            max_leaf_size in 1usize..8,
+            bucket_number in 2usize..32,
+        ) {
+            let mut triangles = make_triangles(&positions);
+            let bvh = BvhAccel::new(&mut triangles, max_leaf_size, bucket_number);
+
+            for u in nodes(&bvh) {
+                for tri in &triangles[u.start..u.end] {
+                    prop_assert!(contains(&u.bbox, &tri.bbox()));
+                }
+                if !u.is_leaf() {
+                    prop_assert!(contains(&u.bbox, &u.lc.as_ref().unwrap().bbox));
+                    prop_assert!(contains(&u.bbox, &u.rc.as_ref().unwrap().bbox));
+                }
+            }
+        }
+
+        #[test]
+        fn closest_hit_matches_brute_force(
+            positions in prop::collection::vec([point(), point(), point()], 1..100),
+            rays in prop::collection::vec(
+                (point(), (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0)),
+                16,
+            ),
+        ) {
+            let mut triangles = make_triangles(&positions);
+            let bvh = BvhAccel::new(&mut triangles, 4, 16);
+            let cpu_triangles: Vec<_> = triangles
+                .iter()
+                .map(|tri| match &tri.shape {
+                    Shape::Mesh { mesh, indices } => CpuTriangle {
+                        geometry: CpuGeometry::Triangle {
+                            positions: indices.map(|i| mesh.position(i)),
+                            normals: [Vector3::unit_z(); 3],
+                        },
+                        material: 0,
+                        object: 0,
+                    },
+                    Shape::Analytic(_) => unreachable!(),
+                })
+                .collect();
+
+            for (origin, (x, y, z)) in rays {
+                let direction = Vector3::new(x, y, z);
+                if direction.magnitude2() < 1e-4 {
+                    continue;
+                }
+                let ray = Ray::new(origin, direction.normalize());
+
+                let mut visitor = ClosestHitVisitor::new(ray, &cpu_triangles);
+                bvh.traverse(&mut visitor);
+                let bvh_t = Some(visitor.inter.t).filter(|_| visitor.hit);
+
+                let brute_t = cpu_triangles.iter().fold(None, |best: Option<f32>, tri| {
+                    tri.intersect(&ray, best.unwrap_or(1e9))
+                        .map(|(t, _, _)| t)
+                        .or(best)
+                });
+                prop_assert_eq!(bvh_t, brute_t);
+            }
+        }
+    }
+}
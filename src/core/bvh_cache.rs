@@ -0,0 +1,388 @@
+use std::{
+    convert::TryInto,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+
+use super::{Bbox, BvhAccel, BvhNode, Triangle};
+
+const CACHE_MAGIC: &[u8; 8] = b"SPTGLBVH";
+/// Bump this whenever the builder or the file layout changes, old caches are then rebuilt
+const CACHE_VERSION: u32 = 2;
+
+impl BvhAccel {
+    /// Key of a build, the builder only looks at triangle bounding boxes so hashing them
+    /// (in the original triangle order) together with the build parameters is enough
+    pub fn cache_key(triangles: &[Triangle], max_leaf_size: usize, bucket_number: usize) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write_u32(CACHE_VERSION);
+        hasher.write_u32(max_leaf_size as u32);
+        hasher.write_u32(bucket_number as u32);
+        hasher.write_u32(triangles.len() as u32);
+        for tri in triangles {
+            let bbox = tri.bbox();
+            for v in [
+                bbox.p_min.x,
+                bbox.p_min.y,
+                bbox.p_min.z,
+                bbox.p_max.x,
+                bbox.p_max.y,
+                bbox.p_max.z,
+            ] {
+                hasher.write_u32(v.to_bits());
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Returns `Ok(None)` if the cache is from another version or another build,
+    /// otherwise `triangles` is reordered in the same way as `BvhAccel::new` would do
+    pub fn load_cache<P: AsRef<Path>>(
+        path: P,
+        key: u64,
+        triangles: &mut Vec<Triangle>,
+    ) -> Result<Option<Self>> {
+        let mut bytes = vec![];
+        std::fs::File::open(path.as_ref())?.read_to_end(&mut bytes)?;
+        let mut reader = ByteReader { bytes: &bytes };
+
+        if reader.read_bytes(CACHE_MAGIC.len())? != CACHE_MAGIC {
+            bail!("bvh cache: not a bvh cache file");
+        }
+        if reader.read_u32()? != CACHE_VERSION || reader.read_u64()? != key {
+            return Ok(None);
+        }
+
+        let node_count = reader.read_u32()? as usize;
+        let triangle_count = reader.read_u32()? as usize;
+        if triangle_count != triangles.len() {
+            return Ok(None);
+        }
+
+        let mut links = Vec::with_capacity(node_count);
+        let mut nodes = Vec::with_capacity(node_count);
+        for index in 0..node_count {
+            let lc = reader.read_i32()?;
+            let rc = reader.read_i32()?;
+            let start = reader.read_u32()? as usize;
+            let end = reader.read_u32()? as usize;
+            let p_min = reader.read_point3()?;
+            let p_max = reader.read_point3()?;
+            if start > end || end > triangle_count {
+                bail!("bvh cache: invalid primitive range");
+            }
+            links.push((lc, rc));
+            nodes.push(Some(BvhNode::new(
+                start,
+                end,
+                Bbox { p_min, p_max },
+                index as u32,
+            )));
+        }
+
+        // `order` must be a permutation, otherwise triangles would be lost or duplicated
+        let mut order = Vec::with_capacity(triangle_count);
+        let mut seen = vec![false; triangle_count];
+        for _ in 0..triangle_count {
+            let index = reader.read_u32()? as usize;
+            match seen.get_mut(index) {
+                Some(seen) if !*seen => *seen = true,
+                _ => bail!("bvh cache: invalid triangle order"),
+            }
+            order.push(index);
+        }
+        if !reader.bytes.is_empty() {
+            bail!("bvh cache: trailing data");
+        }
+
+        let bvh_root = if node_count == 0 {
+            None
+        } else {
+            // every node can be taken only once, so cycles and shared children are rejected
+            let mut take_node = |index: i32| -> Result<Box<BvhNode>> {
+                nodes
+                    .get_mut(index as usize)
+                    .and_then(Option::take)
+                    .map(Box::new)
+                    .context("bvh cache: invalid node index")
+            };
+            let mut root = take_node(0)?;
+            let mut stack = vec![&mut root];
+            while let Some(u) = stack.pop() {
+                let (lc, rc) = links[u.index as usize];
+                if lc < 0 && rc < 0 {
+                    continue;
+                }
+                u.lc = Some(take_node(lc)?);
+                u.rc = Some(take_node(rc)?);
+                stack.push(u.lc.as_mut().unwrap());
+                stack.push(u.rc.as_mut().unwrap());
+            }
+            Some(root)
+        };
+
+        // the whole cache is valid here, `triangles` is only touched now
+        let mut slots: Vec<Option<Triangle>> = triangles.drain(..).map(Some).collect();
+        triangles.extend(order.into_iter().map(|index| slots[index].take().unwrap()));
+
+        Ok(Some(Self { bvh_root }))
+    }
+
+    pub fn save_cache<P: AsRef<Path>>(
+        &self,
+        path: P,
+        key: u64,
+        triangles: &[Triangle],
+    ) -> Result<()> {
+        let mut nodes = vec![];
+        if let Some(root) = self.bvh_root.as_ref() {
+            let mut stack = vec![root];
+            while let Some(u) = stack.pop() {
+                let index = u.index as usize;
+                if nodes.len() <= index {
+                    nodes.resize_with(index + 1, || None);
+                }
+                nodes[index] = Some(u);
+                if !u.is_leaf() {
+                    stack.push(u.lc.as_ref().unwrap());
+                    stack.push(u.rc.as_ref().unwrap());
+                }
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(32 + nodes.len() * 40 + triangles.len() * 4);
+        bytes.extend_from_slice(CACHE_MAGIC);
+        bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&key.to_le_bytes());
+        bytes.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for node in nodes {
+            // indices of discarded splits leave holes, they are stored as unreachable empty leaves
+            let (lc, rc, start, end, bbox) = match node {
+                Some(node) => (
+                    node.lc.as_ref().map_or(-1, |c| c.index as i32),
+                    node.rc.as_ref().map_or(-1, |c| c.index as i32),
+                    node.start as u32,
+                    node.end as u32,
+                    node.bbox,
+                ),
+                None => (-1, -1, 0, 0, Bbox::empty()),
+            };
+            bytes.extend_from_slice(&lc.to_le_bytes());
+            bytes.extend_from_slice(&rc.to_le_bytes());
+            bytes.extend_from_slice(&start.to_le_bytes());
+            bytes.extend_from_slice(&end.to_le_bytes());
+            for v in [
+                bbox.p_min.x,
+                bbox.p_min.y,
+                bbox.p_min.z,
+                bbox.p_max.x,
+                bbox.p_max.y,
+                bbox.p_max.z,
+            ] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        for tri in triangles {
+            bytes.extend_from_slice(&tri.index.to_le_bytes());
+        }
+
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::File::create(path.as_ref())?.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a, unlike `DefaultHasher` it is stable across Rust versions
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        for byte in value.to_le_bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            bail!("bvh cache: unexpected end of file");
+        }
+        let (head, tail) = self.bytes.split_at(count);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_point3(&mut self) -> Result<cgmath::Point3<f32>> {
+        Ok(cgmath::Point3::new(
+            self.read_f32()?,
+            self.read_f32()?,
+            self.read_f32()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, rc::Rc};
+
+    use cgmath::{Matrix4, Point3, SquareMatrix};
+
+    use super::*;
+    use crate::core::{MeshVertex, TriangleMesh};
+
+    /// A row of `count` triangles in reversed x order, so the build has to reorder them
+    fn make_triangles(count: usize) -> Vec<Triangle> {
+        let vertices = (0..count)
+            .rev()
+            .flat_map(|i| {
+                let x = i as f32 * 2.0;
+                [(x, 0.0), (x + 1.0, 0.0), (x, 1.0)]
+            })
+            .map(|(x, y)| MeshVertex {
+                position: Point3::new(x, y, 0.0),
+                ..Default::default()
+            })
+            .collect();
+        let indices = (0..count as u32 * 3).collect();
+        let mesh = Rc::new(TriangleMesh::new(vertices, indices, 0));
+        (0..count)
+            .map(|i| {
+                let indices = [i * 3, i * 3 + 1, i * 3 + 2];
+                Triangle::new(mesh.clone(), i as u32, indices, 0, &Matrix4::identity(), 0)
+            })
+            .collect()
+    }
+
+    fn cache_path() -> PathBuf {
+        std::env::temp_dir().join(format!("spt-bvh-{}.bin", uuid::Uuid::new_v4()))
+    }
+
+    fn triangle_indices(triangles: &[Triangle]) -> Vec<u32> {
+        triangles.iter().map(|tri| tri.index).collect()
+    }
+
+    /// (index, lc, rc, start, end, bbox) of every node, sorted by index
+    fn flatten(bvh: &BvhAccel) -> Vec<(u32, i32, i32, usize, usize, [f32; 6])> {
+        let mut nodes = vec![];
+        let mut stack: Vec<&BvhNode> = bvh.bvh_root.iter().map(|root| root.as_ref()).collect();
+        while let Some(u) = stack.pop() {
+            let bbox = u.bbox;
+            nodes.push((
+                u.index,
+                u.lc.as_ref().map_or(-1, |c| c.index as i32),
+                u.rc.as_ref().map_or(-1, |c| c.index as i32),
+                u.start,
+                u.end,
+                [
+                    bbox.p_min.x,
+                    bbox.p_min.y,
+                    bbox.p_min.z,
+                    bbox.p_max.x,
+                    bbox.p_max.y,
+                    bbox.p_max.z,
+                ],
+            ));
+            if !u.is_leaf() {
+                stack.push(u.lc.as_ref().unwrap());
+                stack.push(u.rc.as_ref().unwrap());
+            }
+        }
+        nodes.sort_by_key(|node| node.0);
+        nodes
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut built = make_triangles(20);
+        let key = BvhAccel::cache_key(&built, 2, 8);
+        let bvh = BvhAccel::new(&mut built, 2, 8);
+        let path = cache_path();
+        bvh.save_cache(&path, key, &built).unwrap();
+
+        let mut loaded = make_triangles(20);
+        let cached = BvhAccel::load_cache(&path, key, &mut loaded).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let cached = cached.unwrap();
+        assert_eq!(triangle_indices(&loaded), triangle_indices(&built));
+        assert_ne!(triangle_indices(&built), (0..20).collect::<Vec<_>>());
+        assert!(flatten(&bvh).len() > 1);
+        assert_eq!(flatten(&cached), flatten(&bvh));
+    }
+
+    #[test]
+    fn other_key_is_out_of_date() {
+        let mut triangles = make_triangles(4);
+        let key = BvhAccel::cache_key(&triangles, 2, 8);
+        let bvh = BvhAccel::new(&mut triangles, 2, 8);
+        let path = cache_path();
+        bvh.save_cache(&path, key, &triangles).unwrap();
+
+        let mut triangles = make_triangles(4);
+        let cached = BvhAccel::load_cache(&path, key ^ 1, &mut triangles).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(cached.is_none());
+        assert_eq!(triangle_indices(&triangles), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn corrupt_order_leaves_triangles_untouched() {
+        let mut triangles = make_triangles(4);
+        let key = BvhAccel::cache_key(&triangles, 2, 8);
+        let bvh = BvhAccel::new(&mut triangles, 2, 8);
+        let path = cache_path();
+        bvh.save_cache(&path, key, &triangles).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let order_start = bytes.len() - 4 * 4;
+
+        for corrupt in [[0, 1, 2, 4], [0, 1, 2, 2]] {
+            let mut bytes = bytes.clone();
+            for (i, index) in corrupt.iter().enumerate() {
+                let offset = order_start + i * 4;
+                bytes[offset..offset + 4].copy_from_slice(&(*index as u32).to_le_bytes());
+            }
+            std::fs::write(&path, bytes).unwrap();
+
+            let mut triangles = make_triangles(4);
+            let result = BvhAccel::load_cache(&path, key, &mut triangles);
+            assert!(result.is_err());
+            assert_eq!(triangle_indices(&triangles), vec![0, 1, 2, 3]);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
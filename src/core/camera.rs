@@ -0,0 +1,29 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub forward: Vector3<f32>,
+    pub up: Vector3<f32>,
+    pub right: Vector3<f32>,
+    pub fov_deg: f32,
+}
+
+impl Camera {
+    pub fn new(eye: Point3<f32>, forward: Vector3<f32>, up: Vector3<f32>, fov_deg: f32) -> Self {
+        let forward = forward.normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+        Self {
+            eye,
+            forward,
+            up,
+            right,
+            fov_deg,
+        }
+    }
+
+    pub fn half_cot_half_fov(&self) -> f32 {
+        0.5 / (self.fov_deg.to_radians() * 0.5).tan()
+    }
+}
@@ -0,0 +1,33 @@
+#[derive(Copy, Clone, PartialEq)]
+pub enum Light {
+    Point {
+        position: [f32; 3],
+        strength: [f32; 3],
+    },
+    Directional {
+        /// normalized
+        direction: [f32; 3],
+        strength: [f32; 3],
+    },
+}
+
+impl Light {
+    pub fn point(position: [f32; 3], strength: [f32; 3]) -> Self {
+        Self::Point { position, strength }
+    }
+
+    pub fn directional(direction: [f32; 3], strength: [f32; 3]) -> Self {
+        let norm = (direction[0] * direction[0]
+            + direction[1] * direction[1]
+            + direction[2] * direction[2])
+            .sqrt();
+        Self::Directional {
+            direction: [
+                direction[0] / norm,
+                direction[1] / norm,
+                direction[2] / norm,
+            ],
+            strength,
+        }
+    }
+}
@@ -1,9 +1,12 @@
+#[derive(Copy, Clone, PartialEq)]
 pub struct Material {
     pub albedo: [f32; 3],
     pub ior: f32,
     pub roughness: f32,
     pub metallic: f32,
     pub is_translucent: bool,
+    /// radiance leaving the side of the surface its normals point to
+    pub emission: [f32; 3],
 }
 
 impl Material {
@@ -13,6 +16,7 @@ impl Material {
         roughness: f32,
         metallic: f32,
         is_translucent: bool,
+        emission: [f32; 3],
     ) -> Self {
         Self {
             albedo,
@@ -20,6 +24,7 @@ impl Material {
             roughness: roughness * roughness,
             metallic,
             is_translucent,
+            emission,
         }
     }
 }
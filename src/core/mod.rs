@@ -1,11 +1,22 @@
 mod bbox;
+mod blue_noise;
 mod bvh;
+mod bvh_cache;
+mod camera;
+mod light;
 mod material;
 mod mesh;
+mod sampler;
+mod scene;
 mod triangle;
 
 pub use bbox::*;
+pub use blue_noise::*;
 pub use bvh::*;
+pub use camera::*;
+pub use light::*;
 pub use material::*;
 pub use mesh::*;
+pub use sampler::*;
+pub use scene::*;
 pub use triangle::*;
@@ -0,0 +1,50 @@
+/// Generator of the random numbers used by the path tracers, the values match `SAMPLER_*`
+/// in `ray_tracing.comp`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SamplerKind {
+    /// the original hash based stream, kept as the default so existing renders don't change
+    #[default]
+    Hash = 0,
+    /// a PCG stream for each pixel, every sample starts at a fixed offset of it
+    Pcg = 1,
+    /// shuffled and Owen scrambled Sobol sequence, decorrelated between pixels
+    Sobol = 2,
+    /// one scrambled Sobol sequence for all pixels, shifted by a blue noise mask
+    BlueNoise = 3,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Hash,
+        SamplerKind::Pcg,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// Name in scene files
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Hash => "hash",
+            SamplerKind::Pcg => "pcg",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue_noise",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        SamplerKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+/// Same as `rand_hash` in `ray_tracing.comp`
+pub fn rand_hash(mut s: u32) -> u32 {
+    s ^= 2747636419;
+    s = s.wrapping_mul(2654435769);
+    s ^= s >> 16;
+    s = s.wrapping_mul(2654435769);
+    s ^= s >> 16;
+    s.wrapping_mul(2654435769)
+}
@@ -0,0 +1,21 @@
+use std::rc::Rc;
+
+use cgmath::Matrix4;
+
+use super::{BvhAccel, Camera, Light, Material, SamplerKind, Triangle, TriangleMesh};
+
+/// Everything the renderers need, triangles are in the order of `bvh` leaves
+pub struct Scene {
+    pub camera: Camera,
+    pub max_depth: u32,
+    pub sampler: SamplerKind,
+    pub materials: Vec<Material>,
+    pub meshes: Vec<Vec<Rc<TriangleMesh>>>,
+    pub triangles: Vec<Triangle>,
+    pub transforms: Vec<Matrix4<f32>>,
+    pub lights: Vec<Light>,
+    /// constant radiance of rays leaving the scene, without it only camera rays see a
+    /// dark gray background
+    pub environment: Option<[f32; 3]>,
+    pub bvh: BvhAccel,
+}
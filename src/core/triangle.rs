@@ -1,13 +1,33 @@
 use std::rc::Rc;
 
-use cgmath::{Matrix4, Transform};
+use cgmath::{Matrix4, Point3, Transform};
 
 use super::{Bbox, TriangleMesh};
 
+/// Analytic shapes are unit sized in object space and are placed by the object transform,
+/// the values are the `PRIMITIVE_*` defines of `ray_tracing.comp`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnalyticShape {
+    /// radius 1 around the origin
+    Sphere = 1,
+    /// radius 1 around the origin in the xy plane, facing +z
+    Disc = 2,
+    /// from -1 to 1 in x and y, facing +z
+    Quad = 3,
+}
+
+pub enum Shape {
+    Mesh {
+        mesh: Rc<TriangleMesh>,
+        indices: [usize; 3],
+    },
+    Analytic(AnalyticShape),
+}
+
+/// Primitive of the BVH, a mesh triangle or an analytic shape
 pub struct Triangle {
-    pub mesh: Rc<TriangleMesh>,
+    pub shape: Shape,
     pub index: u32,
-    pub indices: [usize; 3],
     pub material: u32,
     pub trans_index: u32,
     pub bbox: Bbox,
@@ -27,16 +47,94 @@ impl Triangle {
         let p2 = trans.transform_point(mesh.position(indices[2]));
         let bbox = Bbox::from_points(&[p0, p1, p2]);
         Self {
-            mesh,
+            shape: Shape::Mesh { mesh, indices },
             index,
-            indices,
             material,
             trans_index,
             bbox,
         }
     }
 
+    pub fn analytic(
+        shape: AnalyticShape,
+        index: u32,
+        material: u32,
+        trans: &Matrix4<f32>,
+        trans_index: u32,
+    ) -> Self {
+        let z_extent = match shape {
+            AnalyticShape::Sphere => 1.0,
+            AnalyticShape::Disc | AnalyticShape::Quad => 0.0,
+        };
+        let mut corners = vec![];
+        for x in [-1.0, 1.0] {
+            for y in [-1.0, 1.0] {
+                for z in [-z_extent, z_extent] {
+                    corners.push(trans.transform_point(Point3::new(x, y, z)));
+                }
+            }
+        }
+        Self {
+            shape: Shape::Analytic(shape),
+            index,
+            material,
+            trans_index,
+            bbox: Bbox::from_points(&corners),
+        }
+    }
+
     pub fn bbox(&self) -> Bbox {
         self.bbox
     }
 }
+
+impl Shape {
+    /// Same as the `PRIMITIVE_*` defines of `ray_tracing.comp`
+    pub fn kind(&self) -> u32 {
+        match self {
+            Shape::Mesh { .. } => 0,
+            Shape::Analytic(shape) => *shape as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Point3, Vector3};
+
+    use super::*;
+    use crate::core::MeshVertex;
+
+    #[test]
+    fn bbox_is_in_world_space() {
+        let vertices = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 2.0, 0.0)]
+            .iter()
+            .map(|&(x, y, z)| MeshVertex {
+                position: Point3::new(x, y, z),
+                ..Default::default()
+            })
+            .collect();
+        let mesh = Rc::new(TriangleMesh::new(vertices, vec![0, 1, 2], 0));
+        let trans = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::from_angle_z(cgmath::Deg(90.0));
+
+        let bbox = Triangle::new(mesh, 0, [0, 1, 2], 0, &trans, 0).bbox();
+        let eps = 1e-6;
+        assert!((bbox.p_min - Point3::new(-1.0, 2.0, 3.0)).magnitude() < eps);
+        assert!((bbox.p_max - Point3::new(1.0, 3.0, 3.0)).magnitude() < eps);
+    }
+
+    #[test]
+    fn analytic_bbox_covers_the_shape() {
+        let trans =
+            Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0)) * Matrix4::from_scale(0.5);
+        let sphere = Triangle::analytic(AnalyticShape::Sphere, 0, 0, &trans, 0).bbox();
+        assert_eq!(sphere.p_min, Point3::new(0.5, 1.5, 2.5));
+        assert_eq!(sphere.p_max, Point3::new(1.5, 2.5, 3.5));
+
+        let quad = Triangle::analytic(AnalyticShape::Quad, 0, 0, &trans, 0).bbox();
+        assert_eq!(quad.p_min.z, 3.0);
+        assert_eq!(quad.p_max.z, 3.0);
+        assert_eq!(Shape::Analytic(AnalyticShape::Disc).kind(), 2);
+    }
+}
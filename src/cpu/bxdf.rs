@@ -0,0 +1,335 @@
+// Port of the material functions in `ray_tracing.comp`, directions are in the local frame
+// where the shading normal is +z. Keep both in sync when changing either of them.
+
+use std::f32::consts::{FRAC_1_PI, PI};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::core::Material;
+
+use super::Sampler;
+
+/// Lobes of a material, like `BSDF_*` in `ray_tracing.comp`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BsdfLobe {
+    Diffuse = 0,
+    Specular = 1,
+    Transmission = 2,
+}
+
+pub struct BxdfSample {
+    pub wi: Vector3<f32>,
+    pub pdf: f32,
+    pub bxdf: Vector3<f32>,
+}
+
+pub fn color_luminance(color: Vector3<f32>) -> f32 {
+    0.299 * color.x + 0.587 * color.y + 0.114 * color.z
+}
+
+fn pow2(x: f32) -> f32 {
+    x * x
+}
+
+fn albedo(mat: &Material) -> Vector3<f32> {
+    Vector3::from(mat.albedo)
+}
+
+fn reflect(i: Vector3<f32>, n: Vector3<f32>) -> Vector3<f32> {
+    i - 2.0 * n.dot(i) * n
+}
+
+fn refract_n(i: Vector3<f32>, n: Vector3<f32>, ior: f32) -> Option<Vector3<f32>> {
+    let cos_i = i.dot(n);
+    if cos_i >= 0.0 {
+        let ior_ratio = 1.0 / ior;
+        let o_z_sqr = 1.0 - (1.0 - cos_i * cos_i) * ior_ratio * ior_ratio;
+        if o_z_sqr >= 0.0 {
+            Some((ior_ratio * cos_i - o_z_sqr.sqrt()) * n - ior_ratio * i)
+        } else {
+            None
+        }
+    } else {
+        let ior_ratio = ior;
+        let o_z_sqr = 1.0 - (1.0 - cos_i * cos_i) * ior_ratio * ior_ratio;
+        if o_z_sqr >= 0.0 {
+            Some((o_z_sqr.sqrt() + ior_ratio * cos_i) * n - ior_ratio * i)
+        } else {
+            None
+        }
+    }
+}
+
+fn fresnel_n(ior: f32, i: Vector3<f32>, n: Vector3<f32>) -> f32 {
+    let (i_ior, o_ior) = if i.dot(n) >= 0.0 {
+        (1.0, ior)
+    } else {
+        (ior, 1.0)
+    };
+
+    if let Some(refract) = refract_n(i, n, ior) {
+        let idotn = i.dot(n).abs();
+        let rdotn = refract.dot(n).abs();
+
+        let denom = i_ior * idotn + o_ior * rdotn;
+        let num = i_ior * idotn - o_ior * rdotn;
+        let rs = pow2(num / denom);
+
+        let denom = i_ior * rdotn + o_ior * idotn;
+        let num = i_ior * rdotn - o_ior * idotn;
+        let rp = pow2(num / denom);
+
+        (rs + rp) * 0.5
+    } else {
+        1.0
+    }
+}
+
+fn half_from_reflect(i: Vector3<f32>, o: Vector3<f32>) -> Vector3<f32> {
+    if i.z >= 0.0 {
+        (i + o).normalize()
+    } else {
+        -(i + o).normalize()
+    }
+}
+
+fn ggx_ndf(ndoth: f32, a2: f32) -> f32 {
+    a2 * FRAC_1_PI / pow2(ndoth * ndoth * (a2 - 1.0) + 1.0).max(0.0001)
+}
+
+/// return sampled (n dot h)^2
+fn ggx_ndf_cdf_inverse(a2: f32, rand: f32) -> f32 {
+    (1.0 - rand) / (1.0 - rand * (1.0 - a2))
+}
+
+fn smith_separable_visible(ndotv: f32, ndotl: f32, a2: f32) -> f32 {
+    let v = ndotv.abs() + ((1.0 - a2) * ndotv * ndotv + a2).sqrt();
+    let l = ndotl.abs() + ((1.0 - a2) * ndotl * ndotl + a2).sqrt();
+    1.0 / (v * l)
+}
+
+fn sample_ggx_half(a2: f32, sampler: &mut Sampler) -> Vector3<f32> {
+    let rand_x = sampler.random();
+    let rand_y = sampler.random();
+
+    let cos_theta_sqr = ggx_ndf_cdf_inverse(a2, rand_x);
+    let cos_theta = cos_theta_sqr.sqrt();
+    let sin_theta = (1.0 - cos_theta_sqr).sqrt();
+    let phi = 2.0 * PI * rand_y;
+    Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+fn zero_sample(wi: Vector3<f32>) -> BxdfSample {
+    BxdfSample {
+        wi,
+        pdf: 1.0,
+        bxdf: Vector3::new(0.0, 0.0, 0.0),
+    }
+}
+
+fn lambert_reflect_sample(mat: &Material, wo: Vector3<f32>, sampler: &mut Sampler) -> BxdfSample {
+    let rand_x = sampler.random();
+    let rand_y = sampler.random();
+    let phi = 2.0 * PI * rand_x;
+    let sin_theta_sqr = rand_y;
+    let sin_theta = sin_theta_sqr.sqrt();
+    let cos_theta = (1.0 - sin_theta_sqr).sqrt();
+    let mut wi = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    if wo.z < 0.0 {
+        wi.z = -wi.z;
+    }
+
+    BxdfSample {
+        wi,
+        pdf: wi.z.abs() * FRAC_1_PI,
+        bxdf: albedo(mat) * FRAC_1_PI,
+    }
+}
+
+fn lambert_reflect_bxdf(mat: &Material, wo: Vector3<f32>, wi: Vector3<f32>) -> Vector3<f32> {
+    if wi.z * wo.z >= 0.0 {
+        albedo(mat) * FRAC_1_PI
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+fn microfacet_reflect_sample(
+    mat: &Material,
+    wo: Vector3<f32>,
+    sampler: &mut Sampler,
+) -> BxdfSample {
+    let a2 = mat.roughness * mat.roughness;
+    let half_v = sample_ggx_half(a2, sampler);
+
+    let wi = reflect(-wo, half_v);
+    if wi.z * wo.z >= 0.0 {
+        let ndf = ggx_ndf(half_v.z, a2);
+        let visible = smith_separable_visible(wo.z.abs(), wi.z.abs(), a2);
+        BxdfSample {
+            wi,
+            pdf: ndf * half_v.z / (4.0 * wo.dot(half_v).abs()),
+            bxdf: albedo(mat) * ndf * visible,
+        }
+    } else {
+        zero_sample(wi)
+    }
+}
+
+fn microfacet_reflect_bxdf(mat: &Material, wo: Vector3<f32>, wi: Vector3<f32>) -> Vector3<f32> {
+    if wi.z * wo.z >= 0.0 {
+        let half_v = half_from_reflect(wo, wi);
+        let a2 = mat.roughness * mat.roughness;
+        let ndf = ggx_ndf(half_v.z, a2);
+        let visible = smith_separable_visible(wo.z.abs(), wi.z.abs(), a2);
+        albedo(mat) * ndf * visible
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+fn microfacet_transmit_sample(
+    mat: &Material,
+    wo: Vector3<f32>,
+    sampler: &mut Sampler,
+) -> BxdfSample {
+    let a2 = mat.roughness * mat.roughness;
+    let half_v = sample_ggx_half(a2, sampler);
+
+    match refract_n(wo, half_v, mat.ior) {
+        Some(wi) if wi.z * wo.z <= 0.0 => {
+            let ndf = ggx_ndf(half_v.z, a2);
+            let visible = smith_separable_visible(wo.z.abs(), wi.z.abs(), a2);
+
+            let ior_ratio = if wo.z >= 0.0 { 1.0 / mat.ior } else { mat.ior };
+            let denom = pow2(ior_ratio * wo.dot(half_v) + wi.dot(half_v));
+            let num = 4.0 * wo.dot(half_v).abs() * wi.dot(half_v).abs();
+            let bxdf = albedo(mat) * ndf * visible * num / denom;
+
+            let num = wi.dot(half_v).abs();
+            BxdfSample {
+                wi,
+                pdf: ndf * half_v.z * num / denom,
+                bxdf,
+            }
+        }
+        Some(wi) => zero_sample(wi),
+        None => zero_sample(Vector3::new(0.0, 0.0, 0.0)),
+    }
+}
+
+fn microfacet_transmit_bxdf(mat: &Material, wo: Vector3<f32>, wi: Vector3<f32>) -> Vector3<f32> {
+    if wi.z * wo.z >= 0.0 {
+        let half_v = half_from_reflect(wo, wi);
+        let a2 = mat.roughness * mat.roughness;
+        let ndf = ggx_ndf(half_v.z, a2);
+        let visible = smith_separable_visible(wo.z.abs(), wi.z.abs(), a2);
+        let ior_ratio = if wo.z >= 0.0 { 1.0 / mat.ior } else { mat.ior };
+        let denom = pow2(ior_ratio * wo.dot(half_v) + wi.dot(half_v));
+        let num = 4.0 * wo.dot(half_v).abs() * wi.dot(half_v).abs();
+        albedo(mat) * ndf * visible * num / denom
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+impl BsdfLobe {
+    pub const ALL: [BsdfLobe; 3] = [
+        BsdfLobe::Diffuse,
+        BsdfLobe::Specular,
+        BsdfLobe::Transmission,
+    ];
+}
+
+/// Also returns the lobe that is sampled
+pub fn mat_sample(
+    mat: &Material,
+    wo: Vector3<f32>,
+    sampler: &mut Sampler,
+) -> (BxdfSample, BsdfLobe) {
+    let fresnel = fresnel_n(mat.ior, wo, Vector3::unit_z());
+    let rand = sampler.random();
+    let (mut sample, weight, lobe) = if rand <= fresnel {
+        (
+            microfacet_reflect_sample(mat, wo, sampler),
+            fresnel,
+            BsdfLobe::Specular,
+        )
+    } else if !mat.is_translucent {
+        (
+            lambert_reflect_sample(mat, wo, sampler),
+            1.0 - fresnel,
+            BsdfLobe::Diffuse,
+        )
+    } else {
+        (
+            microfacet_transmit_sample(mat, wo, sampler),
+            1.0 - fresnel,
+            BsdfLobe::Transmission,
+        )
+    };
+    sample.pdf *= weight;
+    sample.bxdf *= weight;
+    (sample, lobe)
+}
+
+pub fn mat_bxdf(mat: &Material, wo: Vector3<f32>, wi: Vector3<f32>) -> Vector3<f32> {
+    let fresnel = fresnel_n(mat.ior, wo, Vector3::unit_z());
+    let reflect_bxdf = microfacet_reflect_bxdf(mat, wo, wi);
+    if !mat.is_translucent {
+        let transmit_bxdf = lambert_reflect_bxdf(mat, wo, wi);
+        fresnel * reflect_bxdf + (1.0 - fresnel) * transmit_bxdf
+    } else if wo.z * wi.z >= 0.0 {
+        fresnel * reflect_bxdf
+    } else {
+        (1.0 - fresnel) * microfacet_transmit_bxdf(mat, wo, wi)
+    }
+}
+
+/// Part of `mat_bxdf` from one lobe, they sum up to `mat_bxdf`
+pub fn mat_bxdf_lobe(
+    mat: &Material,
+    wo: Vector3<f32>,
+    wi: Vector3<f32>,
+    lobe: BsdfLobe,
+) -> Vector3<f32> {
+    let fresnel = fresnel_n(mat.ior, wo, Vector3::unit_z());
+    match lobe {
+        BsdfLobe::Specular => fresnel * microfacet_reflect_bxdf(mat, wo, wi),
+        BsdfLobe::Diffuse if !mat.is_translucent => {
+            (1.0 - fresnel) * lambert_reflect_bxdf(mat, wo, wi)
+        }
+        BsdfLobe::Transmission if mat.is_translucent && wo.z * wi.z < 0.0 => {
+            (1.0 - fresnel) * microfacet_transmit_bxdf(mat, wo, wi)
+        }
+        _ => Vector3::new(0.0, 0.0, 0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lobes_sum_up_to_bxdf() {
+        let wo = Vector3::new(0.3, -0.2, 0.9).normalize();
+        let directions = [
+            Vector3::new(-0.4, 0.1, 0.8),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.5, 0.5, -0.6),
+            Vector3::new(-0.7, 0.2, -0.1),
+        ];
+        for is_translucent in [false, true] {
+            let mat = Material::new([0.8, 0.5, 0.2], 1.5, 0.4, 0.0, is_translucent, [0.0; 3]);
+            for wi in directions {
+                let wi = wi.normalize();
+                let sum = BsdfLobe::ALL
+                    .iter()
+                    .map(|&lobe| mat_bxdf_lobe(&mat, wo, wi, lobe))
+                    .fold(Vector3::new(0.0, 0.0, 0.0), |a, b| a + b);
+                let bxdf = mat_bxdf(&mat, wo, wi);
+                assert!((sum - bxdf).magnitude() <= 1e-5 * bxdf.magnitude().max(1.0));
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::ops::Range;
+
+use cgmath::{InnerSpace, Matrix4, Point3, Transform, Vector3};
+
+use crate::core::{AnalyticShape, Bbox, BvhVisitor};
+
+use super::{CpuGeometry, CpuTriangle};
+
+#[derive(Copy, Clone)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub t_min: f32,
+}
+
+#[derive(Copy, Clone)]
+pub struct Intersection {
+    pub normal: Vector3<f32>,
+    /// normal of the triangle itself, on the side of `normal`
+    pub geometric_normal: Vector3<f32>,
+    pub t: f32,
+    pub material_index: u32,
+    pub object_index: u32,
+    /// index into the (bvh ordered) scene triangles
+    pub triangle_index: usize,
+}
+
+#[derive(Copy, Clone)]
+pub struct TriangleHit {
+    pub t: f32,
+    /// weights of the 3 vertices
+    pub barycentric: [f32; 3],
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction,
+            t_min: 0.0001,
+        }
+    }
+
+    pub fn point_at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+impl Intersection {
+    pub fn new() -> Self {
+        Self {
+            normal: Vector3::unit_z(),
+            geometric_normal: Vector3::unit_z(),
+            t: 1e9,
+            material_index: 0,
+            object_index: 0,
+            triangle_index: 0,
+        }
+    }
+}
+
+/// Same slab test as `intersect_bbox` in `ray_tracing.comp`
+pub fn intersect_bbox(ray: &Ray, bbox: &Bbox, t_max: f32) -> bool {
+    if bbox.is_empty() {
+        return false;
+    }
+
+    let slab = |p_min: f32, p_max: f32, o: f32, d: f32| {
+        let t0 = (p_min - o) / d;
+        let t1 = (p_max - o) / d;
+        if t0 > t1 {
+            (t1, t0)
+        } else {
+            (t0, t1)
+        }
+    };
+    let (x0, x1) = slab(bbox.p_min.x, bbox.p_max.x, ray.origin.x, ray.direction.x);
+    let (y0, y1) = slab(bbox.p_min.y, bbox.p_max.y, ray.origin.y, ray.direction.y);
+    let (z0, z1) = slab(bbox.p_min.z, bbox.p_max.z, ray.origin.z, ray.direction.z);
+
+    let t0 = x0.max(y0.max(z0));
+    let t1 = x1.min(y1.min(z1));
+
+    t0 <= t1 && t1 > ray.t_min && t0 < t_max
+}
+
+/// Same Möller–Trumbore test as `intersect_triangle` in `ray_tracing.comp`
+pub fn intersect_triangle(
+    ray: &Ray,
+    positions: &[Point3<f32>; 3],
+    t_max: f32,
+) -> Option<TriangleHit> {
+    let e1 = positions[1] - positions[0];
+    let e2 = positions[2] - positions[0];
+    let q = ray.direction.cross(e2);
+    let det = e1.dot(q);
+    if det == 0.0 {
+        return None;
+    }
+
+    let det = 1.0 / det;
+    let s = ray.origin - positions[0];
+    let v = s.dot(q) * det;
+    if v < 0.0 {
+        return None;
+    }
+    let r = s.cross(e1);
+    let w = ray.direction.dot(r) * det;
+    let u = 1.0 - v - w;
+    if w < 0.0 || u < 0.0 {
+        return None;
+    }
+
+    let t = e2.dot(r) * det;
+    if t > ray.t_min && t < t_max {
+        Some(TriangleHit {
+            t,
+            barycentric: [u, v, w],
+        })
+    } else {
+        None
+    }
+}
+
+/// Same test as `intersect_analytic` in `ray_tracing.comp`, the ray is moved into object
+/// space, where `t` stays the same as the direction isn't normalized. Returns the distance
+/// and the object space normal.
+pub fn intersect_analytic(
+    ray: &Ray,
+    shape: AnalyticShape,
+    world_to_object: &Matrix4<f32>,
+    t_max: f32,
+) -> Option<(f32, Vector3<f32>)> {
+    let o = world_to_object.transform_point(ray.origin);
+    let d = world_to_object.transform_vector(ray.direction);
+    let (t, normal) = match shape {
+        AnalyticShape::Sphere => {
+            let o = o - Point3::new(0.0, 0.0, 0.0);
+            let a = d.dot(d);
+            let b = o.dot(d);
+            let c = o.dot(o) - 1.0;
+            let discriminant = b * b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrt = discriminant.sqrt();
+            let mut t = (-b - sqrt) / a;
+            if t <= ray.t_min {
+                t = (-b + sqrt) / a;
+            }
+            (t, o + d * t)
+        }
+        AnalyticShape::Disc | AnalyticShape::Quad => {
+            if d.z == 0.0 {
+                return None;
+            }
+            let t = -o.z / d.z;
+            let p = o + d * t;
+            let inside = if shape == AnalyticShape::Disc {
+                p.x * p.x + p.y * p.y <= 1.0
+            } else {
+                p.x.abs() <= 1.0 && p.y.abs() <= 1.0
+            };
+            if !inside {
+                return None;
+            }
+            (t, Vector3::unit_z())
+        }
+    };
+    (t > ray.t_min && t < t_max).then_some((t, normal))
+}
+
+impl CpuTriangle {
+    /// Distance, normalized world space shading normal and geometric normal of the hit before
+    /// `t_max`, the geometric normal is flipped to the side of the shading one
+    pub fn intersect(&self, ray: &Ray, t_max: f32) -> Option<(f32, Vector3<f32>, Vector3<f32>)> {
+        match &self.geometry {
+            CpuGeometry::Triangle { positions, normals } => {
+                let hit = intersect_triangle(ray, positions, t_max)?;
+                let [u, v, w] = hit.barycentric;
+                let normal = (normals[0] * u + normals[1] * v + normals[2] * w).normalize();
+                let geometric_normal = (positions[1] - positions[0])
+                    .cross(positions[2] - positions[0])
+                    .normalize();
+                if geometric_normal.dot(normal) < 0.0 {
+                    Some((hit.t, normal, -geometric_normal))
+                } else {
+                    Some((hit.t, normal, geometric_normal))
+                }
+            }
+            CpuGeometry::Analytic {
+                shape,
+                world_to_object,
+                normal_transform,
+            } => {
+                let (t, normal) = intersect_analytic(ray, *shape, world_to_object, t_max)?;
+                let normal = (normal_transform * normal).normalize();
+                Some((t, normal, normal))
+            }
+        }
+    }
+}
+
+/// Finds the closest hit, like `intersect_bvh` in `ray_tracing.comp`
+pub struct ClosestHitVisitor<'a> {
+    pub ray: Ray,
+    pub triangles: &'a [CpuTriangle],
+    pub inter: Intersection,
+    pub hit: bool,
+}
+
+/// Finds any hit before `t_max`, like `intersect_bvh_test` in `ray_tracing.comp`
+pub struct AnyHitVisitor<'a> {
+    pub ray: Ray,
+    pub triangles: &'a [CpuTriangle],
+    pub t_max: f32,
+    pub hit: bool,
+}
+
+impl<'a> ClosestHitVisitor<'a> {
+    pub fn new(ray: Ray, triangles: &'a [CpuTriangle]) -> Self {
+        Self {
+            ray,
+            triangles,
+            inter: Intersection::new(),
+            hit: false,
+        }
+    }
+}
+
+impl<'a> AnyHitVisitor<'a> {
+    pub fn new(ray: Ray, triangles: &'a [CpuTriangle], t_max: f32) -> Self {
+        Self {
+            ray,
+            triangles,
+            t_max,
+            hit: false,
+        }
+    }
+}
+
+impl BvhVisitor for ClosestHitVisitor<'_> {
+    fn visit_bbox(&mut self, bbox: &Bbox) -> bool {
+        intersect_bbox(&self.ray, bbox, self.inter.t)
+    }
+
+    fn visit_leaf(&mut self, range: Range<usize>) -> bool {
+        for i in range {
+            let tri = &self.triangles[i];
+            if let Some((t, normal, geometric_normal)) = tri.intersect(&self.ray, self.inter.t) {
+                self.inter.t = t;
+                self.inter.normal = normal;
+                self.inter.geometric_normal = geometric_normal;
+                self.inter.material_index = tri.material;
+                self.inter.object_index = tri.object;
+                self.inter.triangle_index = i;
+                self.hit = true;
+            }
+        }
+        false
+    }
+}
+
+impl BvhVisitor for AnyHitVisitor<'_> {
+    fn visit_bbox(&mut self, bbox: &Bbox) -> bool {
+        intersect_bbox(&self.ray, bbox, self.t_max)
+    }
+
+    fn visit_leaf(&mut self, range: Range<usize>) -> bool {
+        self.hit = self.triangles[range]
+            .iter()
+            .any(|tri| tri.intersect(&self.ray, self.t_max).is_some());
+        self.hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Bbox {
+        Bbox::from_points(&[Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)])
+    }
+
+    fn triangle() -> [Point3<f32>; 3] {
+        [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn ray_box() {
+        let ray = Ray::new(Point3::new(0.5, 0.5, -1.0), Vector3::unit_z());
+        assert!(intersect_bbox(&ray, &unit_box(), 1e9));
+        // the box starts at t = 1
+        assert!(!intersect_bbox(&ray, &unit_box(), 0.5));
+        assert!(!intersect_bbox(&ray, &Bbox::empty(), 1e9));
+
+        let behind = Ray::new(Point3::new(0.5, 0.5, 2.0), Vector3::unit_z());
+        assert!(!intersect_bbox(&behind, &unit_box(), 1e9));
+        let inside = Ray::new(Point3::new(0.5, 0.5, 0.5), Vector3::unit_x());
+        assert!(intersect_bbox(&inside, &unit_box(), 1e9));
+        let beside = Ray::new(Point3::new(2.0, 0.5, -1.0), Vector3::unit_z());
+        assert!(!intersect_bbox(&beside, &unit_box(), 1e9));
+    }
+
+    #[test]
+    fn ray_triangle() {
+        let ray = Ray::new(Point3::new(0.25, 0.5, 2.0), -Vector3::unit_z());
+        let hit = intersect_triangle(&ray, &triangle(), 1e9).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-6);
+        let [u, v, w] = hit.barycentric;
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.25).abs() < 1e-6 && (w - 0.5).abs() < 1e-6);
+
+        // both faces are hit
+        let below = Ray::new(Point3::new(0.25, 0.5, -2.0), Vector3::unit_z());
+        assert!(intersect_triangle(&below, &triangle(), 1e9).is_some());
+
+        assert!(intersect_triangle(&ray, &triangle(), 1.5).is_none());
+        let outside = Ray::new(Point3::new(0.75, 0.75, 2.0), -Vector3::unit_z());
+        assert!(intersect_triangle(&outside, &triangle(), 1e9).is_none());
+        let parallel = Ray::new(Point3::new(-1.0, 0.25, 0.0), Vector3::unit_x());
+        assert!(intersect_triangle(&parallel, &triangle(), 1e9).is_none());
+    }
+
+    #[test]
+    fn ray_analytic_shapes() {
+        let trans =
+            Matrix4::from_translation(Vector3::new(0.0, 0.0, -3.0)) * Matrix4::from_scale(2.0);
+        let world_to_object = trans.inverse_transform().unwrap();
+        let ray = Ray::new(Point3::new(0.5, 0.0, 5.0), -Vector3::unit_z());
+
+        let (t, normal) =
+            intersect_analytic(&ray, AnalyticShape::Sphere, &world_to_object, 1e9).unwrap();
+        let z = (4.0f32 - 0.25).sqrt() - 3.0;
+        assert!((t - (5.0 - z)).abs() < 1e-5);
+        assert!((normal - Vector3::new(0.25, 0.0, (z + 3.0) / 2.0)).magnitude() < 1e-5);
+        // from inside the far side is hit
+        let inside = Ray::new(Point3::new(0.0, 0.0, -3.0), Vector3::unit_x());
+        let (t, _) =
+            intersect_analytic(&inside, AnalyticShape::Sphere, &world_to_object, 1e9).unwrap();
+        assert!((t - 2.0).abs() < 1e-5);
+        assert!(intersect_analytic(&ray, AnalyticShape::Sphere, &world_to_object, 1.0).is_none());
+
+        let (t, normal) =
+            intersect_analytic(&ray, AnalyticShape::Disc, &world_to_object, 1e9).unwrap();
+        assert!((t - 8.0).abs() < 1e-5);
+        assert_eq!(normal, Vector3::unit_z());
+        let corner = Ray::new(Point3::new(1.8, 1.8, 5.0), -Vector3::unit_z());
+        assert!(intersect_analytic(&corner, AnalyticShape::Disc, &world_to_object, 1e9).is_none());
+        assert!(intersect_analytic(&corner, AnalyticShape::Quad, &world_to_object, 1e9).is_some());
+        let outside = Ray::new(Point3::new(2.5, 0.0, 5.0), -Vector3::unit_z());
+        assert!(intersect_analytic(&outside, AnalyticShape::Quad, &world_to_object, 1e9).is_none());
+    }
+}
@@ -0,0 +1,517 @@
+mod bxdf;
+mod intersect;
+mod sampler;
+
+pub use bxdf::*;
+pub use intersect::*;
+pub use sampler::*;
+
+use cgmath::{
+    ElementWise, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3,
+};
+
+use crate::{
+    core::{
+        AnalyticShape, BlueNoise, BvhAccel, Camera, Light, Material, SamplerKind, Scene, Shape,
+    },
+    output::{Aov, FilterTable, ImageLayer, Lobe, PixelFilter},
+};
+
+const LOBE_COUNT: usize = Lobe::ALL.len();
+
+/// Primitive in world space, in the same order as the scene triangles
+pub struct CpuTriangle {
+    pub geometry: CpuGeometry,
+    pub material: u32,
+    pub object: u32,
+}
+
+pub enum CpuGeometry {
+    Triangle {
+        positions: [Point3<f32>; 3],
+        /// transformed but not normalized vertex normals
+        normals: [Vector3<f32>; 3],
+    },
+    /// intersected in object space like in `ray_tracing.comp`
+    Analytic {
+        shape: AnalyticShape,
+        world_to_object: Matrix4<f32>,
+        normal_transform: Matrix3<f32>,
+    },
+}
+
+/// Reference path tracer on the CPU, it implements the same integrator as `ray_tracing.comp`
+/// (including the random number sequence) so its output can be compared with the GPU one.
+pub struct CpuRenderer<'a> {
+    bvh: &'a BvhAccel,
+    triangles: Vec<CpuTriangle>,
+    materials: Vec<Material>,
+    lights: Vec<Light>,
+    environment: Option<Vector3<f32>>,
+    camera: Camera,
+    max_depth: u32,
+    sampler: SamplerKind,
+    blue_noise: Option<BlueNoise>,
+}
+
+/// Scene content under a pixel, see `CpuRenderer::pick`
+#[derive(Clone, Copy)]
+pub struct Pick {
+    /// index of the object in the scene file
+    pub object_index: u32,
+    pub material_index: u32,
+    /// index into the (bvh ordered) scene triangles
+    pub triangle_index: usize,
+    pub position: Point3<f32>,
+    /// distance along the camera ray
+    pub distance: f32,
+}
+
+struct LightSample {
+    wi: Vector3<f32>,
+    pdf: f32,
+    strength: Vector3<f32>,
+    dist: f32,
+}
+
+impl<'a> CpuRenderer<'a> {
+    pub fn new(scene: &'a Scene) -> Self {
+        let normal_transforms: Vec<Matrix3<f32>> = scene
+            .transforms
+            .iter()
+            .map(|trans| {
+                let model_iv = trans.transpose().invert().unwrap_or_else(Matrix4::identity);
+                Matrix3::from_cols(
+                    model_iv.x.truncate(),
+                    model_iv.y.truncate(),
+                    model_iv.z.truncate(),
+                )
+            })
+            .collect();
+
+        let triangles = scene
+            .triangles
+            .iter()
+            .map(|tri| {
+                let trans = &scene.transforms[tri.trans_index as usize];
+                let normal_trans = &normal_transforms[tri.trans_index as usize];
+                let geometry = match &tri.shape {
+                    Shape::Mesh { mesh, indices } => {
+                        let vertices = indices.map(|index| mesh.vertices[index]);
+                        CpuGeometry::Triangle {
+                            positions: vertices.map(|vert| trans.transform_point(vert.position)),
+                            normals: vertices.map(|vert| normal_trans * vert.normal),
+                        }
+                    }
+                    Shape::Analytic(shape) => CpuGeometry::Analytic {
+                        shape: *shape,
+                        world_to_object: trans.invert().unwrap_or_else(Matrix4::identity),
+                        normal_transform: *normal_trans,
+                    },
+                };
+                CpuTriangle {
+                    geometry,
+                    material: tri.material,
+                    object: tri.trans_index,
+                }
+            })
+            .collect();
+
+        Self {
+            bvh: &scene.bvh,
+            triangles,
+            materials: scene.materials.clone(),
+            lights: scene.lights.clone(),
+            environment: scene.environment.map(Vector3::from),
+            camera: scene.camera,
+            max_depth: scene.max_depth,
+            sampler: scene.sampler,
+            blue_noise: (scene.sampler == SamplerKind::BlueNoise).then(BlueNoise::new),
+        }
+    }
+
+    /// Renders `spp` samples per pixel using all cores, returns RGBA radiance with rows
+    /// from top to bottom like `Renderer::read_output_image` and a layer for each of `lobes`
+    pub fn render(
+        &self,
+        width: u32,
+        height: u32,
+        spp: u32,
+        filter: &PixelFilter,
+        lobes: &[Lobe],
+    ) -> (Vec<f32>, Vec<ImageLayer>) {
+        let filter = &filter.table();
+        // RGBA followed by the RGB of every lobe
+        let stride = 4 + 3 * lobes.len();
+        let mut pixels = vec![0.0; (width * height) as usize * stride];
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_chunk = (height as usize).div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            for (chunk_index, chunk) in pixels
+                .chunks_mut(rows_per_chunk * width as usize * stride)
+                .enumerate()
+            {
+                scope.spawn(move || {
+                    let first_row = chunk_index * rows_per_chunk;
+                    for (i, pixel) in chunk.chunks_exact_mut(stride).enumerate() {
+                        let x = (i % width as usize) as u32;
+                        let y = (first_row + i / width as usize) as u32;
+                        let (color, lobe_colors) =
+                            self.render_pixel(x, y, width, height, spp, filter);
+                        pixel[..4].copy_from_slice(&[color.x, color.y, color.z, 1.0]);
+                        for (j, &lobe) in lobes.iter().enumerate() {
+                            let lobe_color: [f32; 3] = lobe_colors[lobe as usize].into();
+                            pixel[4 + 3 * j..7 + 3 * j].copy_from_slice(&lobe_color);
+                        }
+                    }
+                });
+            }
+        });
+
+        let image = pixels
+            .chunks_exact(stride)
+            .flat_map(|pixel| pixel[..4].to_vec())
+            .collect();
+        let layers = lobes
+            .iter()
+            .enumerate()
+            .map(|(j, &lobe)| {
+                let data = pixels
+                    .chunks_exact(stride)
+                    .flat_map(|pixel| pixel[4 + 3 * j..7 + 3 * j].to_vec())
+                    .collect();
+                lobe.layer(data)
+            })
+            .collect();
+        (image, layers)
+    }
+
+    /// First hit data of every pixel, like the AOVs `ray_tracing.comp` writes to the aux image
+    pub fn render_aovs(&self, width: u32, height: u32) -> Vec<ImageLayer> {
+        let mut images: Vec<_> = Aov::ALL
+            .iter()
+            .map(|&aov| {
+                aov.layer(Vec::with_capacity(
+                    (width * height) as usize * aov.channels().len(),
+                ))
+            })
+            .collect();
+
+        for y in 0..height {
+            for x in 0..width {
+                let ray = self.primary_ray(x, y, width, height);
+                let inter = self.intersect(ray);
+                for (&aov, image) in Aov::ALL.iter().zip(&mut images) {
+                    let value: [f32; 3] = match (aov, inter) {
+                        (Aov::Depth | Aov::ObjectId | Aov::MaterialId, None) => [-1.0; 3],
+                        (_, None) => [0.0; 3],
+                        (Aov::Albedo, Some(inter)) => {
+                            self.materials[inter.material_index as usize].albedo
+                        }
+                        (Aov::Normal, Some(inter)) => {
+                            if ray.direction.dot(inter.normal) > 0.0 {
+                                (-inter.normal).into()
+                            } else {
+                                inter.normal.into()
+                            }
+                        }
+                        (Aov::Depth, Some(inter)) => [inter.t; 3],
+                        (Aov::Position, Some(inter)) => ray.point_at(inter.t).into(),
+                        (Aov::ObjectId, Some(inter)) => [inter.object_index as f32; 3],
+                        (Aov::MaterialId, Some(inter)) => [inter.material_index as f32; 3],
+                    };
+                    image.data.extend_from_slice(&value[..aov.channels().len()]);
+                }
+            }
+        }
+
+        images
+    }
+
+    /// Returns the radiance and its parts indexed by `Lobe`
+    pub fn render_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        spp: u32,
+        filter: &FilterTable,
+    ) -> (Vector3<f32>, [Vector3<f32>; LOBE_COUNT]) {
+        let mut result = Vector3::new(0.0, 0.0, 0.0);
+        let mut lobes = [Vector3::new(0.0, 0.0, 0.0); LOBE_COUNT];
+        for frame_index in 0..spp {
+            let mut sampler = Sampler::new(
+                self.sampler,
+                self.blue_noise.as_ref(),
+                (x, y),
+                x + y * width,
+                frame_index,
+            );
+            let (ray, weight) = self.camera_ray(x, y, width, height, filter, &mut sampler);
+            let light_index = if self.lights.is_empty() {
+                0
+            } else {
+                frame_index as usize % self.lights.len()
+            };
+            let (color, sample_lobes) = self.trace(ray, light_index, &mut sampler);
+            result += color * weight;
+            for (lobe, sample_lobe) in lobes.iter_mut().zip(sample_lobes) {
+                *lobe += sample_lobe * weight;
+            }
+        }
+        let spp = spp.max(1) as f32;
+        (result / spp, lobes.map(|lobe| lobe / spp))
+    }
+
+    /// Viewers move the camera, picking and rendering use the latest one
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+
+    /// Traces the ray through the center of a pixel (rows from top to bottom), `None` if
+    /// nothing is hit
+    pub fn pick(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Pick> {
+        let ray = self.primary_ray(x, y, width, height);
+        self.intersect(ray).map(|inter| Pick {
+            object_index: inter.object_index,
+            material_index: inter.material_index,
+            triangle_index: inter.triangle_index,
+            position: ray.point_at(inter.t),
+            distance: inter.t,
+        })
+    }
+
+    /// Ray through the center of a pixel
+    pub fn primary_ray(&self, x: u32, y: u32, width: u32, height: u32) -> Ray {
+        self.film_ray(x as f32 + 0.5, y as f32 + 0.5, width, height)
+    }
+
+    /// Ray through a point of a pixel distributed like `filter` and the weight of its
+    /// sample, like `main` in `ray_tracing.comp`
+    pub fn camera_ray(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        filter: &FilterTable,
+        sampler: &mut Sampler,
+    ) -> (Ray, f32) {
+        let (mut offset_x, mut offset_y, mut weight) = (0.0, 0.0, 1.0);
+        if filter.radius > 0.0 {
+            let (x, weight_x) = filter.sample(sampler.random());
+            let (y, weight_y) = filter.sample(sampler.random());
+            offset_x = x;
+            offset_y = y;
+            weight = weight_x * weight_y;
+        }
+        let ray = self.film_ray(
+            x as f32 + 0.5 + offset_x,
+            y as f32 + 0.5 + offset_y,
+            width,
+            height,
+        );
+        (ray, weight)
+    }
+
+    /// Ray through a point in pixel units, rows from top to bottom
+    fn film_ray(&self, x: f32, y: f32, width: u32, height: u32) -> Ray {
+        let u = x / width as f32;
+        let v = y / height as f32;
+        self.generate_ray((u - 0.5) * width as f32 / height as f32, 0.5 - v)
+    }
+
+    pub fn generate_ray(&self, u: f32, v: f32) -> Ray {
+        let camera = &self.camera;
+        let direction =
+            camera.forward * camera.half_cot_half_fov() + camera.right * u + camera.up * v;
+        Ray::new(camera.eye, direction.normalize())
+    }
+
+    pub fn intersect(&self, ray: Ray) -> Option<Intersection> {
+        let mut visitor = ClosestHitVisitor::new(ray, &self.triangles);
+        self.bvh.traverse(&mut visitor);
+        if visitor.hit {
+            Some(visitor.inter)
+        } else {
+            None
+        }
+    }
+
+    pub fn intersect_test(&self, ray: Ray, t_max: f32) -> bool {
+        let mut visitor = AnyHitVisitor::new(ray, &self.triangles, t_max);
+        self.bvh.traverse(&mut visitor);
+        visitor.hit
+    }
+
+    /// Returns the radiance and its parts indexed by `Lobe`
+    fn trace(
+        &self,
+        mut ray: Ray,
+        light_index: usize,
+        sampler: &mut Sampler,
+    ) -> (Vector3<f32>, [Vector3<f32>; LOBE_COUNT]) {
+        let mut final_color = Vector3::new(0.0, 0.0, 0.0);
+        let mut color_coe = Vector3::new(1.0, 1.0, 1.0);
+        let mut lobes = [Vector3::new(0.0, 0.0, 0.0); LOBE_COUNT];
+        let mut first_lobe = BsdfLobe::Diffuse;
+
+        for curr_depth in 0..self.max_depth {
+            sampler.start_set(1 + curr_depth);
+
+            let inter = match self.intersect(ray) {
+                Some(inter) => inter,
+                None => {
+                    if let Some(environment) = self.environment {
+                        let le = color_coe.mul_element_wise(environment);
+                        add_emission(le, curr_depth, first_lobe, &mut final_color, &mut lobes);
+                    } else if curr_depth == 0 {
+                        let le = Vector3::new(0.1, 0.1, 0.1);
+                        add_emission(le, curr_depth, first_lobe, &mut final_color, &mut lobes);
+                    }
+                    break;
+                }
+            };
+
+            let po = ray.point_at(inter.t);
+            let mat = &self.materials[inter.material_index as usize];
+            if ray.direction.dot(inter.normal) < 0.0 {
+                let le = color_coe.mul_element_wise(Vector3::from(mat.emission));
+                add_emission(le, curr_depth, first_lobe, &mut final_color, &mut lobes);
+            }
+
+            let (local_to_world, world_to_local) = coord_from_z(inter.normal);
+            let wo = world_to_local * -ray.direction;
+
+            let mut li = Vector3::new(0.0, 0.0, 0.0);
+            if let Some(light) = self.lights.get(light_index) {
+                let light_sample = light_sample(light, po);
+                let wi = world_to_local * light_sample.wi;
+
+                let bxdf = mat_bxdf(mat, wo, wi);
+
+                let shadow_ray = Ray::new(po, light_sample.wi);
+                if light_sample.pdf > 0.0
+                    && !leaks(&inter, -ray.direction, light_sample.wi)
+                    && !self.intersect_test(shadow_ray, light_sample.dist)
+                {
+                    let light_coe = light_sample.strength * wi.z / light_sample.pdf.max(0.0001);
+                    li = light_coe.mul_element_wise(bxdf);
+                    if curr_depth == 0 {
+                        for lobe in BsdfLobe::ALL {
+                            lobes[Lobe::DirectDiffuse as usize + lobe as usize] = light_coe
+                                .mul_element_wise(mat_bxdf_lobe(mat, wo, wi, lobe))
+                                * self.lights.len() as f32;
+                        }
+                    }
+                }
+            }
+            let contribution = color_coe.mul_element_wise(li) * self.lights.len() as f32;
+            final_color += contribution;
+            if curr_depth > 0 {
+                lobes[Lobe::IndirectDiffuse as usize + first_lobe as usize] += contribution;
+            }
+
+            let (sample, lobe) = mat_sample(mat, wo, sampler);
+            if curr_depth == 0 {
+                first_lobe = lobe;
+            }
+            let wi_world = local_to_world * sample.wi;
+            if leaks(&inter, -ray.direction, wi_world) {
+                break;
+            }
+            ray = Ray::new(po, wi_world);
+            color_coe = color_coe.mul_element_wise(sample.bxdf) * sample.wi.z.abs()
+                / sample.pdf.max(0.0001);
+
+            let color_coe_lum = color_luminance(color_coe);
+            if color_coe_lum < 0.001 {
+                break;
+            }
+
+            let rr_rand = sampler.random();
+            let rr_prop = color_coe_lum.min(1.0);
+            if rr_rand > rr_prop {
+                break;
+            }
+
+            color_coe /= rr_prop;
+        }
+
+        (final_color, lobes)
+    }
+}
+
+/// Adds emitted radiance `le` (already weighted by the path) found at `depth`, camera rays
+/// see it directly, after one bounce it is direct light of `first_lobe` and after more
+/// indirect light
+fn add_emission(
+    le: Vector3<f32>,
+    depth: u32,
+    first_lobe: BsdfLobe,
+    final_color: &mut Vector3<f32>,
+    lobes: &mut [Vector3<f32>; LOBE_COUNT],
+) {
+    *final_color += le;
+    let lobe = match depth {
+        0 => Lobe::Emission as usize,
+        1 => Lobe::DirectDiffuse as usize + first_lobe as usize,
+        _ => Lobe::IndirectDiffuse as usize + first_lobe as usize,
+    };
+    lobes[lobe] += le;
+}
+
+/// Whether the shading normal takes `wo` and `wi` to be on the same side while the surface
+/// itself doesn't (or the other way around), following such paths would leak light through it
+fn leaks(inter: &Intersection, wo: Vector3<f32>, wi: Vector3<f32>) -> bool {
+    let geometric = wo.dot(inter.geometric_normal) * wi.dot(inter.geometric_normal) > 0.0;
+    let shading = wo.dot(inter.normal) * wi.dot(inter.normal) > 0.0;
+    geometric != shading
+}
+
+/// Returns (local to world, world to local) of a frame whose z axis is `z_world`
+fn coord_from_z(z_world: Vector3<f32>) -> (Matrix3<f32>, Matrix3<f32>) {
+    let sign = if z_world.z == 0.0 {
+        1.0
+    } else {
+        z_world.z.signum()
+    };
+    let a = -1.0 / (sign + z_world.z);
+    let b = z_world.x * z_world.y * a;
+    let x_world = Vector3::new(
+        1.0 + sign * z_world.x * z_world.x * a,
+        sign * b,
+        -sign * z_world.x,
+    );
+    let y_world = Vector3::new(b, sign + z_world.y * z_world.y * a, -z_world.y);
+
+    let local_to_world = Matrix3::from_cols(x_world, y_world, z_world);
+    (local_to_world, local_to_world.transpose())
+}
+
+fn light_sample(light: &Light, pi: Point3<f32>) -> LightSample {
+    match *light {
+        Light::Point { position, strength } => {
+            let samp = Point3::from(position) - pi;
+            let dist_sqr = samp.magnitude2();
+            let dist = dist_sqr.sqrt();
+            LightSample {
+                wi: samp / dist,
+                pdf: 1.0,
+                strength: Vector3::from(strength) / dist_sqr,
+                dist,
+            }
+        }
+        Light::Directional {
+            direction,
+            strength,
+        } => LightSample {
+            wi: -Vector3::from(direction),
+            pdf: 1.0,
+            strength: Vector3::from(strength),
+            dist: 1e9,
+        },
+    }
+}
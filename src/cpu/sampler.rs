@@ -0,0 +1,256 @@
+// Port of the samplers in `ray_tracing.comp`, keep both in sync when changing either of them.
+
+use crate::core::{rand_hash, BlueNoise, SamplerKind};
+
+/// dimensions are handed out in sets, set 0 is for the camera ray and set 1 + depth for a bounce
+const SAMPLER_SET_SIZE: u32 = 4;
+/// dimensions reserved for every sample in the PCG stream of a pixel
+const PCG_SAMPLE_STRIDE: u32 = 4096;
+const PCG_MULTIPLIER: u32 = 747796405;
+
+/// Joe-Kuo direction numbers of Sobol dimensions 1 to 3, dimension 0 is the bit reversed index
+const SOBOL_DIRECTIONS: [[u32; 32]; 3] = [
+    [
+        0x80000000, 0xc0000000, 0xa0000000, 0xf0000000, 0x88000000, 0xcc000000, 0xaa000000,
+        0xff000000, 0x80800000, 0xc0c00000, 0xa0a00000, 0xf0f00000, 0x88880000, 0xcccc0000,
+        0xaaaa0000, 0xffff0000, 0x80008000, 0xc000c000, 0xa000a000, 0xf000f000, 0x88008800,
+        0xcc00cc00, 0xaa00aa00, 0xff00ff00, 0x80808080, 0xc0c0c0c0, 0xa0a0a0a0, 0xf0f0f0f0,
+        0x88888888, 0xcccccccc, 0xaaaaaaaa, 0xffffffff,
+    ],
+    [
+        0x80000000, 0xc0000000, 0x60000000, 0x90000000, 0xe8000000, 0x5c000000, 0x8e000000,
+        0xc5000000, 0x68800000, 0x9cc00000, 0xee600000, 0x55900000, 0x80680000, 0xc09c0000,
+        0x60ee0000, 0x90550000, 0xe8808000, 0x5cc0c000, 0x8e606000, 0xc5909000, 0x6868e800,
+        0x9c9c5c00, 0xeeee8e00, 0x5555c500, 0x8000e880, 0xc0005cc0, 0x60008e60, 0x9000c590,
+        0xe8006868, 0x5c009c9c, 0x8e00eeee, 0xc5005555,
+    ],
+    [
+        0x80000000, 0xc0000000, 0x20000000, 0x50000000, 0xf8000000, 0x74000000, 0xa2000000,
+        0x93000000, 0xd8800000, 0x25400000, 0x59e00000, 0xe6d00000, 0x78080000, 0xb40c0000,
+        0x82020000, 0xc3050000, 0x208f8000, 0x51474000, 0xfbea2000, 0x75d93000, 0xa0858800,
+        0x914e5400, 0xdbe79e00, 0x25db6d00, 0x58800080, 0xe54000c0, 0x79e00020, 0xb6d00050,
+        0x800800f8, 0xc00c0074, 0x200200a2, 0x50050093,
+    ],
+];
+
+/// Same generator as `random()` in `ray_tracing.comp` for one sample of a pixel
+pub struct Sampler<'a> {
+    kind: SamplerKind,
+    blue_noise: Option<&'a BlueNoise>,
+    pixel: (u32, u32),
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+    seed: u32,
+    pcg_state: u32,
+    pcg_increment: u32,
+}
+
+impl<'a> Sampler<'a> {
+    /// `blue_noise` is only needed by `SamplerKind::BlueNoise`
+    pub fn new(
+        kind: SamplerKind,
+        blue_noise: Option<&'a BlueNoise>,
+        pixel: (u32, u32),
+        pixel_index: u32,
+        sample_index: u32,
+    ) -> Self {
+        let pixel_seed = rand_hash(pixel_index);
+        let pcg_increment = (pixel_index << 1) | 1;
+        Self {
+            kind,
+            blue_noise,
+            pixel,
+            pixel_seed,
+            index: sample_index,
+            dimension: 0,
+            seed: pixel_index.wrapping_add(rand_hash(sample_index)),
+            pcg_state: pcg_advance(
+                pixel_seed,
+                pcg_increment,
+                sample_index.wrapping_mul(PCG_SAMPLE_STRIDE),
+            ),
+            pcg_increment,
+        }
+    }
+
+    /// The following `random()` calls use the dimensions of `set`
+    pub fn start_set(&mut self, set: u32) {
+        let dimension = set * SAMPLER_SET_SIZE;
+        if self.kind == SamplerKind::Pcg {
+            self.pcg_state = pcg_advance(
+                self.pcg_state,
+                self.pcg_increment,
+                dimension.wrapping_sub(self.dimension),
+            );
+        }
+        self.dimension = dimension;
+    }
+
+    pub fn random(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension = self.dimension.wrapping_add(1);
+        match self.kind {
+            SamplerKind::Hash => {
+                self.seed = rand_hash(self.seed);
+                self.seed as f32 / 4294967295.0
+            }
+            SamplerKind::Pcg => {
+                let x = pcg_output(self.pcg_state);
+                self.pcg_state = pcg_step(self.pcg_state, self.pcg_increment);
+                uint_to_unit_float(x)
+            }
+            SamplerKind::Sobol => {
+                uint_to_unit_float(sobol_owen(self.index, dimension, self.pixel_seed))
+            }
+            SamplerKind::BlueNoise => {
+                let offset_hash = rand_hash(dimension);
+                let shift = self.blue_noise.map_or(0.0, |noise| {
+                    noise.value(
+                        self.pixel.0 + (offset_hash & 63),
+                        self.pixel.1 + ((offset_hash >> 6) & 63),
+                    )
+                });
+                let value = uint_to_unit_float(sobol_owen(self.index, dimension, 0)) + shift;
+                value - value.floor()
+            }
+        }
+    }
+}
+
+fn pcg_step(state: u32, increment: u32) -> u32 {
+    state.wrapping_mul(PCG_MULTIPLIER).wrapping_add(increment)
+}
+
+/// PCG-RXS-M-XS with 32-bit state
+fn pcg_output(state: u32) -> u32 {
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// State of the PCG LCG after `delta` steps (Brown 1994)
+fn pcg_advance(state: u32, increment: u32, mut delta: u32) -> u32 {
+    let mut acc_mult = 1u32;
+    let mut acc_plus = 0u32;
+    let mut cur_mult = PCG_MULTIPLIER;
+    let mut cur_plus = increment;
+    while delta > 0 {
+        if delta & 1 != 0 {
+            acc_mult = acc_mult.wrapping_mul(cur_mult);
+            acc_plus = acc_plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+        }
+        cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+        cur_mult = cur_mult.wrapping_mul(cur_mult);
+        delta >>= 1;
+    }
+    acc_mult.wrapping_mul(state).wrapping_add(acc_plus)
+}
+
+fn sobol(mut index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let mut x = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            x ^= SOBOL_DIRECTIONS[dimension as usize - 1][bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn hash_combine(seed: u32, v: u32) -> u32 {
+    seed ^ v.wrapping_add(seed << 6).wrapping_add(seed >> 2)
+}
+
+/// Shuffled and Owen scrambled Sobol (Burley 2020), every set of dimensions is a 4D Sobol
+/// sequence with its own shuffling of the sample index
+fn sobol_owen(index: u32, dimension: u32, seed: u32) -> u32 {
+    let set_seed = hash_combine(seed, rand_hash(dimension / SAMPLER_SET_SIZE));
+    let component = dimension % SAMPLER_SET_SIZE;
+    let shuffled = nested_uniform_scramble(index, set_seed);
+    nested_uniform_scramble(
+        sobol(shuffled, component),
+        hash_combine(set_seed, component),
+    )
+}
+
+fn uint_to_unit_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / 16777216.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcg_advance_matches_stepping() {
+        let (mut state, increment) = (12345, (678 << 1) | 1);
+        let start = state;
+        for delta in 0..100 {
+            assert_eq!(pcg_advance(start, increment, delta), state);
+            state = pcg_step(state, increment);
+        }
+        // deltas wrap around the period of 2^32, so going back works too
+        let back = pcg_advance(start, increment, 3u32.wrapping_neg());
+        assert_eq!(pcg_advance(back, increment, 3), start);
+    }
+
+    #[test]
+    fn sobol_owen_is_stratified() {
+        // the first 16 samples of a set are a (0, 4, 2)-net in every set and pixel
+        for (set, seed) in [(0, 0), (1, 42), (5, rand_hash(7))] {
+            let points: Vec<_> = (0..16)
+                .map(|i| {
+                    let dimension = set * SAMPLER_SET_SIZE;
+                    let x = uint_to_unit_float(sobol_owen(i, dimension, seed));
+                    let y = uint_to_unit_float(sobol_owen(i, dimension + 1, seed));
+                    (x, y)
+                })
+                .collect();
+            for log_cols in 0..=4 {
+                let (cols, rows) = (1 << log_cols, 16 >> log_cols);
+                let mut cells = [0; 16];
+                for &(x, y) in &points {
+                    let col = (x * cols as f32) as usize;
+                    let row = (y * rows as f32) as usize;
+                    cells[row * cols + col] += 1;
+                }
+                assert!(cells.iter().all(|&c| c == 1), "{} x {} strata", cols, rows);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_in_unit_interval() {
+        let noise = BlueNoise::new();
+        for kind in SamplerKind::ALL {
+            for sample_index in 0..8 {
+                let mut sampler = Sampler::new(kind, Some(&noise), (3, 70), 1234, sample_index);
+                for set in 0..4 {
+                    sampler.start_set(set);
+                    for _ in 0..SAMPLER_SET_SIZE {
+                        let value = sampler.random();
+                        assert!((0.0..=1.0).contains(&value), "{:?} {}", kind, value);
+                    }
+                }
+            }
+        }
+    }
+}
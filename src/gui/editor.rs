@@ -0,0 +1,152 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    core::{Camera, Light},
+    cpu::Pick,
+    renderer::Renderer,
+};
+
+/// Panel editing the camera, `max_depth`, materials and lights of the rendered scene,
+/// every change restarts accumulation. The material of `selection` is shown first.
+pub fn scene_editor(context: &egui::Context, renderer: &mut Renderer, selection: Option<&Pick>) {
+    egui::Window::new("Scene")
+        .default_width(260.0)
+        .vscroll(true)
+        .show(context, |ui| {
+            if let Some(pick) = selection {
+                egui::CollapsingHeader::new("Selection")
+                    .default_open(true)
+                    .show(ui, |ui| selection_editor(ui, renderer, pick));
+            }
+            egui::CollapsingHeader::new("Camera")
+                .default_open(true)
+                .show(ui, |ui| camera_editor(ui, renderer));
+            egui::CollapsingHeader::new("Materials").show(ui, |ui| {
+                for index in 0..renderer.materials().len() {
+                    ui.push_id(index, |ui| {
+                        ui.collapsing(format!("Material {}", index), |ui| {
+                            material_editor(ui, renderer, index)
+                        });
+                    });
+                }
+            });
+            egui::CollapsingHeader::new("Lights").show(ui, |ui| {
+                for index in 0..renderer.lights().len() {
+                    ui.push_id(index, |ui| {
+                        ui.collapsing(format!("Light {}", index), |ui| {
+                            light_editor(ui, renderer, index)
+                        });
+                    });
+                }
+            });
+        });
+}
+
+fn selection_editor(ui: &mut egui::Ui, renderer: &mut Renderer, pick: &Pick) {
+    ui.label(format!("object {}", pick.object_index));
+    ui.label(format!("triangle {}", pick.triangle_index));
+    ui.label(format!(
+        "position ({:.3}, {:.3}, {:.3})",
+        pick.position.x, pick.position.y, pick.position.z
+    ));
+    ui.label(format!("distance {:.3}", pick.distance));
+    ui.push_id("selection", |ui| {
+        ui.collapsing(format!("Material {}", pick.material_index), |ui| {
+            material_editor(ui, renderer, pick.material_index as usize)
+        });
+    });
+}
+
+fn camera_editor(ui: &mut egui::Ui, renderer: &mut Renderer) {
+    let camera = *renderer.camera();
+    let mut eye: [f32; 3] = camera.eye.into();
+    let mut forward: [f32; 3] = camera.forward.into();
+    let mut up: [f32; 3] = camera.up.into();
+    let mut fov_deg = camera.fov_deg;
+    let mut changed = vec3_editor(ui, "eye", &mut eye, 0.01);
+    changed |= vec3_editor(ui, "forward", &mut forward, 0.01);
+    changed |= vec3_editor(ui, "up", &mut up, 0.01);
+    changed |= ui
+        .add(egui::Slider::new(&mut fov_deg, 1.0..=179.0).text("fov"))
+        .changed();
+    // the basis is renormalized, which fails for zero or parallel forward and up
+    let (forward, up) = (Vector3::from(forward), Vector3::from(up));
+    let valid = forward.cross(up).magnitude2() > 0.0;
+    if changed && valid {
+        renderer.set_camera(Camera::new(eye.into(), forward, up, fov_deg));
+    }
+
+    let mut max_depth = renderer.max_depth();
+    if ui
+        .add(egui::Slider::new(&mut max_depth, 1..=64).text("max depth"))
+        .changed()
+    {
+        renderer.set_max_depth(max_depth);
+    }
+}
+
+fn material_editor(ui: &mut egui::Ui, renderer: &mut Renderer, index: usize) {
+    let mut material = renderer.materials()[index];
+    // roughness is stored squared, the slider shows the one of the scene file
+    let mut roughness = material.roughness.sqrt();
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        changed |= ui.color_edit_button_rgb(&mut material.albedo).changed();
+        ui.label("albedo");
+    });
+    changed |= ui
+        .add(egui::Slider::new(&mut roughness, 0.0..=1.0).text("roughness"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut material.metallic, 0.0..=1.0).text("metallic"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut material.ior, 1.0..=3.0).text("ior"))
+        .changed();
+    changed |= ui
+        .checkbox(&mut material.is_translucent, "translucent")
+        .changed();
+    if changed {
+        material.roughness = roughness * roughness;
+        renderer.set_material(index, material);
+    }
+}
+
+fn light_editor(ui: &mut egui::Ui, renderer: &mut Renderer, index: usize) {
+    let light = match renderer.lights()[index] {
+        Light::Point {
+            mut position,
+            mut strength,
+        } => {
+            let changed = vec3_editor(ui, "position", &mut position, 0.01)
+                | vec3_editor(ui, "strength", &mut strength, 0.05);
+            changed.then(|| Light::point(position, strength))
+        }
+        Light::Directional {
+            mut direction,
+            mut strength,
+        } => {
+            let changed = vec3_editor(ui, "direction", &mut direction, 0.01)
+                | vec3_editor(ui, "strength", &mut strength, 0.05);
+            // a zero direction can't be normalized
+            let valid = direction.iter().any(|&x| x != 0.0);
+            (changed && valid).then(|| Light::directional(direction, strength))
+        }
+    };
+    if let Some(light) = light {
+        renderer.set_light(index, light);
+    }
+}
+
+/// Returns true if any component is changed
+fn vec3_editor(ui: &mut egui::Ui, label: &str, value: &mut [f32; 3], speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        let mut changed = false;
+        for x in value.iter_mut() {
+            changed |= ui.add(egui::DragValue::new(x).speed(speed)).changed();
+        }
+        ui.label(label);
+        changed
+    })
+    .inner
+}
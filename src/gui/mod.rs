@@ -0,0 +1,197 @@
+mod editor;
+
+pub use editor::*;
+
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{Context, Result};
+
+/// egui drawn over the image in the GLFW window, with its own GL objects through `glow`
+pub struct Gui {
+    context: egui::Context,
+    gl: Arc<glow::Context>,
+    painter: egui_glow::Painter,
+    /// events received since the last frame
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    pointer: egui::Pos2,
+    start: Instant,
+    pub visible: bool,
+}
+
+impl Gui {
+    /// The OpenGL context of `window` should be current
+    pub fn new(window: &mut glfw::Window) -> Result<Self> {
+        let gl = unsafe {
+            glow::Context::from_loader_function(|s| window.get_proc_address(s) as *const _)
+        };
+        let gl = Arc::new(gl);
+        let painter = egui_glow::Painter::new(gl.clone(), "", None, false)
+            .map_err(|err| anyhow::anyhow!(err.to_string()))
+            .context("gui: failed to create the painter")?;
+
+        Ok(Self {
+            context: egui::Context::default(),
+            gl,
+            painter,
+            events: vec![],
+            modifiers: egui::Modifiers::default(),
+            pointer: egui::Pos2::ZERO,
+            start: Instant::now(),
+            visible: true,
+        })
+    }
+
+    /// Returns true if the GUI takes the event, it shouldn't control the viewer then
+    pub fn handle_event(&mut self, event: &glfw::WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        match *event {
+            glfw::WindowEvent::CursorPos(x, y) => {
+                self.pointer = egui::pos2(x as f32, y as f32);
+                self.events.push(egui::Event::PointerMoved(self.pointer));
+                self.context.wants_pointer_input()
+            }
+            glfw::WindowEvent::CursorEnter(false) => {
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            glfw::WindowEvent::MouseButton(button, action, mods) => {
+                let button = match button {
+                    glfw::MouseButtonLeft => egui::PointerButton::Primary,
+                    glfw::MouseButtonRight => egui::PointerButton::Secondary,
+                    glfw::MouseButtonMiddle => egui::PointerButton::Middle,
+                    _ => return false,
+                };
+                self.modifiers = modifiers(mods);
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: action != glfw::Action::Release,
+                    modifiers: self.modifiers,
+                });
+                self.context.wants_pointer_input()
+            }
+            glfw::WindowEvent::Scroll(x, y) => {
+                self.events.push(egui::Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Line,
+                    delta: egui::vec2(x as f32, y as f32),
+                    modifiers: self.modifiers,
+                });
+                self.context.wants_pointer_input()
+            }
+            glfw::WindowEvent::Char(c) => {
+                self.events.push(egui::Event::Text(c.to_string()));
+                self.context.wants_keyboard_input()
+            }
+            glfw::WindowEvent::Key(key, _, action, mods) => {
+                self.modifiers = modifiers(mods);
+                if let Some(key) = egui_key(key) {
+                    self.events.push(egui::Event::Key {
+                        key,
+                        physical_key: None,
+                        pressed: action != glfw::Action::Release,
+                        repeat: action == glfw::Action::Repeat,
+                        modifiers: self.modifiers,
+                    });
+                }
+                self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs `run_ui` and draws the result over the default framebuffer
+    pub fn frame(&mut self, window: &glfw::Window, run_ui: impl FnMut(&egui::Context)) {
+        if !self.visible {
+            self.events.clear();
+            return;
+        }
+
+        let (window_width, window_height) = window.get_size();
+        let (fb_width, fb_height) = window.get_framebuffer_size();
+        if window_width <= 0 || fb_width <= 0 {
+            return;
+        }
+        let pixels_per_point = fb_width as f32 / window_width as f32;
+
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(window_width as f32, window_height as f32),
+            )),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: true,
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        let output = self.context.run(input, run_ui);
+        let primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        unsafe {
+            use glow::HasContext;
+            // the sampler object of the renderer would override the parameters of egui textures
+            self.gl.bind_sampler(0, None);
+        }
+        self.painter.paint_and_update_textures(
+            [fb_width as u32, fb_height as u32],
+            output.pixels_per_point,
+            &primitives,
+            &output.textures_delta,
+        );
+        unsafe {
+            use glow::HasContext;
+            // the renderer draws with program pipelines and without blending
+            self.gl.use_program(None);
+            self.gl.disable(glow::BLEND);
+        }
+    }
+}
+
+impl Drop for Gui {
+    fn drop(&mut self) {
+        self.painter.destroy();
+    }
+}
+
+fn modifiers(mods: glfw::Modifiers) -> egui::Modifiers {
+    let ctrl = mods.contains(glfw::Modifiers::Control);
+    egui::Modifiers {
+        alt: mods.contains(glfw::Modifiers::Alt),
+        ctrl,
+        shift: mods.contains(glfw::Modifiers::Shift),
+        mac_cmd: false,
+        command: ctrl,
+    }
+}
+
+/// Keys used to edit text in widgets
+fn egui_key(key: glfw::Key) -> Option<egui::Key> {
+    let key = match key {
+        glfw::Key::Up => egui::Key::ArrowUp,
+        glfw::Key::Down => egui::Key::ArrowDown,
+        glfw::Key::Left => egui::Key::ArrowLeft,
+        glfw::Key::Right => egui::Key::ArrowRight,
+        glfw::Key::Home => egui::Key::Home,
+        glfw::Key::End => egui::Key::End,
+        glfw::Key::Escape => egui::Key::Escape,
+        glfw::Key::Tab => egui::Key::Tab,
+        glfw::Key::Backspace => egui::Key::Backspace,
+        glfw::Key::Delete => egui::Key::Delete,
+        glfw::Key::Enter | glfw::Key::KpEnter => egui::Key::Enter,
+        glfw::Key::A => egui::Key::A,
+        glfw::Key::Z => egui::Key::Z,
+        _ => return None,
+    };
+    Some(key)
+}
@@ -0,0 +1,858 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    Deserialize, Deserializer, Serialize,
+};
+
+use crate::core::{Camera, Light, Material};
+
+/// Scene file as it is written, optional fields are `None` when they are left out so that
+/// saving it back keeps the file as it was apart from the edits
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    /// files whose materials, meshes, objects and lights come before the ones of this file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// directories (relative to the scene file) where included files and meshes are looked
+    /// for when they aren't next to the file naming them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub search_paths: Vec<String>,
+    pub camera: CameraDescription,
+    /// default: all fields default
+    #[serde(default)]
+    pub bvh: BvhDescription,
+    pub max_depth: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampler: Option<String>,
+    pub output: OutputDescription,
+    #[serde(default)]
+    pub materials: Vec<MaterialDescription>,
+    #[serde(default)]
+    pub meshes: Vec<MeshDescription>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+    /// default: no lights
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    /// constant radiance seen by rays leaving the scene, default: none, then only camera
+    /// rays see a dark gray background
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<[f32; 3]>,
+}
+
+/// Material library or object set included by scenes, indices in it count the entries of
+/// the files it includes first, like in scene files
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct IncludeDescription {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub materials: Vec<MaterialDescription>,
+    #[serde(default)]
+    pub meshes: Vec<MeshDescription>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+}
+
+/// An OBJ or PLY file relative to the scene file, or a mesh given in the scene, either may be
+/// wrapped to choose its shading
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MeshDescription {
+    File(String),
+    Inline(InlineMeshDescription),
+    Shaded(ShadedMeshDescription),
+}
+
+/// The form is chosen by the JSON type and the `mesh` key instead of trying them in turn like
+/// `untagged` does, so typos and type errors of the chosen form are reported
+impl<'de> Deserialize<'de> for MeshDescription {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let mesh = match &value {
+            serde_json::Value::String(file) => return Ok(MeshDescription::File(file.clone())),
+            serde_json::Value::Object(map) if map.contains_key("mesh") => {
+                serde_json::from_value(value).map(MeshDescription::Shaded)
+            }
+            serde_json::Value::Object(_) => {
+                serde_json::from_value(value).map(MeshDescription::Inline)
+            }
+            _ => return Err(D::Error::custom("expected a file name or a mesh object")),
+        };
+        mesh.map_err(D::Error::custom)
+    }
+}
+
+/// `shading` is "smooth" (default) or "flat"; smooth normals are generated for meshes without
+/// normals, faces meeting at more than `crease_angle` degrees (default: 60) don't share them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShadedMeshDescription {
+    pub mesh: Box<MeshDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shading: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crease_angle: Option<f32>,
+}
+
+impl MeshDescription {
+    /// The file of the mesh, if it is one
+    pub fn file_mut(&mut self) -> Option<&mut String> {
+        match self {
+            MeshDescription::File(file) => Some(file),
+            MeshDescription::Inline(_) => None,
+            MeshDescription::Shaded(shaded) => shaded.mesh.file_mut(),
+        }
+    }
+}
+
+/// Unknown fields are errors, tagged enums are buffered so `serde_ignored` can't see them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum InlineMeshDescription {
+    /// `normals` are per vertex like `positions`, or left out
+    Triangles {
+        positions: Vec<[f32; 3]>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normals: Vec<[f32; 3]>,
+        indices: Vec<u32>,
+    },
+    /// from -1 to 1 in x and z, facing +y
+    Plane {
+        #[serde(default = "default_subdivisions")]
+        subdivisions: u32,
+    },
+    /// from -1 to 1 with a flat normal per face
+    Cube {
+        #[serde(default = "default_subdivisions")]
+        subdivisions: u32,
+    },
+    /// radius 1 with its poles on the y axis
+    UvSphere {
+        #[serde(default = "default_segments")]
+        segments: u32,
+        #[serde(default = "default_rings")]
+        rings: u32,
+    },
+    /// radius 1 around the y axis from y = -1 to 1
+    Cylinder {
+        #[serde(default = "default_segments")]
+        segments: u32,
+        #[serde(default = "default_subdivisions")]
+        rings: u32,
+        /// default: true
+        #[serde(default = "default_caps")]
+        caps: bool,
+    },
+    /// around the y axis, `segments` go around it and `sides` around the tube
+    Torus {
+        #[serde(default = "default_radius")]
+        major_radius: f32,
+        #[serde(default = "default_minor_radius")]
+        minor_radius: f32,
+        #[serde(default = "default_segments")]
+        segments: u32,
+        #[serde(default = "default_sides")]
+        sides: u32,
+    },
+    /// x and z from -1 to 1, y from 0 to 2, open towards +z; its models are the white floor,
+    /// ceiling and back wall, the red left wall, the green right wall, the short and the tall block
+    CornellBox {
+        #[serde(default = "default_subdivisions")]
+        subdivisions: u32,
+    },
+}
+
+fn default_subdivisions() -> u32 {
+    1
+}
+
+fn default_segments() -> u32 {
+    32
+}
+
+fn default_rings() -> u32 {
+    16
+}
+
+fn default_sides() -> u32 {
+    16
+}
+
+fn default_caps() -> bool {
+    true
+}
+
+fn default_minor_radius() -> f32 {
+    0.25
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub eye: [f32; 3],
+    pub forward: [f32; 3],
+    pub up: [f32; 3],
+    /// vertical field of view in degrees
+    pub fov: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BvhDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_leaf_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket_number: Option<u32>,
+    /// cache file relative to the scene file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutputDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<DisplayDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<FilterDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denoise: Option<DenoiseDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adaptive: Option<AdaptiveDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lobes: Option<LobesDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workgroup_size: Option<[u32; 2]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resize: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisplayDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tone_mapping: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub white_balance: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+}
+
+/// A filter type name, or an object with a 'type' and the parameters to override
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FilterDescription {
+    Name(String),
+    Params {
+        #[serde(rename = "type")]
+        ty: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        radius: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sigma: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        b: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        c: Option<f32>,
+    },
+}
+
+/// The form is picked by the JSON type, so that errors of the parameters are reported as they
+/// are instead of as a mismatch of every form
+impl<'de> Deserialize<'de> for FilterDescription {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Unknown fields are errors, the enum is buffered so `serde_ignored` can't see them
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Params {
+            #[serde(rename = "type")]
+            ty: String,
+            #[serde(default)]
+            radius: Option<f32>,
+            #[serde(default)]
+            sigma: Option<f32>,
+            #[serde(default)]
+            b: Option<f32>,
+            #[serde(default)]
+            c: Option<f32>,
+        }
+
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value {
+            serde_json::Value::String(name) => Ok(FilterDescription::Name(name)),
+            serde_json::Value::Object(_) => {
+                let params: Params = serde_json::from_value(value).map_err(D::Error::custom)?;
+                Ok(FilterDescription::Params {
+                    ty: params.ty,
+                    radius: params.radius,
+                    sigma: params.sigma,
+                    b: params.b,
+                    c: params.c,
+                })
+            }
+            _ => Err(D::Error::custom(
+                "expected a filter name or an object with a 'type'",
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DenoiseDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_color: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_normal: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_depth: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temporal: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temporal_alpha: Option<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_samples: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_error: Option<f32>,
+}
+
+/// "all" or a list of lobe names
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LobesDescription {
+    Name(String),
+    List(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MaterialDescription {
+    pub albedo: [f32; 3],
+    /// default: 1.5
+    #[serde(default = "default_ior")]
+    pub ior: f32,
+    /// perceptual roughness, it is squared when loaded, default: 1
+    #[serde(default = "default_roughness")]
+    pub roughness: f32,
+    /// default: 0
+    #[serde(default)]
+    pub metallic: f32,
+    /// default: false
+    #[serde(default)]
+    pub is_translucent: bool,
+    /// radiance leaving the side the normals point to, default: 0
+    #[serde(default, skip_serializing_if = "is_black")]
+    pub emission: [f32; 3],
+}
+
+fn default_ior() -> f32 {
+    1.5
+}
+
+fn default_roughness() -> f32 {
+    1.0
+}
+
+fn is_black(color: &[f32; 3]) -> bool {
+    *color == [0.0; 3]
+}
+
+/// An object is either a mesh or an analytic shape
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ObjectDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformDescription>,
+    /// index of the OBJ file in `meshes` and of the model in it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<[u32; 2]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<ShapeDescription>,
+    pub material: u32,
+}
+
+/// Analytic shape around the origin of the object, flat shapes are in the xy plane and face +z.
+/// Unknown fields are errors, tagged enums are buffered so `serde_ignored` can't see them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDescription {
+    Sphere {
+        #[serde(default = "default_radius")]
+        radius: f32,
+    },
+    Disc {
+        #[serde(default = "default_radius")]
+        radius: f32,
+    },
+    /// `size` is along x and y
+    Quad {
+        #[serde(default = "default_size")]
+        size: [f32; 2],
+    },
+}
+
+fn default_radius() -> f32 {
+    1.0
+}
+
+fn default_size() -> [f32; 2] {
+    [1.0, 1.0]
+}
+
+/// `matrix` (column major) is scaled, rotated (Y, X then Z, in degrees) and translated
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransformDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<[f32; 16]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate: Option<[f32; 3]>,
+}
+
+/// Unknown fields are errors, tagged enums are buffered so `serde_ignored` can't see them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDescription {
+    Point {
+        position: [f32; 3],
+        strength: [f32; 3],
+    },
+    Directional {
+        direction: [f32; 3],
+        strength: [f32; 3],
+    },
+}
+
+impl SceneDescription {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        read_json(path.as_ref())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("scene: failed to write '{}'", path.display()))?;
+        println!("Scene is saved to '{}'", path.display());
+        Ok(())
+    }
+
+    /// Takes over runtime edits, entries that still load to the same values are kept as
+    /// they are written
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        max_depth: u32,
+        materials: &[Material],
+        lights: &[Light],
+    ) {
+        if self.camera.build() != *camera {
+            self.camera = CameraDescription::from_camera(camera);
+        }
+        self.max_depth = max_depth;
+        // included entries come first, their edits aren't saved
+        let materials = &materials[materials.len().saturating_sub(self.materials.len())..];
+        let lights = &lights[lights.len().saturating_sub(self.lights.len())..];
+        for (desc, material) in self.materials.iter_mut().zip(materials) {
+            if desc.build() != *material {
+                *desc = MaterialDescription::from_material(material);
+            }
+        }
+        for (desc, light) in self.lights.iter_mut().zip(lights) {
+            if desc.build() != *light {
+                *desc = LightDescription::from_light(light);
+            }
+        }
+    }
+}
+
+/// Reads a scene or included file, unknown fields are printed as warnings
+pub(super) fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("scene: failed to open '{}'", path.display()))?;
+    let (description, unknown_fields) = parse_json(&json)
+        .with_context(|| format!("scene: failed to parse '{}'", path.display()))?;
+    for field in unknown_fields {
+        println!(
+            "WARNING: unknown field '{}' in '{}' is ignored",
+            field,
+            path.display()
+        );
+    }
+    Ok(description)
+}
+
+/// Returns the description and the paths of fields it doesn't know, errors start with
+/// the path of the field, like `materials[3].ior: invalid type: ...`
+fn parse_json<T: DeserializeOwned>(json: &str) -> Result<(T, Vec<String>)> {
+    let mut unknown_fields = vec![];
+    let mut callback = |path: serde_ignored::Path| unknown_fields.push(field_path(&path));
+    let mut json_deserializer = serde_json::Deserializer::from_str(json);
+    let deserializer = serde_ignored::Deserializer::new(&mut json_deserializer, &mut callback);
+    let description = serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        match err.path().to_string().as_str() {
+            "." => anyhow!("{}", err.inner()),
+            path => anyhow!("{}: {}", path, err.inner()),
+        }
+    })?;
+    json_deserializer.end()?;
+    Ok((description, unknown_fields))
+}
+
+/// `materials[3].ior` like the paths of `serde_path_to_error`
+fn field_path(path: &serde_ignored::Path) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => {
+            format!("{}[{}]", field_path(parent), index)
+        }
+        serde_ignored::Path::Map { parent, key } => match field_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => field_path(parent),
+    }
+}
+
+impl CameraDescription {
+    pub fn build(&self) -> Camera {
+        Camera::new(
+            self.eye.into(),
+            self.forward.into(),
+            self.up.into(),
+            self.fov,
+        )
+    }
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            eye: camera.eye.into(),
+            forward: camera.forward.into(),
+            up: camera.up.into(),
+            fov: camera.fov_deg,
+        }
+    }
+}
+
+impl MaterialDescription {
+    pub fn build(&self) -> Material {
+        Material::new(
+            self.albedo,
+            self.ior,
+            self.roughness,
+            self.metallic,
+            self.is_translucent,
+            self.emission,
+        )
+    }
+
+    pub fn from_material(material: &Material) -> Self {
+        Self {
+            albedo: material.albedo,
+            ior: material.ior,
+            roughness: material.roughness.sqrt(),
+            metallic: material.metallic,
+            is_translucent: material.is_translucent,
+            emission: material.emission,
+        }
+    }
+}
+
+impl LightDescription {
+    pub fn build(&self) -> Light {
+        match *self {
+            LightDescription::Point { position, strength } => Light::point(position, strength),
+            LightDescription::Directional {
+                direction,
+                strength,
+            } => Light::directional(direction, strength),
+        }
+    }
+
+    pub fn from_light(light: &Light) -> Self {
+        match *light {
+            Light::Point { position, strength } => LightDescription::Point { position, strength },
+            Light::Directional {
+                direction,
+                strength,
+            } => LightDescription::Directional {
+                direction,
+                strength,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenes_round_trip() {
+        for name in ["test_scene_00", "test_scene_01"] {
+            let path = format!("{}/scenes/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+            let desc = SceneDescription::from_file(&path).unwrap();
+            let json = serde_json::to_string(&desc).unwrap();
+            let reloaded: SceneDescription = serde_json::from_str(&json).unwrap();
+            assert_eq!(desc, reloaded);
+        }
+    }
+
+    fn minimal_scene() -> serde_json::Value {
+        serde_json::json!({
+            "camera": { "eye": [0.0, 0.0, 5.0], "forward": [0.0, 0.0, -1.0], "up": [0.0, 1.0, 0.0], "fov": 45.0 },
+            "max_depth": 4,
+            "output": { "width": 8, "height": 8 },
+            "materials": [{ "albedo": [1.0, 1.0, 1.0] }],
+            "meshes": [],
+            "objects": []
+        })
+    }
+
+    #[test]
+    fn optional_fields_have_defaults() {
+        let (desc, unknown_fields) =
+            parse_json::<SceneDescription>(&minimal_scene().to_string()).unwrap();
+        assert!(unknown_fields.is_empty());
+        assert_eq!(desc.bvh, BvhDescription::default());
+        assert!(desc.lights.is_empty());
+        assert_eq!(
+            desc.materials[0],
+            MaterialDescription {
+                albedo: [1.0, 1.0, 1.0],
+                ior: 1.5,
+                roughness: 1.0,
+                metallic: 0.0,
+                is_translucent: false,
+                emission: [0.0; 3],
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let mut scene = minimal_scene();
+        scene["materials"][0]["metalic"] = 1.0.into();
+        scene["output"]["denoise"] = serde_json::json!({ "iteration": 2 });
+        scene["fog"] = true.into();
+        let (_, unknown_fields) = parse_json::<SceneDescription>(&scene.to_string()).unwrap();
+        assert_eq!(
+            unknown_fields,
+            vec!["fog", "materials[0].metalic", "output.denoise.iteration"]
+        );
+    }
+
+    #[test]
+    fn errors_tell_the_path() {
+        let error = |scene: serde_json::Value| {
+            let err = parse_json::<SceneDescription>(&scene.to_string()).unwrap_err();
+            err.to_string()
+        };
+
+        let mut scene = minimal_scene();
+        scene["materials"] = serde_json::json!([
+            { "albedo": [1.0, 1.0, 1.0] },
+            { "albedo": [1.0, 1.0, 1.0], "ior": "1.5" }
+        ]);
+        let message = error(scene);
+        assert!(
+            message.starts_with("materials[1].ior: invalid type"),
+            "{}",
+            message
+        );
+
+        let mut scene = minimal_scene();
+        scene["camera"]["eye"] = serde_json::json!([0.0, 0.0]);
+        let message = error(scene);
+        assert!(
+            message.starts_with("camera.eye: invalid length 2"),
+            "{}",
+            message
+        );
+
+        let mut scene = minimal_scene();
+        scene.as_object_mut().unwrap().remove("max_depth");
+        let message = error(scene);
+        assert!(
+            message.starts_with("missing field `max_depth`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn mesh_errors_are_reported() {
+        let error = |mesh: serde_json::Value| {
+            let mut scene = minimal_scene();
+            scene["meshes"] = serde_json::json!(["a.obj", mesh]);
+            let err = parse_json::<SceneDescription>(&scene.to_string()).unwrap_err();
+            err.to_string()
+        };
+
+        let message = error(serde_json::json!({ "type": "cube", "subdivisons": 2 }));
+        assert!(
+            message.starts_with("meshes[1]: unknown field `subdivisons`"),
+            "{}",
+            message
+        );
+
+        let message = error(serde_json::json!({ "mesh": { "type": "plane" }, "shadng": "flat" }));
+        assert!(
+            message.starts_with("meshes[1]: unknown field `shadng`"),
+            "{}",
+            message
+        );
+
+        let message = error(serde_json::json!({ "type": "cube", "subdivisions": "2" }));
+        assert!(
+            message.starts_with("meshes[1]: invalid type: string \"2\""),
+            "{}",
+            message
+        );
+
+        let message = error(serde_json::json!({ "type": "cone" }));
+        assert!(
+            message.starts_with("meshes[1]: unknown variant `cone`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn light_and_filter_errors_are_reported() {
+        let error = |scene: serde_json::Value| {
+            let err = parse_json::<SceneDescription>(&scene.to_string()).unwrap_err();
+            err.to_string()
+        };
+
+        let mut scene = minimal_scene();
+        scene["lights"] = serde_json::json!([
+            { "type": "point", "position": [0.0, 1.0, 0.0], "strenght": [1.0, 1.0, 1.0] }
+        ]);
+        let message = error(scene);
+        assert!(
+            message.starts_with("lights[0]: unknown field `strenght`"),
+            "{}",
+            message
+        );
+
+        let mut scene = minimal_scene();
+        scene["output"]["filter"] = serde_json::json!({ "type": "gaussian", "sigam": 0.5 });
+        let message = error(scene);
+        assert!(
+            message.starts_with("output.filter: unknown field `sigam`"),
+            "{}",
+            message
+        );
+
+        let mut scene = minimal_scene();
+        scene["output"]["filter"] = serde_json::json!({ "type": "gaussian", "sigma": "0.5" });
+        let message = error(scene);
+        assert!(
+            message.starts_with("output.filter: invalid type: string \"0.5\""),
+            "{}",
+            message
+        );
+
+        let mut scene = minimal_scene();
+        scene["output"]["filter"] = serde_json::json!("tent");
+        let (desc, _) = parse_json::<SceneDescription>(&scene.to_string()).unwrap();
+        assert_eq!(
+            desc.output.filter,
+            Some(FilterDescription::Name("tent".to_string()))
+        );
+    }
+
+    #[test]
+    fn shape_errors_are_reported() {
+        let mut scene = minimal_scene();
+        scene["objects"] = serde_json::json!([
+            { "shape": { "type": "sphere", "radus": 2.0 }, "material": 0 }
+        ]);
+        let err = parse_json::<SceneDescription>(&scene.to_string()).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("objects[0].shape: unknown field `radus`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn mesh_forms_are_parsed() {
+        let mut scene = minimal_scene();
+        scene["meshes"] = serde_json::json!([
+            "a.obj",
+            { "type": "cube", "subdivisions": 2 },
+            { "mesh": "b.ply", "shading": "flat" }
+        ]);
+        let (desc, unknown_fields) = parse_json::<SceneDescription>(&scene.to_string()).unwrap();
+        assert!(unknown_fields.is_empty());
+        assert_eq!(
+            desc.meshes,
+            vec![
+                MeshDescription::File("a.obj".to_string()),
+                MeshDescription::Inline(InlineMeshDescription::Cube { subdivisions: 2 }),
+                MeshDescription::Shaded(ShadedMeshDescription {
+                    mesh: Box::new(MeshDescription::File("b.ply".to_string())),
+                    shading: Some("flat".to_string()),
+                    crease_angle: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_entries_are_kept() {
+        let mut desc: SceneDescription = serde_json::from_value(serde_json::json!({
+            "camera": { "eye": [0.0, 0.0, 5.0], "forward": [0.0, 0.0, -2.0], "up": [0.0, 1.0, 0.0], "fov": 45.0 },
+            "bvh": {},
+            "max_depth": 4,
+            "output": { "width": 8, "height": 8 },
+            "materials": [
+                { "albedo": [1.0, 1.0, 1.0], "ior": 1.5, "roughness": 0.3, "metallic": 0.0, "is_translucent": false },
+                { "albedo": [1.0, 0.0, 0.0], "ior": 1.5, "roughness": 0.3, "metallic": 0.0, "is_translucent": false }
+            ],
+            "meshes": [],
+            "objects": [],
+            "lights": []
+        }))
+        .unwrap();
+        let original = desc.clone();
+
+        let camera = desc.camera.build();
+        let mut materials: Vec<_> = desc.materials.iter().map(|m| m.build()).collect();
+        desc.update(&camera, 4, &materials, &[]);
+        assert_eq!(desc, original);
+
+        materials[1].metallic = 1.0;
+        desc.update(&camera, 8, &materials, &[]);
+        assert_eq!(desc.max_depth, 8);
+        assert_eq!(desc.materials[0], original.materials[0]);
+        assert_eq!(desc.materials[1].metallic, 1.0);
+        assert!((desc.materials[1].roughness - 0.3).abs() < 1e-6);
+        // the forward direction isn't normalized in the file
+        assert_eq!(desc.camera.forward, [0.0, 0.0, -2.0]);
+    }
+}
@@ -0,0 +1,267 @@
+use std::f32::consts::PI;
+
+use cgmath::{Deg, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use super::ModelData;
+
+impl ModelData {
+    fn push_vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>) {
+        self.positions.extend([position.x, position.y, position.z]);
+        self.normals.extend([normal.x, normal.y, normal.z]);
+    }
+
+    fn append(&mut self, other: ModelData) {
+        let base = self.positions.len() as u32 / 3;
+        self.positions.extend(other.positions);
+        self.normals.extend(other.normals);
+        self.indices.extend(other.indices.iter().map(|i| base + i));
+    }
+
+    fn transform(mut self, matrix: Matrix4<f32>) -> ModelData {
+        let normal_matrix = Matrix3::from_cols(
+            matrix.x.truncate(),
+            matrix.y.truncate(),
+            matrix.z.truncate(),
+        )
+        .invert()
+        .unwrap()
+        .transpose();
+        for p in self.positions.chunks_exact_mut(3) {
+            let q = matrix.transform_point(Point3::new(p[0], p[1], p[2]));
+            p.copy_from_slice(&[q.x, q.y, q.z]);
+        }
+        for n in self.normals.chunks_exact_mut(3) {
+            let m = (normal_matrix * Vector3::new(n[0], n[1], n[2])).normalize();
+            n.copy_from_slice(&[m.x, m.y, m.z]);
+        }
+        self
+    }
+}
+
+fn empty() -> ModelData {
+    ModelData {
+        positions: vec![],
+        normals: vec![],
+        indices: vec![],
+    }
+}
+
+/// Grid of `u_count` by `v_count` quads over `surface`, which maps `[0, 1]^2` to a position and
+/// a normal; the triangles face the side of `d surface / du x d surface / dv`
+fn surface<F>(u_count: u32, v_count: u32, surface: F) -> ModelData
+where
+    F: Fn(f32, f32) -> (Vector3<f32>, Vector3<f32>),
+{
+    let mut model = empty();
+    for j in 0..=v_count {
+        for i in 0..=u_count {
+            let (position, normal) = surface(i as f32 / u_count as f32, j as f32 / v_count as f32);
+            model.push_vertex(position, normal);
+        }
+    }
+    let index = |i: u32, j: u32| j * (u_count + 1) + i;
+    let same = |a: u32, b: u32| {
+        let (a, b) = (a as usize * 3, b as usize * 3);
+        model.positions[a..a + 3] == model.positions[b..b + 3]
+    };
+    let mut indices = vec![];
+    for j in 0..v_count {
+        for i in 0..u_count {
+            let (a, b, c, d) = (
+                index(i, j),
+                index(i + 1, j),
+                index(i + 1, j + 1),
+                index(i, j + 1),
+            );
+            // skip the triangles collapsed at poles
+            for [p, q, r] in [[a, b, c], [a, c, d]] {
+                if !same(p, q) && !same(q, r) && !same(r, p) {
+                    indices.extend([p, q, r]);
+                }
+            }
+        }
+    }
+    model.indices = indices;
+    model
+}
+
+/// Flat grid from `corner` spanned by `u` and `v`, facing `u x v`
+fn grid(corner: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, subdivisions: u32) -> ModelData {
+    let normal = u.cross(v).normalize();
+    surface(subdivisions, subdivisions, |s, t| {
+        (corner + u * s + v * t, normal)
+    })
+}
+
+pub(super) fn plane(subdivisions: u32) -> ModelData {
+    grid(
+        Vector3::new(-1.0, 0.0, -1.0),
+        Vector3::new(0.0, 0.0, 2.0),
+        Vector3::new(2.0, 0.0, 0.0),
+        subdivisions,
+    )
+}
+
+pub(super) fn cube(subdivisions: u32) -> ModelData {
+    let mut model = empty();
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    for axis in 0..3 {
+        for sign in [-1.0, 1.0] {
+            let normal = axes[axis] * sign;
+            let (mut u, mut v) = (axes[(axis + 1) % 3] * 2.0, axes[(axis + 2) % 3] * 2.0);
+            if sign < 0.0 {
+                std::mem::swap(&mut u, &mut v);
+            }
+            model.append(grid(normal - (u + v) * 0.5, u, v, subdivisions));
+        }
+    }
+    model
+}
+
+pub(super) fn uv_sphere(segments: u32, rings: u32) -> ModelData {
+    surface(segments, rings, |u, v| {
+        let normal = if v == 0.0 || v == 1.0 {
+            // exact poles, so the triangles around them are found collapsed
+            Vector3::new(0.0, 2.0 * v - 1.0, 0.0)
+        } else {
+            let (phi, theta) = (2.0 * PI * u, PI * v);
+            Vector3::new(
+                phi.sin() * theta.sin(),
+                -theta.cos(),
+                phi.cos() * theta.sin(),
+            )
+        };
+        (normal, normal)
+    })
+}
+
+pub(super) fn cylinder(segments: u32, rings: u32, caps: bool) -> ModelData {
+    let around = |u: f32| {
+        let phi = 2.0 * PI * u;
+        Vector3::new(phi.sin(), 0.0, phi.cos())
+    };
+    let mut model = surface(segments, rings, |u, v| {
+        let normal = around(u);
+        (normal + Vector3::unit_y() * (2.0 * v - 1.0), normal)
+    });
+    if caps {
+        for sign in [-1.0, 1.0] {
+            let normal = Vector3::unit_y() * sign;
+            let mut cap = empty();
+            cap.push_vertex(normal, normal);
+            for i in 0..=segments {
+                cap.push_vertex(normal + around(i as f32 / segments as f32), normal);
+            }
+            for i in 1..=segments {
+                cap.indices.extend(if sign > 0.0 {
+                    [0, i, i + 1]
+                } else {
+                    [0, i + 1, i]
+                });
+            }
+            model.append(cap);
+        }
+    }
+    model
+}
+
+pub(super) fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> ModelData {
+    surface(segments, sides, |u, v| {
+        let (phi, theta) = (2.0 * PI * u, 2.0 * PI * v);
+        let outward = Vector3::new(phi.sin(), 0.0, phi.cos());
+        let normal = outward * theta.cos() + Vector3::unit_y() * theta.sin();
+        (outward * major_radius + normal * minor_radius, normal)
+    })
+}
+
+/// Models in the order documented on the description
+pub(super) fn cornell_box(subdivisions: u32) -> Vec<ModelData> {
+    let wall = |corner: [f32; 3], u: [f32; 3], v: [f32; 3]| {
+        grid(corner.into(), u.into(), v.into(), subdivisions)
+    };
+    let mut white = wall([-1.0, 0.0, -1.0], [0.0, 0.0, 2.0], [2.0, 0.0, 0.0]);
+    white.append(wall([-1.0, 2.0, -1.0], [2.0, 0.0, 0.0], [0.0, 0.0, 2.0]));
+    white.append(wall([-1.0, 0.0, -1.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]));
+    let red = wall([-1.0, 0.0, -1.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]);
+    let green = wall([1.0, 0.0, -1.0], [0.0, 0.0, 2.0], [0.0, 2.0, 0.0]);
+    let block = |size: [f32; 3], angle: f32, center: [f32; 3]| {
+        let matrix = Matrix4::from_translation(center.into())
+            * Matrix4::from_angle_y(Deg(angle))
+            * Matrix4::from_nonuniform_scale(0.5 * size[0], 0.5 * size[1], 0.5 * size[2]);
+        cube(1).transform(matrix)
+    };
+    let short = block([0.6, 0.6, 0.6], -18.0, [0.33, 0.3, 0.29]);
+    let tall = block([0.6, 1.2, 0.6], 18.0, [-0.33, 0.6, -0.29]);
+    vec![white, red, green, short, tall]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(data: &[f32], index: u32) -> Vector3<f32> {
+        let i = index as usize * 3;
+        Vector3::new(data[i], data[i + 1], data[i + 2])
+    }
+
+    /// Every triangle should be counter-clockwise seen from the side its normals are at
+    fn assert_outward(model: &ModelData) {
+        assert_eq!(model.positions.len(), model.normals.len());
+        for triangle in model.indices.chunks_exact(3) {
+            let p: Vec<_> = triangle
+                .iter()
+                .map(|&i| vertex(&model.positions, i))
+                .collect();
+            let face_normal = (p[1] - p[0]).cross(p[2] - p[0]);
+            assert!(face_normal.magnitude() > 0.0, "degenerate triangle");
+            for &i in triangle {
+                assert!(face_normal.dot(vertex(&model.normals, i)) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_meshes_face_outward() {
+        let unit_cube = cube(1);
+        assert_eq!(unit_cube.indices.len(), 36);
+        assert_outward(&unit_cube);
+        assert_eq!(cube(3).indices.len(), 6 * 9 * 6);
+
+        let plane = plane(2);
+        assert_eq!(plane.indices.len(), 4 * 6);
+        assert_outward(&plane);
+        assert!((0..9).all(|i| vertex(&plane.normals, i) == Vector3::unit_y()));
+
+        let sphere = uv_sphere(8, 4);
+        // a single triangle per segment at each pole
+        assert_eq!(sphere.indices.len(), (8 * 2 * 4 - 2 * 8) * 3);
+        assert_outward(&sphere);
+        assert_outward(&cylinder(8, 2, true));
+        assert_outward(&torus(1.0, 0.25, 8, 6));
+        for model in cornell_box(2) {
+            assert_outward(&model);
+        }
+    }
+
+    #[test]
+    fn cornell_box_faces_inward() {
+        let models = cornell_box(1);
+        assert_eq!(models.len(), 5);
+        // the walls face the center of the box
+        for model in &models[..3] {
+            for i in 0..model.positions.len() as u32 / 3 {
+                let to_center = Vector3::new(0.0, 1.0, 0.0) - vertex(&model.positions, i);
+                assert!(to_center.dot(vertex(&model.normals, i)) > 0.0);
+            }
+        }
+        // the blocks stand on the floor
+        for model in &models[3..] {
+            let bottom = model
+                .positions
+                .chunks_exact(3)
+                .map(|p| p[1])
+                .fold(f32::MAX, f32::min);
+            assert!(bottom.abs() < 1e-6);
+        }
+    }
+}
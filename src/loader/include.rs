@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use super::{
+    description::read_json, IncludeDescription, LightDescription, MaterialDescription,
+    MeshDescription, ObjectDescription, SceneDescription,
+};
+
+/// Materials, meshes, objects and lights of a file together with the ones it includes
+#[derive(Default)]
+struct Entries {
+    materials: Vec<MaterialDescription>,
+    meshes: Vec<MeshDescription>,
+    objects: Vec<ObjectDescription>,
+    lights: Vec<LightDescription>,
+}
+
+impl Entries {
+    /// Indices of `other` objects are shifted when they are relative to `other` itself
+    fn append(&mut self, other: Entries, shift_indices: bool) {
+        let (material_offset, mesh_offset) = if shift_indices {
+            (self.materials.len() as u32, self.meshes.len() as u32)
+        } else {
+            (0, 0)
+        };
+        self.materials.extend(other.materials);
+        self.meshes.extend(other.meshes);
+        self.objects
+            .extend(other.objects.into_iter().map(|mut object| {
+                object.material += material_offset;
+                if let Some(mesh) = &mut object.mesh {
+                    mesh[0] += mesh_offset;
+                }
+                object
+            }));
+        self.lights.extend(other.lights);
+    }
+}
+
+/// Finds files named in a scene, paths are relative to the directory of the scene file
+struct Resolver {
+    root_dir: PathBuf,
+    search_paths: Vec<PathBuf>,
+    /// files being included, to find include cycles
+    stack: Vec<PathBuf>,
+}
+
+/// Returns `description` with the entries of its included files and with mesh files
+/// relative to `path`, files are looked for next to the file naming them, then in
+/// `search_paths` of the scene and in `extra_search_paths`
+pub fn resolve<P: AsRef<Path>>(
+    path: P,
+    description: &SceneDescription,
+    extra_search_paths: &[PathBuf],
+) -> Result<SceneDescription> {
+    let path = path.as_ref();
+    let mut resolver = Resolver {
+        root_dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        search_paths: description
+            .search_paths
+            .iter()
+            .map(PathBuf::from)
+            .chain(extra_search_paths.iter().cloned())
+            .collect(),
+        stack: vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())],
+    };
+    let own = Entries {
+        materials: description.materials.clone(),
+        meshes: description.meshes.clone(),
+        objects: description.objects.clone(),
+        lights: description.lights.clone(),
+    };
+    let entries = resolver.merge(Path::new(""), &description.include, own)?;
+
+    Ok(SceneDescription {
+        include: vec![],
+        materials: entries.materials,
+        meshes: entries.meshes,
+        objects: entries.objects,
+        lights: entries.lights,
+        ..description.clone()
+    })
+}
+
+impl Resolver {
+    /// `dir` is the directory of the file relative to the scene file
+    fn merge(&mut self, dir: &Path, include: &[String], own: Entries) -> Result<Entries> {
+        let mut entries = Entries::default();
+        for (index, file) in include.iter().enumerate() {
+            let included = self
+                .include(dir, file)
+                .with_context(|| format!("include[{}]: failed to include '{}'", index, file))?;
+            entries.append(included, true);
+        }
+
+        let mut own = own;
+        for (index, mesh) in own.meshes.iter_mut().enumerate() {
+            if let Some(file) = mesh.file_mut() {
+                *file = self
+                    .find(dir, file)
+                    .with_context(|| format!("meshes[{}]", index))?;
+            }
+        }
+        entries.append(own, false);
+        Ok(entries)
+    }
+
+    fn include(&mut self, dir: &Path, file: &str) -> Result<Entries> {
+        let file = self.find(dir, file)?;
+        let path = self.root_dir.join(&file);
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.stack.contains(&key) {
+            bail!("'{}' includes itself", path.display());
+        }
+
+        let description: IncludeDescription = read_json(&path)?;
+        self.stack.push(key);
+        let own = Entries {
+            materials: description.materials,
+            meshes: description.meshes,
+            objects: description.objects,
+            lights: description.lights,
+        };
+        let dir = Path::new(&file).parent().unwrap_or(Path::new(""));
+        let entries = self.merge(dir, &description.include, own);
+        self.stack.pop();
+        entries
+    }
+
+    /// Path of `file` relative to the scene file
+    fn find(&self, dir: &Path, file: &str) -> Result<String> {
+        let candidates: Vec<_> = std::iter::once(dir.join(file))
+            .chain(
+                self.search_paths
+                    .iter()
+                    .map(|search_path| search_path.join(file)),
+            )
+            .collect();
+        for candidate in &candidates {
+            if self.root_dir.join(candidate).is_file() {
+                return Ok(candidate.to_string_lossy().into_owned());
+            }
+        }
+
+        let looked_in: Vec<_> = candidates
+            .iter()
+            .map(|candidate| format!("'{}'", self.root_dir.join(candidate).display()))
+            .collect();
+        bail!(
+            "'{}' isn't found, looked for {}",
+            file,
+            looked_in.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` to a new temporary directory
+    fn scene_dir(files: &[(&str, serde_json::Value)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spt-include-{}", uuid::Uuid::new_v4()));
+        for (name, value) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let content = match value {
+                serde_json::Value::String(content) => content.clone(),
+                value => value.to_string(),
+            };
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn scene(extra: serde_json::Value) -> SceneDescription {
+        let mut scene = serde_json::json!({
+            "camera": { "eye": [0.0, 0.0, 5.0], "forward": [0.0, 0.0, -1.0], "up": [0.0, 1.0, 0.0], "fov": 45.0 },
+            "max_depth": 4,
+            "output": { "width": 8, "height": 8 }
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            scene[key] = value.clone();
+        }
+        serde_json::from_value(scene).unwrap()
+    }
+
+    fn material(gray: f32) -> serde_json::Value {
+        serde_json::json!({ "albedo": [gray, gray, gray] })
+    }
+
+    #[test]
+    fn included_entries_come_first() {
+        let dir = scene_dir(&[
+            (
+                "lib/materials.json",
+                serde_json::json!({ "materials": [material(0.1), material(0.2)] }),
+            ),
+            (
+                "lib/props.json",
+                serde_json::json!({
+                    "include": ["materials.json"],
+                    "materials": [material(0.3)],
+                    "meshes": ["models/prop.obj"],
+                    "objects": [{ "mesh": [0, 0], "material": 2 }]
+                }),
+            ),
+            ("lib/models/prop.obj", "".into()),
+            ("models/floor.obj", "".into()),
+        ]);
+        let description = scene(serde_json::json!({
+            "include": ["lib/props.json"],
+            "materials": [material(0.4)],
+            "meshes": ["models/floor.obj"],
+            "objects": [{ "mesh": [1, 0], "material": 3 }]
+        }));
+
+        let resolved = resolve(dir.join("scene.json"), &description, &[]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let albedos: Vec<_> = resolved.materials.iter().map(|m| m.albedo[0]).collect();
+        assert_eq!(albedos, vec![0.1, 0.2, 0.3, 0.4]);
+        let file = |path: &str| MeshDescription::File(Path::new(path).to_string_lossy().into());
+        assert_eq!(
+            resolved.meshes,
+            vec![file("lib/models/prop.obj"), file("models/floor.obj")]
+        );
+        let objects: Vec<_> = resolved
+            .objects
+            .iter()
+            .map(|object| (object.mesh, object.material))
+            .collect();
+        assert_eq!(objects, vec![(Some([0, 0]), 2), (Some([1, 0]), 3)]);
+        assert!(resolved.include.is_empty());
+    }
+
+    #[test]
+    fn files_are_looked_for_in_search_paths() {
+        let dir = scene_dir(&[
+            (
+                "shared/materials.json",
+                serde_json::json!({ "materials": [material(0.5)] }),
+            ),
+            ("assets/cube.obj", "".into()),
+        ]);
+        let description = scene(serde_json::json!({
+            "search_paths": ["shared"],
+            "include": ["materials.json"],
+            "meshes": ["cube.obj"]
+        }));
+
+        let resolved =
+            resolve(dir.join("scene.json"), &description, &[dir.join("assets")]).unwrap();
+        assert_eq!(resolved.materials.len(), 1);
+        let cube = dir.join("assets/cube.obj").to_string_lossy().into_owned();
+        assert_eq!(resolved.meshes[0], MeshDescription::File(cube));
+
+        let err = resolve(dir.join("scene.json"), &description, &[]).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let message = format!("{:#}", err);
+        assert!(
+            message.starts_with("meshes[0]: 'cube.obj' isn't found, looked for"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = scene_dir(&[
+            ("a.json", serde_json::json!({ "include": ["b.json"] })),
+            ("b.json", serde_json::json!({ "include": ["a.json"] })),
+        ]);
+        let description = scene(serde_json::json!({ "include": ["a.json"] }));
+        let err = resolve(dir.join("scene.json"), &description, &[]).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            format!("{:#}", err).contains("includes itself"),
+            "{:#}",
+            err
+        );
+    }
+}
@@ -0,0 +1,1112 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use xml::{
+    common::Position,
+    reader::{EventReader, XmlEvent},
+};
+
+use super::{
+    conductor_reflectance, environment_average, BvhDescription, CameraDescription,
+    FilterDescription, InlineMeshDescription, LightDescription, MaterialDescription,
+    MeshDescription, ObjectDescription, OutputDescription, SceneDescription, ShadedMeshDescription,
+    ShapeDescription, TransformDescription, METALS,
+};
+
+/// Named IORs of Mitsuba dielectrics
+const IORS: [(&str, f32); 23] = [
+    ("vacuum", 1.0),
+    ("helium", 1.000036),
+    ("hydrogen", 1.000132),
+    ("air", 1.000277),
+    ("carbon dioxide", 1.00045),
+    ("water", 1.333),
+    ("acetone", 1.36),
+    ("ethanol", 1.361),
+    ("carbon tetrachloride", 1.461),
+    ("glycerol", 1.4729),
+    ("benzene", 1.501),
+    ("silicone oil", 1.52045),
+    ("bromine", 1.661),
+    ("water ice", 1.31),
+    ("fused quartz", 1.458),
+    ("pyrex", 1.47),
+    ("acrylic glass", 1.49),
+    ("polypropylene", 1.49),
+    ("bk7", 1.5046),
+    ("sodium chloride", 1.544),
+    ("amber", 1.55),
+    ("pet", 1.575),
+    ("diamond", 2.419),
+];
+
+/// `max_depth` used for the unlimited depth of Mitsuba (-1)
+const UNLIMITED_DEPTH: u32 = 16;
+
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    line: usize,
+}
+
+/// Builds the element tree, replacing `$name` in attributes with the values of `<default>`
+/// and `<include>` with the elements of the included file
+struct Parser {
+    dir: PathBuf,
+    defaults: HashMap<String, String>,
+    /// files being included, to find include cycles
+    stack: Vec<PathBuf>,
+}
+
+struct Converter {
+    /// directory of the scene file, environment maps are relative to it
+    dir: PathBuf,
+    named_materials: HashMap<String, MaterialDescription>,
+    camera: Option<CameraDescription>,
+    width: u32,
+    height: u32,
+    filter: Option<FilterDescription>,
+    sampler: Option<String>,
+    max_depth: u32,
+    materials: Vec<MaterialDescription>,
+    meshes: Vec<MeshDescription>,
+    objects: Vec<ObjectDescription>,
+    lights: Vec<LightDescription>,
+    /// sum of the `constant` and `envmap` emitters
+    environment: Option<[f32; 3]>,
+    warnings: BTreeSet<String>,
+}
+
+/// Converts a Mitsuba 3 scene file, things that can't be rendered are printed as warnings
+pub(super) fn load_mitsuba(path: &Path) -> Result<SceneDescription> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("scene: failed to open '{}'", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let (description, warnings) = convert(&text, dir)
+        .with_context(|| format!("scene: failed to parse '{}'", path.display()))?;
+    for warning in warnings {
+        println!("WARNING: {} in '{}'", warning, path.display());
+    }
+    Ok(description)
+}
+
+fn convert(text: &str, dir: PathBuf) -> Result<(SceneDescription, BTreeSet<String>)> {
+    let mut parser = Parser {
+        dir: dir.clone(),
+        defaults: HashMap::new(),
+        stack: vec![],
+    };
+    let scene = parser.parse(text)?;
+    if scene.name != "scene" {
+        bail!("the root element should be 'scene'");
+    }
+    let mut converter = Converter::new(dir);
+    converter.run(&scene)?;
+    Ok(converter.finish())
+}
+
+fn default_material() -> MaterialDescription {
+    MaterialDescription {
+        albedo: [0.5, 0.5, 0.5],
+        ior: 1.5,
+        roughness: 1.0,
+        metallic: 0.0,
+        is_translucent: false,
+        emission: [0.0; 3],
+    }
+}
+
+impl Parser {
+    fn parse(&mut self, text: &str) -> Result<Element> {
+        let mut reader = EventReader::from_str(text);
+        let mut stack: Vec<Element> = vec![];
+        loop {
+            let event = reader.next()?;
+            let line = reader.position().row as usize + 1;
+            match event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let mut element = Element {
+                        name: name.local_name,
+                        attributes: HashMap::new(),
+                        children: vec![],
+                        line,
+                    };
+                    for attribute in attributes {
+                        let value = self
+                            .substitute(&attribute.value)
+                            .with_context(|| format!("line {}: '{}'", line, element.name))?;
+                        element.attributes.insert(attribute.name.local_name, value);
+                    }
+                    if element.name == "default" {
+                        let name = element.attribute("name")?.to_string();
+                        let value = element.attribute("value")?.to_string();
+                        self.defaults.entry(name).or_insert(value);
+                    }
+                    stack.push(element);
+                }
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().context("unmatched end tag")?;
+                    match stack.last_mut() {
+                        None => return Ok(element),
+                        Some(parent) if element.name == "include" => {
+                            let children = self.include(&element).with_context(|| {
+                                format!("line {}: '{}'", element.line, element.name)
+                            })?;
+                            parent.children.extend(children);
+                        }
+                        Some(parent) => parent.children.push(element),
+                    }
+                }
+                XmlEvent::EndDocument => bail!("no root element"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replaces `$name` with the value of the default named `name`
+    fn substitute(&self, value: &str) -> Result<String> {
+        let mut result = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find('$') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            let value = self
+                .defaults
+                .get(name)
+                .with_context(|| format!("'${}' has no default", name))?;
+            result.push_str(value);
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    /// Elements in the `scene` of the included file
+    fn include(&mut self, element: &Element) -> Result<Vec<Element>> {
+        let path = self.dir.join(element.attribute("filename")?);
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.stack.contains(&key) {
+            bail!("'{}' includes itself", path.display());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to open '{}'", path.display()))?;
+        self.stack.push(key);
+        let scene = self
+            .parse(&text)
+            .with_context(|| format!("in '{}'", path.display()));
+        self.stack.pop();
+        let scene = scene?;
+        if scene.name != "scene" {
+            bail!("the root element of '{}' should be 'scene'", path.display());
+        }
+        Ok(scene.children)
+    }
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Result<&str> {
+        self.attributes
+            .get(name)
+            .map(String::as_str)
+            .with_context(|| format!("'{}' is needed", name))
+    }
+
+    /// Child element with the `name` attribute `name`
+    fn property(&self, name: &str) -> Option<&Element> {
+        self.children
+            .iter()
+            .find(|child| child.attributes.get("name").map(String::as_str) == Some(name))
+    }
+
+    fn child(&self, tag: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == tag)
+    }
+
+    fn float(&self, name: &str) -> Result<Option<f32>> {
+        match self.property(name) {
+            Some(property) if property.name == "float" || property.name == "integer" => {
+                let value = property.attribute("value")?;
+                let value = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("'{}': '{}' should be a number", name, value))?;
+                Ok(Some(value))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn boolean(&self, name: &str) -> Result<Option<bool>> {
+        match self.property(name) {
+            Some(property) if property.name == "boolean" => {
+                match property.attribute("value")?.trim() {
+                    "true" => Ok(Some(true)),
+                    "false" => Ok(Some(false)),
+                    value => bail!("'{}': '{}' should be true or false", name, value),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.property(name) {
+            Some(property) if property.name == "string" => {
+                property.attributes.get("value").map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+
+    /// `point` or `vector` given as `value` or as `x`, `y` and `z`
+    fn vector(&self, name: &str) -> Result<Option<[f32; 3]>> {
+        match self.property(name) {
+            Some(property) if property.name == "point" || property.name == "vector" => {
+                property.xyz(0.0).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// 3 numbers of `value`, or one number for all of them, or `x`, `y` and `z` defaulting to
+    /// `default`
+    fn xyz(&self, default: f32) -> Result<[f32; 3]> {
+        if let Some(value) = self.attributes.get("value") {
+            return vector3(value);
+        }
+        let mut v = [default; 3];
+        for (v, name) in v.iter_mut().zip(["x", "y", "z"]) {
+            if let Some(value) = self.attributes.get(name) {
+                *v = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("'{}': '{}' should be a number", name, value))?;
+            }
+        }
+        Ok(v)
+    }
+
+    /// The `to_world` transform, identity if there is none
+    fn to_world(&self) -> Result<Matrix4<f32>> {
+        let transform = match self.property("to_world") {
+            Some(transform) if transform.name == "transform" => transform,
+            _ => return Ok(Matrix4::identity()),
+        };
+        let mut matrix = Matrix4::identity();
+        for op in &transform.children {
+            let op_matrix = match op.name.as_str() {
+                "translate" => Matrix4::from_translation(op.xyz(0.0)?.into()),
+                "scale" => {
+                    let v = op.xyz(1.0)?;
+                    Matrix4::from_nonuniform_scale(v[0], v[1], v[2])
+                }
+                "rotate" => {
+                    let axis = Vector3::from(op.xyz(0.0)?);
+                    if axis == Vector3::new(0.0, 0.0, 0.0) {
+                        bail!("'rotate': the axis should not be zero");
+                    }
+                    let angle: f32 = op
+                        .attribute("angle")?
+                        .trim()
+                        .parse()
+                        .context("'rotate': 'angle' should be a number")?;
+                    Matrix4::from_axis_angle(axis.normalize(), Deg(angle))
+                }
+                "matrix" => {
+                    let m = numbers(op.attribute("value")?)?;
+                    match m.len() {
+                        // row major
+                        16 => Matrix4::new(
+                            m[0], m[4], m[8], m[12], m[1], m[5], m[9], m[13], m[2], m[6], m[10],
+                            m[14], m[3], m[7], m[11], m[15],
+                        ),
+                        9 => Matrix4::new(
+                            m[0], m[3], m[6], 0.0, m[1], m[4], m[7], 0.0, m[2], m[5], m[8], 0.0,
+                            0.0, 0.0, 0.0, 1.0,
+                        ),
+                        _ => bail!("'matrix': 9 or 16 numbers are needed"),
+                    }
+                }
+                "lookat" => {
+                    let origin = Vector3::from(vector3(op.attribute("origin")?)?);
+                    let target = Vector3::from(vector3(op.attribute("target")?)?);
+                    let up = match op.attributes.get("up") {
+                        Some(up) => Vector3::from(vector3(up)?),
+                        None => Vector3::unit_y(),
+                    };
+                    let dir = (target - origin).normalize();
+                    let left = up.cross(dir);
+                    if left == Vector3::new(0.0, 0.0, 0.0) {
+                        bail!("'lookat': 'up' should not be along the view direction");
+                    }
+                    let left = left.normalize();
+                    let up = dir.cross(left);
+                    Matrix4::from_cols(
+                        left.extend(0.0),
+                        up.extend(0.0),
+                        dir.extend(0.0),
+                        origin.extend(1.0),
+                    )
+                }
+                _ => bail!("unknown transform '{}'", op.name),
+            };
+            matrix = op_matrix * matrix;
+        }
+        Ok(matrix)
+    }
+}
+
+/// Numbers separated by commas or spaces
+fn numbers(text: &str) -> Result<Vec<f32>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.parse()
+                .with_context(|| format!("'{}' should be a number", word))
+        })
+        .collect()
+}
+
+fn vector3(text: &str) -> Result<[f32; 3]> {
+    match *numbers(text)?.as_slice() {
+        [x, y, z] => Ok([x, y, z]),
+        [x] => Ok([x; 3]),
+        _ => bail!("'{}' should be 3 numbers", text),
+    }
+}
+
+impl Converter {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            named_materials: HashMap::new(),
+            camera: None,
+            width: 768,
+            height: 576,
+            filter: None,
+            sampler: None,
+            max_depth: UNLIMITED_DEPTH,
+            materials: vec![],
+            meshes: vec![],
+            objects: vec![],
+            lights: vec![],
+            environment: None,
+            warnings: BTreeSet::new(),
+        }
+    }
+
+    fn run(&mut self, scene: &Element) -> Result<()> {
+        for element in &scene.children {
+            self.element(element)
+                .with_context(|| format!("line {}: '{}'", element.line, element.name))?;
+        }
+        Ok(())
+    }
+
+    fn element(&mut self, element: &Element) -> Result<()> {
+        match element.name.as_str() {
+            "sensor" => self.sensor(element)?,
+            "integrator" => self.integrator(element)?,
+            "bsdf" => {
+                let id = element.attribute("id")?.to_string();
+                let material = self.bsdf(element)?;
+                self.named_materials.insert(id, material);
+            }
+            "shape" => self.shape(element)?,
+            "emitter" => self.emitter(element)?,
+            "texture" => self.warn("textures are ignored".to_string()),
+            "medium" | "phase" => self.warn(format!("'{}' elements are ignored", element.name)),
+            "default" => {}
+            _ => bail!("unknown element"),
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, warning: String) {
+        self.warnings.insert(warning);
+    }
+
+    /// RGB of a color property, textures aren't supported
+    fn rgb(&mut self, element: &Element, name: &str) -> Result<Option<[f32; 3]>> {
+        let property = match element.property(name) {
+            Some(property) => property,
+            None => return Ok(None),
+        };
+        match property.name.as_str() {
+            "rgb" | "float" => vector3(property.attribute("value")?).map(Some),
+            "spectrum" if property.attributes.contains_key("filename") => {
+                self.warn(format!("spectrum file of '{}' is taken as white", name));
+                Ok(Some([1.0; 3]))
+            }
+            "spectrum" => {
+                let value = property.attribute("value")?;
+                if !value.contains(':') {
+                    return vector3(value).map(Some);
+                }
+                // wavelength and value pairs
+                let mut sum = 0.0;
+                let mut count = 0;
+                for pair in value.split(',') {
+                    let (_, value) = pair
+                        .split_once(':')
+                        .with_context(|| format!("'{}' should be 'wavelength:value'", pair))?;
+                    sum += value
+                        .trim()
+                        .parse::<f32>()
+                        .with_context(|| format!("'{}' should be a number", value))?;
+                    count += 1;
+                }
+                Ok(Some([sum / count as f32; 3]))
+            }
+            _ => {
+                self.warn(format!("textured '{}' uses the default value", name));
+                Ok(None)
+            }
+        }
+    }
+
+    fn sensor(&mut self, element: &Element) -> Result<()> {
+        if self.camera.is_some() {
+            self.warn("only the first sensor is used".to_string());
+            return Ok(());
+        }
+        let ty = element.attribute("type")?;
+        if ty != "perspective" {
+            self.warn(format!("'{}' sensor is rendered as 'perspective'", ty));
+        }
+
+        if let Some(film) = element.child("film") {
+            self.width = film.float("width")?.unwrap_or(768.0) as u32;
+            self.height = film.float("height")?.unwrap_or(576.0) as u32;
+            if let Some(filter) = film.child("rfilter") {
+                self.filter = self.filter(filter)?;
+            }
+        }
+        if let Some(sampler) = element.child("sampler") {
+            self.sampler = match sampler.attribute("type")? {
+                "independent" => Some("pcg".to_string()),
+                "stratified" | "multijitter" | "orthogonal" | "ldsampler" => {
+                    Some("sobol".to_string())
+                }
+                _ => None,
+            };
+        }
+
+        let to_world = element.to_world()?;
+        if to_world.determinant() < 0.0 {
+            self.warn("mirrored sensor is rendered without mirroring".to_string());
+        }
+        let eye = to_world.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let forward = to_world.transform_vector(Vector3::unit_z()).normalize();
+        let up = to_world.transform_vector(Vector3::unit_y()).normalize();
+        let fov = self.vertical_fov(element)?;
+        self.camera = Some(CameraDescription {
+            eye: eye.into(),
+            forward: forward.into(),
+            up: up.into(),
+            fov,
+        });
+        Ok(())
+    }
+
+    /// Vertical field of view of `fov` along `fov_axis`, or of `focal_length` of 35mm film
+    fn vertical_fov(&mut self, sensor: &Element) -> Result<f32> {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let (fov, axis) = match sensor.float("fov")? {
+            Some(fov) => (fov, sensor.string("fov_axis").unwrap_or("x")),
+            None => {
+                let focal_length = sensor.string("focal_length").unwrap_or("50mm");
+                let focal_length: f32 = focal_length
+                    .trim_end_matches("mm")
+                    .parse()
+                    .with_context(|| format!("'{}' should be like '50mm'", focal_length))?;
+                let diagonal = (36.0f32 * 36.0 + 24.0 * 24.0).sqrt();
+                let fov = 2.0 * (diagonal / (2.0 * focal_length)).atan().to_degrees();
+                (fov, "diagonal")
+            }
+        };
+        let axis_length = match axis {
+            "x" => width,
+            "y" => height,
+            "diagonal" => (width * width + height * height).sqrt(),
+            "smaller" => width.min(height),
+            "larger" => width.max(height),
+            _ => bail!("unknown fov axis '{}'", axis),
+        };
+        let tan = (fov.to_radians() * 0.5).tan() * height / axis_length;
+        Ok(2.0 * tan.atan().to_degrees())
+    }
+
+    fn filter(&mut self, filter: &Element) -> Result<Option<FilterDescription>> {
+        let ty = filter.attribute("type")?;
+        let (name, radius, sigma, b, c) = match ty {
+            "box" => ("box", None, None, None, None),
+            "tent" => ("tent", filter.float("radius")?, None, None, None),
+            "gaussian" => ("gaussian", None, filter.float("stddev")?, None, None),
+            "mitchell" => (
+                "mitchell",
+                None,
+                None,
+                filter.float("B")?,
+                filter.float("C")?,
+            ),
+            "catmullrom" => ("mitchell", None, None, Some(0.0), Some(0.5)),
+            _ => {
+                self.warn(format!("'{}' filter is ignored", ty));
+                return Ok(None);
+            }
+        };
+        Ok(Some(FilterDescription::Params {
+            ty: name.to_string(),
+            radius,
+            sigma,
+            b,
+            c,
+        }))
+    }
+
+    fn integrator(&mut self, element: &Element) -> Result<()> {
+        // integrators like 'aov' wrap the one that renders
+        if let Some(nested) = element.child("integrator") {
+            return self.integrator(nested);
+        }
+        let max_depth = match element.attribute("type")? {
+            "direct" => 2.0,
+            _ => element.float("max_depth")?.unwrap_or(-1.0),
+        };
+        // depth 1 of Mitsuba sees emitters only, 2 is direct lighting
+        self.max_depth = if max_depth < 0.0 {
+            UNLIMITED_DEPTH
+        } else {
+            (max_depth as u32).saturating_sub(1).max(1)
+        };
+        Ok(())
+    }
+
+    fn bsdf(&mut self, element: &Element) -> Result<MaterialDescription> {
+        let ty = element.attribute("type")?;
+        let rough = ty.starts_with("rough");
+        Ok(match ty {
+            "diffuse" => MaterialDescription {
+                albedo: self.rgb(element, "reflectance")?.unwrap_or([0.5; 3]),
+                ..default_material()
+            },
+            "conductor" | "roughconductor" => MaterialDescription {
+                albedo: self.conductor_reflectance(element)?,
+                roughness: roughness(element, rough)?,
+                metallic: 1.0,
+                ..default_material()
+            },
+            "dielectric" | "thindielectric" | "roughdielectric" => MaterialDescription {
+                albedo: [1.0; 3],
+                ior: relative_ior(element)?,
+                roughness: roughness(element, rough)?,
+                metallic: 0.0,
+                is_translucent: true,
+                ..default_material()
+            },
+            "plastic" | "roughplastic" => MaterialDescription {
+                albedo: self
+                    .rgb(element, "diffuse_reflectance")?
+                    .unwrap_or([0.5; 3]),
+                ior: relative_ior(element)?,
+                roughness: roughness(element, rough)?,
+                ..default_material()
+            },
+            "principled" => MaterialDescription {
+                albedo: self.rgb(element, "base_color")?.unwrap_or([0.5; 3]),
+                ior: element.float("eta")?.unwrap_or(1.5),
+                // already perceptual
+                roughness: element.float("roughness")?.unwrap_or(0.5),
+                metallic: element.float("metallic")?.unwrap_or(0.0),
+                is_translucent: element.float("spec_trans")?.unwrap_or(0.0) > 0.5,
+                ..default_material()
+            },
+            "twosided" => self.nested_bsdf(element)?,
+            "bumpmap" | "normalmap" | "mask" | "blendbsdf" => {
+                self.warn(format!("'{}' is ignored, its nested BSDF is used", ty));
+                self.nested_bsdf(element)?
+            }
+            _ => {
+                self.warn(format!("'{}' BSDF is rendered as gray 'diffuse'", ty));
+                default_material()
+            }
+        })
+    }
+
+    /// First BSDF in `element`, given in place or as a reference
+    fn nested_bsdf(&mut self, element: &Element) -> Result<MaterialDescription> {
+        let nested = element
+            .children
+            .iter()
+            .find(|child| child.name == "bsdf" || child.name == "ref")
+            .context("a nested BSDF is needed")?;
+        self.bsdf_or_ref(nested)
+    }
+
+    fn bsdf_or_ref(&mut self, element: &Element) -> Result<MaterialDescription> {
+        if element.name == "ref" {
+            let id = element.attribute("id")?;
+            self.named_materials
+                .get(id)
+                .cloned()
+                .with_context(|| format!("unknown BSDF '{}'", id))
+        } else {
+            self.bsdf(element)
+                .with_context(|| format!("line {}: 'bsdf'", element.line))
+        }
+    }
+
+    fn conductor_reflectance(&mut self, element: &Element) -> Result<[f32; 3]> {
+        let reflectance = match (element.property("eta"), element.string("material")) {
+            (Some(_), _) => {
+                let eta = self.rgb(element, "eta")?.unwrap_or([0.0; 3]);
+                let k = self.rgb(element, "k")?.unwrap_or([0.0; 3]);
+                conductor_reflectance(eta, k)
+            }
+            // a perfect mirror
+            (None, None) | (None, Some("none")) => [1.0; 3],
+            (None, Some(name)) => match METALS.iter().find(|(metal, _)| *metal == name) {
+                Some((_, reflectance)) => *reflectance,
+                None => {
+                    self.warn(format!("conductor '{}' is rendered as copper", name));
+                    METALS[3].1
+                }
+            },
+        };
+        let specular = self
+            .rgb(element, "specular_reflectance")?
+            .unwrap_or([1.0; 3]);
+        let mut albedo = reflectance;
+        for (albedo, specular) in albedo.iter_mut().zip(specular) {
+            *albedo *= specular;
+        }
+        Ok(albedo)
+    }
+
+    fn shape(&mut self, element: &Element) -> Result<()> {
+        let ty = element.attribute("type")?;
+        let mut transform = element.to_world()?;
+        let (mesh, shape) = match ty {
+            "obj" | "ply" => {
+                let file = element
+                    .string("filename")
+                    .context("'filename' is needed")?
+                    .to_string();
+                let mut mesh = MeshDescription::File(file);
+                if element.boolean("face_normals")?.unwrap_or(false) {
+                    mesh = MeshDescription::Shaded(ShadedMeshDescription {
+                        mesh: Box::new(mesh),
+                        shading: Some("flat".to_string()),
+                        crease_angle: None,
+                    });
+                }
+                (Some([self.mesh(mesh), 0]), None)
+            }
+            "cube" => {
+                let cube = InlineMeshDescription::Cube { subdivisions: 1 };
+                (Some([self.mesh(MeshDescription::Inline(cube)), 0]), None)
+            }
+            "rectangle" => (None, Some(ShapeDescription::Quad { size: [2.0, 2.0] })),
+            "disk" => (None, Some(ShapeDescription::Disc { radius: 1.0 })),
+            "sphere" => {
+                let center = element.vector("center")?.unwrap_or([0.0; 3]);
+                let radius = element.float("radius")?.unwrap_or(1.0);
+                transform = transform * Matrix4::from_translation(center.into());
+                (None, Some(ShapeDescription::Sphere { radius }))
+            }
+            _ => {
+                self.warn(format!("'{}' shapes are ignored", ty));
+                return Ok(());
+            }
+        };
+
+        let mut material = match element
+            .children
+            .iter()
+            .find(|child| child.name == "bsdf" || child.name == "ref")
+        {
+            Some(bsdf) => self.bsdf_or_ref(bsdf)?,
+            None => default_material(),
+        };
+        if let Some(emitter) = element.child("emitter") {
+            material.emission = self.area_emitter(emitter)?;
+            if element.boolean("flip_normals")?.unwrap_or(false) {
+                self.warn("'flip_normals' of area emitters is ignored".to_string());
+            }
+        }
+        let material = match self.materials.iter().position(|m| *m == material) {
+            Some(index) => index as u32,
+            None => {
+                self.materials.push(material);
+                self.materials.len() as u32 - 1
+            }
+        };
+
+        let matrix: [f32; 16] = *transform.as_ref();
+        self.objects.push(ObjectDescription {
+            transform: Some(TransformDescription {
+                matrix: Some(matrix),
+                ..Default::default()
+            }),
+            mesh,
+            shape,
+            material,
+        });
+        Ok(())
+    }
+
+    /// Index of `mesh` in `meshes`, shared by all shapes using the same one
+    fn mesh(&mut self, mesh: MeshDescription) -> u32 {
+        if let Some(index) = self.meshes.iter().position(|m| *m == mesh) {
+            return index as u32;
+        }
+        self.meshes.push(mesh);
+        self.meshes.len() as u32 - 1
+    }
+
+    /// Radiance of an emitter nested in a shape, the shape emits it on the side of its normals
+    fn area_emitter(&mut self, emitter: &Element) -> Result<[f32; 3]> {
+        let ty = emitter.attribute("type")?;
+        if ty != "area" {
+            self.warn(format!("'{}' emitters of shapes are ignored", ty));
+            return Ok([0.0; 3]);
+        }
+        Ok(self.rgb(emitter, "radiance")?.unwrap_or([1.0; 3]))
+    }
+
+    fn add_environment(&mut self, radiance: [f32; 3]) {
+        let environment = self.environment.get_or_insert([0.0; 3]);
+        for (env, radiance) in environment.iter_mut().zip(radiance) {
+            *env += radiance;
+        }
+    }
+
+    fn emitter(&mut self, element: &Element) -> Result<()> {
+        let ty = element.attribute("type")?;
+        let to_world = element.to_world()?;
+        match ty {
+            "point" => {
+                let position = match element.vector("position")? {
+                    Some(position) => position,
+                    None => to_world.transform_point(Point3::new(0.0, 0.0, 0.0)).into(),
+                };
+                let strength = self.rgb(element, "intensity")?.unwrap_or([1.0; 3]);
+                self.lights
+                    .push(LightDescription::Point { position, strength });
+            }
+            "directional" => {
+                let direction = match element.vector("direction")? {
+                    Some(direction) => Vector3::from(direction),
+                    None => to_world.transform_vector(Vector3::unit_z()),
+                };
+                if direction == Vector3::new(0.0, 0.0, 0.0) {
+                    self.warn("directional emitter without a direction is ignored".to_string());
+                    return Ok(());
+                }
+                let strength = self.rgb(element, "irradiance")?.unwrap_or([1.0; 3]);
+                self.lights.push(LightDescription::Directional {
+                    direction: direction.into(),
+                    strength,
+                });
+            }
+            "constant" => {
+                let radiance = self.rgb(element, "radiance")?.unwrap_or([1.0; 3]);
+                self.add_environment(radiance);
+            }
+            "envmap" => {
+                let file = element
+                    .string("filename")
+                    .context("'filename' is needed")?
+                    .to_string();
+                let scale = element.float("scale")?.unwrap_or(1.0);
+                match environment_average(&self.dir.join(&file), true) {
+                    Ok(average) => {
+                        self.warn(format!(
+                            "environment map '{}' is rendered with its mean radiance",
+                            file
+                        ));
+                        self.add_environment(average.map(|x| x * scale));
+                    }
+                    Err(err) => self.warn(format!("'envmap' emitter is ignored: {:#}", err)),
+                }
+            }
+            "area" => self.warn("'area' emitters outside of shapes are ignored".to_string()),
+            _ => self.warn(format!("'{}' emitters are ignored", ty)),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> (SceneDescription, BTreeSet<String>) {
+        let camera = self.camera.unwrap_or(CameraDescription {
+            eye: [0.0, 0.0, 0.0],
+            forward: [0.0, 0.0, 1.0],
+            up: [0.0, 1.0, 0.0],
+            fov: 30.0,
+        });
+        let description = SceneDescription {
+            include: vec![],
+            search_paths: vec![],
+            camera,
+            bvh: BvhDescription::default(),
+            max_depth: self.max_depth,
+            sampler: self.sampler,
+            output: OutputDescription {
+                file: None,
+                width: self.width,
+                height: self.height,
+                scale: None,
+                render_mode: None,
+                display: None,
+                filter: self.filter,
+                denoise: None,
+                adaptive: None,
+                lobes: None,
+                workgroup_size: None,
+                tile_size: None,
+                resize: None,
+            },
+            materials: self.materials,
+            meshes: self.meshes,
+            objects: self.objects,
+            lights: self.lights,
+            environment: self.environment,
+        };
+        (description, self.warnings)
+    }
+}
+
+/// Perceptual roughness of `alpha` (or the mean of `alpha_u` and `alpha_v`), which defaults
+/// to 0.1 for rough BSDFs
+fn roughness(element: &Element, rough: bool) -> Result<f32> {
+    if !rough {
+        return Ok(0.0);
+    }
+    let alpha = match element.float("alpha")? {
+        Some(alpha) => alpha,
+        None => match (element.float("alpha_u")?, element.float("alpha_v")?) {
+            (Some(u), Some(v)) => 0.5 * (u + v),
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => 0.1,
+        },
+    };
+    Ok(alpha.sqrt())
+}
+
+/// `int_ior` over `ext_ior`, each given as a number or a name
+fn relative_ior(element: &Element) -> Result<f32> {
+    let ior = |name: &str, default: f32| -> Result<f32> {
+        if let Some(ior) = element.float(name)? {
+            return Ok(ior);
+        }
+        match element.string(name) {
+            Some(material) => IORS
+                .iter()
+                .find(|(m, _)| *m == material)
+                .map(|(_, ior)| *ior)
+                .with_context(|| format!("'{}': unknown material '{}'", name, material)),
+            None => Ok(default),
+        }
+    };
+    Ok(ior("int_ior", 1.5046)? / ior("ext_ior", 1.000277)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: [f32; 3], b: [f32; 3]) {
+        let d = Vector3::from(a) - Vector3::from(b);
+        assert!(d.magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn converts_a_scene() {
+        let text = r#"
+            <scene version="3.0.0">
+                <default name="res" value="300"/>
+                <integrator type="path">
+                    <integer name="max_depth" value="6"/>
+                </integrator>
+                <sensor type="perspective">
+                    <float name="fov" value="45"/>
+                    <string name="fov_axis" value="y"/>
+                    <transform name="to_world">
+                        <lookat origin="0, 1, -5" target="0, 1, 0" up="0, 1, 0"/>
+                    </transform>
+                    <sampler type="independent"/>
+                    <film type="hdrfilm">
+                        <integer name="width" value="400"/>
+                        <integer name="height" value="$res"/>
+                        <rfilter type="gaussian"><float name="stddev" value="0.3"/></rfilter>
+                    </film>
+                </sensor>
+                <bsdf type="twosided" id="gold">
+                    <bsdf type="roughconductor">
+                        <string name="material" value="Au"/>
+                        <float name="alpha" value="0.04"/>
+                    </bsdf>
+                </bsdf>
+                <shape type="obj">
+                    <string name="filename" value="meshes/a.obj"/>
+                    <ref id="gold"/>
+                </shape>
+                <shape type="sphere">
+                    <point name="center" x="1" y="2" z="3"/>
+                    <float name="radius" value="0.5"/>
+                    <bsdf type="dielectric"><string name="int_ior" value="water"/></bsdf>
+                </shape>
+                <shape type="rectangle">
+                    <bsdf type="diffuse"><rgb name="reflectance" value="0.2, 0.4, 0.6"/></bsdf>
+                    <emitter type="area"><rgb name="radiance" value="1"/></emitter>
+                </shape>
+                <shape type="obj">
+                    <string name="filename" value="meshes/a.obj"/>
+                    <bsdf type="principled"><texture type="bitmap" name="base_color"/></bsdf>
+                </shape>
+                <emitter type="point">
+                    <point name="position" value="1, 2, 3"/>
+                    <rgb name="intensity" value="4"/>
+                </emitter>
+                <emitter type="directional">
+                    <vector name="direction" x="0" y="-1" z="0"/>
+                </emitter>
+                <emitter type="constant"><rgb name="radiance" value="0.5"/></emitter>
+                <emitter type="envmap"><string name="filename" value="missing.exr"/></emitter>
+            </scene>
+        "#;
+        let (desc, warnings) = convert(text, PathBuf::new()).unwrap();
+
+        assert_near(desc.camera.eye, [0.0, 1.0, -5.0]);
+        assert_near(desc.camera.forward, [0.0, 0.0, 1.0]);
+        assert_near(desc.camera.up, [0.0, 1.0, 0.0]);
+        assert!((desc.camera.fov - 45.0).abs() < 1e-4);
+        assert_eq!((desc.output.width, desc.output.height), (400, 300));
+        assert_eq!(desc.sampler.as_deref(), Some("pcg"));
+        assert_eq!(desc.max_depth, 5);
+
+        assert_eq!(desc.materials.len(), 4);
+        assert_eq!(desc.materials[2].emission, [1.0; 3]);
+        assert_eq!(desc.materials[3].emission, [0.0; 3]);
+        assert_eq!(desc.materials[0].albedo, METALS[2].1);
+        assert!((desc.materials[0].roughness - 0.2).abs() < 1e-6);
+        assert!(desc.materials[1].is_translucent);
+        assert!((desc.materials[1].ior - 1.333 / 1.000277).abs() < 1e-6);
+        assert_eq!(desc.materials[2].albedo, [0.2, 0.4, 0.6]);
+        assert_eq!(desc.materials[3].roughness, 0.5);
+
+        assert_eq!(
+            desc.meshes,
+            vec![MeshDescription::File("meshes/a.obj".to_string())]
+        );
+        let objects: Vec<_> = desc.objects.iter().map(|o| (o.mesh, o.material)).collect();
+        assert_eq!(
+            objects,
+            vec![(Some([0, 0]), 0), (None, 1), (None, 2), (Some([0, 0]), 3)]
+        );
+        assert_eq!(
+            desc.objects[1].shape,
+            Some(ShapeDescription::Sphere { radius: 0.5 })
+        );
+        assert_eq!(
+            desc.objects[2].shape,
+            Some(ShapeDescription::Quad { size: [2.0, 2.0] })
+        );
+        let matrix = desc.objects[1].transform.as_ref().unwrap().matrix.unwrap();
+        assert_eq!(matrix[12..15], [1.0, 2.0, 3.0]);
+
+        assert_eq!(
+            desc.lights,
+            vec![
+                LightDescription::Point {
+                    position: [1.0, 2.0, 3.0],
+                    strength: [4.0, 4.0, 4.0]
+                },
+                LightDescription::Directional {
+                    direction: [0.0, -1.0, 0.0],
+                    strength: [1.0, 1.0, 1.0]
+                }
+            ]
+        );
+
+        assert_eq!(desc.environment, Some([0.5; 3]));
+
+        assert!(warnings.contains("textured 'base_color' uses the default value"));
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.starts_with("'envmap' emitter is ignored")));
+    }
+
+    #[test]
+    fn environment_emitters_add_up() {
+        let dir = std::env::temp_dir().join(format!("spt-mitsuba-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::Rgb32FImage::from_pixel(8, 4, image::Rgb([2.0, 4.0, 6.0]))
+            .save(dir.join("sky.exr"))
+            .unwrap();
+        let text = r#"
+            <scene version="3.0.0">
+                <emitter type="envmap">
+                    <string name="filename" value="sky.exr"/>
+                    <float name="scale" value="0.5"/>
+                </emitter>
+                <emitter type="constant"><rgb name="radiance" value="0.1, 0.2, 0.3"/></emitter>
+            </scene>
+        "#;
+        let result = convert(text, dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (desc, warnings) = result.unwrap();
+        assert_near(desc.environment.unwrap(), [1.1, 2.2, 3.3]);
+        assert!(warnings.contains("environment map 'sky.exr' is rendered with its mean radiance"));
+    }
+
+    #[test]
+    fn transforms_apply_in_order() {
+        let text = r#"
+            <scene version="3.0.0">
+                <shape type="cube">
+                    <transform name="to_world">
+                        <scale value="2"/>
+                        <rotate y="1" angle="90"/>
+                        <translate x="1"/>
+                    </transform>
+                </shape>
+            </scene>
+        "#;
+        let (desc, _) = convert(text, PathBuf::new()).unwrap();
+        let matrix = desc.objects[0].transform.as_ref().unwrap().matrix.unwrap();
+        // the first column plus the translation
+        let p = [0, 1, 2].map(|i| matrix[i] + matrix[12 + i]);
+        assert_near(p, [1.0, 0.0, -2.0]);
+    }
+
+    #[test]
+    fn errors_tell_the_line() {
+        let err = convert("<scene>\n\n<foo/></scene>", PathBuf::new()).unwrap_err();
+        assert_eq!(format!("{:#}", err), "line 3: 'foo': unknown element");
+        let err = convert("<scene>\n<shape type=\"obj\"/>\n</scene>", PathBuf::new()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "line 2: 'shape': 'filename' is needed"
+        );
+        let err = convert(
+            "<scene><float name=\"x\" value=\"$x\"/></scene>",
+            PathBuf::new(),
+        )
+        .unwrap_err();
+        assert_eq!(format!("{:#}", err), "line 1: 'float': '$x' has no default");
+        assert!(convert("<scene><shape>", PathBuf::new()).is_err());
+    }
+}
@@ -1,26 +1,131 @@
+mod description;
+mod generate;
+mod include;
+mod mitsuba;
+mod normals;
+mod pbrt;
+mod ply;
+
+pub use description::*;
+pub use include::*;
+
 use std::{
     path::{Path, PathBuf},
     rc::Rc,
 };
 
 use anyhow::{bail, Context, Result};
-use bytemuck::Zeroable;
 use cgmath::{Matrix4, Point3, SquareMatrix, Vector3};
 
 use crate::{
-    core::{BvhAccel, Material, MeshVertex, Triangle, TriangleMesh},
-    renderer::{OutputConfig, Renderer},
-    uniforms,
+    core::{
+        AnalyticShape, BvhAccel, Light, Material, MeshVertex, SamplerKind, Scene, Triangle,
+        TriangleMesh,
+    },
+    output::{DisplayConfig, Lobe, PixelFilter, ToneMapper},
+    renderer::{
+        AdaptiveConfig, DenoiseConfig, OutputConfig, RenderMode, ResizeMode, WorkgroupSize,
+    },
+    uniforms::SceneUniform,
 };
 
+/// Reflectance at normal incidence of some metals, named like in pbrt-v4 spectra
+/// (`metal-Au-eta`) and in Mitsuba conductors, in linear sRGB
+const METALS: [(&str, [f32; 3]); 5] = [
+    ("Ag", [0.972, 0.960, 0.915]),
+    ("Al", [0.913, 0.922, 0.924]),
+    ("Au", [1.0, 0.782, 0.344]),
+    ("Cu", [0.955, 0.638, 0.538]),
+    ("CuZn", [0.910, 0.778, 0.423]),
+];
+
+/// Reflectance at normal incidence of a conductor with the complex IOR `eta` + i `k`
+fn conductor_reflectance(eta: [f32; 3], k: [f32; 3]) -> [f32; 3] {
+    let mut reflectance = [0.0; 3];
+    for i in 0..3 {
+        let (n, k) = (eta[i], k[i]);
+        reflectance[i] = ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+    }
+    reflectance
+}
+
+/// Mean radiance of an environment image, 8 and 16-bit images are sRGB encoded. Rows of
+/// a lat-long image are weighted by their solid angle, pixels of the equal-area octahedral
+/// images of pbrt all cover the same one.
+fn environment_average(path: &Path, latlong: bool) -> Result<[f32; 3]> {
+    let image = image::open(path)
+        .with_context(|| format!("failed to open environment image '{}'", path.display()))?;
+    let is_linear = matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    );
+    let image = image.to_rgb32f();
+    let height = image.height() as f64;
+    let mut sum = [0.0f64; 3];
+    let mut weight_sum = 0.0f64;
+    for (_, y, pixel) in image.enumerate_pixels() {
+        let weight = if latlong {
+            (std::f64::consts::PI * (y as f64 + 0.5) / height).sin()
+        } else {
+            1.0
+        };
+        for (sum, &value) in sum.iter_mut().zip(pixel.0.iter()) {
+            let value = if is_linear { value } else { srgb_decode(value) };
+            *sum += value as f64 * weight;
+        }
+        weight_sum += weight;
+    }
+    if weight_sum <= 0.0 {
+        bail!("environment image '{}' is empty", path.display());
+    }
+    Ok(sum.map(|sum| (sum / weight_sum) as f32))
+}
+
+fn srgb_decode(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Largest angle in degrees between triangles sharing generated smooth normals
+const DEFAULT_CREASE_ANGLE: f32 = 60.0;
+
+/// Triangles of a model with flattened positions and normals, there may be no normals
+#[derive(Debug)]
+struct ModelData {
+    positions: Vec<f32>,
+    normals: Vec<f32>,
+    indices: Vec<u32>,
+}
+
 struct InputLoader {
     path: PathBuf,
     meshes: Vec<Vec<Rc<TriangleMesh>>>,
 }
 
-pub fn load<P: AsRef<Path>>(path: P) -> Result<Renderer> {
+/// Reads a JSON scene, a pbrt-v4 scene if the extension is `.pbrt` or a Mitsuba 3 scene if
+/// it is `.xml`
+pub fn load_description<P: AsRef<Path>>(path: P) -> Result<SceneDescription> {
+    let path = path.as_ref();
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pbrt") => pbrt::load_pbrt(path),
+        Some("xml") => mitsuba::load_mitsuba(path),
+        _ => SceneDescription::from_file(path),
+    }
+}
+
+/// Builds the scene of a description read from `path`, files in it are relative to `path`
+/// or found in the search paths
+pub fn build<P: AsRef<Path>>(
+    path: P,
+    description: &SceneDescription,
+    search_paths: &[PathBuf],
+) -> Result<(OutputConfig, Scene)> {
+    let description = resolve(&path, description, search_paths)?;
     let mut loader = InputLoader::new(path);
-    loader.load()
+    loader.load(&description)
 }
 
 impl InputLoader {
@@ -32,223 +137,338 @@ impl InputLoader {
         }
     }
 
-    fn load(&mut self) -> Result<Renderer> {
-        let json_file = std::fs::File::open(&self.path)?;
-        let json_reader = std::io::BufReader::new(json_file);
-        let json_value: serde_json::Value = serde_json::from_reader(json_reader)?;
-
-        let output_config_json = json_value.get("output").context("top: no 'output' field")?;
-        let output_config = self.load_output(output_config_json)?;
-
-        let max_depth = get_int_field(&json_value, "top", "max_depth")?;
-
-        let camera_json = json_value.get("camera").context("top: no 'camera' field")?;
-        let camera = self.load_camera(camera_json)?;
+    fn load(&mut self, description: &SceneDescription) -> Result<(OutputConfig, Scene)> {
+        let output_config = self.load_output(&description.output)?;
 
-        let materials_json = json_value
-            .get("materials")
-            .context("top: no 'materials' field")?;
-        let materials = self.load_materials(materials_json)?;
-
-        let meshes_json = json_value.get("meshes").context("top: no 'meshes' field")?;
-        self.meshes = self.load_meshes(meshes_json)?;
-
-        let lights_json = json_value.get("lights").context("top: no 'lights' field")?;
-        let lights = self.load_lights(lights_json)?;
+        let max_depth = description.max_depth;
+        let sampler = match &description.sampler {
+            Some(name) => load_sampler(name)?,
+            None => SamplerKind::default(),
+        };
 
-        let objects_json = json_value
-            .get("objects")
-            .context("top: no 'objects' field")?;
-        let (mut triangles, transforms) = self.load_objects(objects_json)?;
+        let camera = description.camera.build();
+        let materials = self.load_materials(&description.materials);
+        self.meshes = self.load_meshes(&description.meshes)?;
+        let lights = self.load_lights(&description.lights);
+        let (mut triangles, transforms) =
+            self.load_objects(&description.objects, materials.len())?;
+
+        let bvh = self.load_bvh(&description.bvh, &mut triangles)?;
+        println!("{}", bvh.stats());
+
+        let scene = Scene {
+            camera,
+            max_depth,
+            sampler,
+            materials,
+            meshes: std::mem::take(&mut self.meshes),
+            triangles,
+            transforms,
+            lights,
+            environment: description.environment,
+            bvh,
+        };
+        SceneUniform::check_capacity(&scene)?;
 
-        let bvh_json = json_value.get("bvh").context("top: no 'bvh' field")?;
-        let bvh = self.load_bvh(bvh_json, &mut triangles)?;
+        Ok((output_config, scene))
+    }
 
-        let mut scene_uniform = unsafe {
-            let layout = std::alloc::Layout::new::<uniforms::SceneUniform>();
-            let prt = std::alloc::alloc(layout) as *mut uniforms::SceneUniform;
-            Box::from_raw(prt)
+    fn load_output(&self, output: &OutputDescription) -> Result<OutputConfig> {
+        let file = output.file.as_deref().unwrap_or("pt_{}.jpg");
+        let render_mode = match output.render_mode.as_deref().unwrap_or("default") {
+            "default" => RenderMode::Default,
+            "bvh_nodes" => RenderMode::BvhNodes,
+            "triangle_tests" => RenderMode::TriangleTests,
+            mode => bail!(format!(
+                "output.render_mode: unknown render mode '{}'",
+                mode
+            )),
         };
-
-        scene_uniform.max_depth = max_depth;
-        // bvh nodes
-        bvh.fill_in_uniform(&mut scene_uniform);
-        // mesh vertices
-        let mut vertex_index = 0;
-        let mut index_offsets = vec![0; self.meshes.len()];
-        let mut index_offset = 0;
-        for model in &self.meshes {
-            for mesh in model {
-                for vert in &mesh.vertices {
-                    assert!(
-                        vertex_index < scene_uniform.vertices.len(),
-                        "too many vertices"
-                    );
-                    scene_uniform.vertices[vertex_index] =
-                        uniforms::Vertex::new(vert.position, vert.normal);
-                    vertex_index += 1;
+        let display = if render_mode != RenderMode::Default {
+            DisplayConfig::passthrough()
+        } else if let Some(display) = &output.display {
+            self.load_display(display)?
+        } else {
+            DisplayConfig::default()
+        };
+        let filter = match &output.filter {
+            Some(filter) => load_filter(filter)?,
+            None => PixelFilter::None,
+        };
+        let denoise = match &output.denoise {
+            Some(denoise) if render_mode == RenderMode::Default => {
+                Some(self.load_denoise(denoise)?)
+            }
+            _ => None,
+        };
+        let adaptive = match &output.adaptive {
+            Some(adaptive) if render_mode == RenderMode::Default => {
+                Some(self.load_adaptive(adaptive)?)
+            }
+            _ => None,
+        };
+        let lobes = match &output.lobes {
+            Some(lobes) if render_mode == RenderMode::Default => load_lobes(lobes)?,
+            _ => vec![],
+        };
+        let workgroup_size = match output.workgroup_size {
+            Some([x, y]) => {
+                // 1024 is the minimum of GL_MAX_COMPUTE_WORK_GROUP_INVOCATIONS
+                if x == 0 || y == 0 || x * y > 1024 {
+                    bail!("output.workgroup_size: should have at most 1024 invocations");
                 }
-                index_offsets[mesh.mesh_index as usize] = index_offset;
-                index_offset += mesh.vertices.len();
+                WorkgroupSize { x, y }
             }
+            None => WorkgroupSize::default(),
+        };
+        let tile_size = output.tile_size.unwrap_or(1024);
+        if tile_size == 0 {
+            bail!("output.tile_size: should be at least 1");
         }
-        // objects & object triangles
-        for (index, tri) in triangles.iter().enumerate() {
-            let offset = index_offsets[tri.mesh.mesh_index as usize];
-            let indices = [
-                tri.indices[0] + offset,
-                tri.indices[1] + offset,
-                tri.indices[2] + offset,
-            ];
-            assert!(index < scene_uniform.triangles.len(), "too many triangles");
-            assert!(
-                (tri.trans_index as usize) < scene_uniform.objects.len(),
-                "too many objects"
-            );
-            scene_uniform.triangles[index] =
-                uniforms::Triangle::new(indices, tri.material, tri.trans_index);
-            scene_uniform.objects[tri.trans_index as usize] =
-                uniforms::SceneObject::new(transforms[tri.trans_index as usize]);
-        }
-        //materials
-        for (index, mat) in materials.iter().enumerate() {
-            assert!(index < scene_uniform.materials.len(), "too many materials");
-            scene_uniform.materials[index] = uniforms::Material::new(
-                mat.albedo,
-                mat.ior,
-                mat.roughness,
-                mat.metallic,
-                mat.is_translucent,
-            );
-        }
-        // lights
-        scene_uniform.lights_count = lights.len() as u32;
-        for (index, light) in lights.into_iter().enumerate() {
-            assert!(index < scene_uniform.lights.len(), "too many lights");
-            scene_uniform.lights[index] = light;
-        }
-
-        let mut variable_uniform = uniforms::VariableUniform::zeroed();
-        variable_uniform.camera = camera;
-        variable_uniform.curr_light_index = 0;
-
-        Ok(Renderer::new(
-            output_config,
-            scene_uniform,
-            variable_uniform,
-        ))
-    }
-
-    fn load_output(&self, value: &serde_json::Value) -> Result<OutputConfig> {
-        let file = get_str_field_or(value, "output", "file", "pt_{}.jpg")?;
-        let width = get_int_field(value, "output", "width")?;
-        let height = get_int_field(value, "output", "height")?;
-        let scale = get_int_field_or(value, "output", "scale", 1)?;
+        let resize = match output.resize.as_deref().unwrap_or("letterbox") {
+            "letterbox" => ResizeMode::Letterbox,
+            "reallocate" => ResizeMode::Reallocate,
+            mode => bail!(format!("output.resize: unknown resize mode '{}'", mode)),
+        };
         Ok(OutputConfig {
             file: file.to_string(),
-            width,
-            height,
-            scale,
+            width: output.width,
+            height: output.height,
+            scale: output.scale.unwrap_or(1),
+            render_mode,
+            display,
+            filter,
+            denoise,
+            adaptive,
+            lobes,
+            workgroup_size,
+            tile_size,
+            resize,
         })
     }
 
-    fn load_camera(&self, value: &serde_json::Value) -> Result<uniforms::Camera> {
-        let eye = get_float_array3_field(value, "camera-perspective", "eye")?;
-        let forward = get_float_array3_field(value, "camera-perspective", "forward")?;
-        let up = get_float_array3_field(value, "camera-perspective", "up")?;
-        let fov = get_float_field(value, "camera-perspective", "fov")?;
-        Ok(uniforms::Camera::new(
-            eye.into(),
-            forward.into(),
-            up.into(),
-            fov,
-        ))
-    }
-
-    fn load_materials(&self, value: &serde_json::Value) -> Result<Vec<Material>> {
-        let arr = value
-            .as_array()
-            .context("top: 'materials' should be an array")?;
-        let mut materials = Vec::with_capacity(arr.len());
-        for mat_json in arr {
-            let ior = get_float_field(mat_json, "material", "ior")?;
-            let albedo = get_float_array3_field(mat_json, "material", "albedo")?;
-            let roughness = get_float_field(mat_json, "material", "roughness")?;
-            let metallic = get_float_field(mat_json, "material", "metallic")?;
-            let is_translucent = get_bool_field(mat_json, "material", "is_translucent")?;
-            let mat = Material::new(albedo, ior, roughness, metallic, is_translucent);
-            materials.push(mat);
+    fn load_denoise(&self, denoise: &DenoiseDescription) -> Result<DenoiseConfig> {
+        let default = DenoiseConfig::default();
+        let iterations = denoise.iterations.unwrap_or(default.iterations);
+        if iterations == 0 {
+            bail!("output.denoise.iterations: should be at least 1");
         }
-        Ok(materials)
+        Ok(DenoiseConfig {
+            iterations,
+            sigma_color: denoise.sigma_color.unwrap_or(default.sigma_color),
+            sigma_normal: denoise.sigma_normal.unwrap_or(default.sigma_normal),
+            sigma_depth: denoise.sigma_depth.unwrap_or(default.sigma_depth),
+            temporal: denoise.temporal.unwrap_or(default.temporal),
+            temporal_alpha: denoise.temporal_alpha.unwrap_or(default.temporal_alpha),
+        })
     }
 
-    fn load_meshes(&self, value: &serde_json::Value) -> Result<Vec<Vec<Rc<TriangleMesh>>>> {
-        let arr = value
-            .as_array()
-            .context("top: 'meshes' should be an array")?;
+    fn load_adaptive(&self, adaptive: &AdaptiveDescription) -> Result<AdaptiveConfig> {
+        let default = AdaptiveConfig::default();
+        let target_error = adaptive.target_error.unwrap_or(default.target_error);
+        if target_error <= 0.0 {
+            bail!("output.adaptive.target_error: should be positive");
+        }
+        Ok(AdaptiveConfig {
+            min_samples: adaptive.min_samples.unwrap_or(default.min_samples),
+            target_error,
+        })
+    }
 
-        let mut meshes = Vec::with_capacity(arr.len());
-        let mut mesh_index = 0;
+    fn load_display(&self, display: &DisplayDescription) -> Result<DisplayConfig> {
+        let exposure = display.exposure.unwrap_or(0.0);
+        let tone_mapper = match display.tone_mapping.as_deref().unwrap_or("none") {
+            "none" => ToneMapper::None,
+            "reinhard" => ToneMapper::Reinhard,
+            "aces" => ToneMapper::AcesFitted,
+            "agx" => ToneMapper::Agx,
+            "filmic" => ToneMapper::Filmic,
+            tone_mapper => bail!(format!(
+                "output.display.tone_mapping: unknown tone mapping '{}'",
+                tone_mapper
+            )),
+        };
+        if let Some(temperature) = display.white_balance {
+            if temperature <= 0.0 {
+                bail!("output.display.white_balance: should be a positive temperature in K");
+            }
+        }
+        let srgb = match display.encoding.as_deref().unwrap_or("srgb") {
+            "srgb" => true,
+            "linear" => false,
+            encoding => bail!(format!(
+                "output.display.encoding: unknown encoding '{}'",
+                encoding
+            )),
+        };
+        Ok(DisplayConfig {
+            exposure,
+            tone_mapper,
+            white_balance: display.white_balance,
+            srgb,
+        })
+    }
 
-        for mesh_json in arr {
-            let file = mesh_json
-                .as_str()
-                .context("meshes: elements should be string")?;
+    fn load_materials(&self, materials: &[MaterialDescription]) -> Vec<Material> {
+        materials.iter().map(MaterialDescription::build).collect()
+    }
 
-            let mut obj_load_option = tobj::LoadOptions::default();
-            obj_load_option.triangulate = true;
-            obj_load_option.single_index = true;
-            let (models, _) = tobj::load_obj(self.path.with_file_name(file), &obj_load_option)?;
+    fn load_meshes(&self, meshes: &[MeshDescription]) -> Result<Vec<Vec<Rc<TriangleMesh>>>> {
+        let mut loaded = Vec::with_capacity(meshes.len());
+        let mut mesh_index = 0;
 
+        for (index, mesh) in meshes.iter().enumerate() {
+            let models = self
+                .load_models(mesh)
+                .with_context(|| format!("meshes[{}]", index))?;
             let mut meshes_temp = vec![];
-            for model in models {
-                let indices = model.mesh.indices;
-                let vertex_count = model.mesh.positions.len() / 3;
+            for mut model in models {
+                if model.normals.is_empty() {
+                    model = model.smooth_normals(DEFAULT_CREASE_ANGLE);
+                }
+                let vertex_count = model.positions.len() / 3;
                 let mut vertices = vec![MeshVertex::default(); vertex_count];
-                for i in 0..vertex_count {
-                    let i0 = 3 * i;
-                    let i1 = 3 * i + 1;
-                    let i2 = 3 * i + 2;
-                    if i2 < model.mesh.positions.len() {
-                        vertices[i].position = Point3::new(
-                            model.mesh.positions[i0],
-                            model.mesh.positions[i1],
-                            model.mesh.positions[i2],
-                        );
-                    }
-                    if i2 < model.mesh.normals.len() {
-                        vertices[i].normal = Vector3::new(
-                            model.mesh.normals[i0],
-                            model.mesh.normals[i1],
-                            model.mesh.normals[i2],
-                        );
+                for (i, vertex) in vertices.iter_mut().enumerate() {
+                    let p = &model.positions[3 * i..3 * i + 3];
+                    vertex.position = Point3::new(p[0], p[1], p[2]);
+                    if let Some(n) = model.normals.get(3 * i..3 * i + 3) {
+                        vertex.normal = Vector3::new(n[0], n[1], n[2]);
                     }
                 }
 
-                let mesh = TriangleMesh::new(vertices, indices, mesh_index);
+                let mesh = TriangleMesh::new(vertices, model.indices, mesh_index);
                 mesh_index += 1;
                 meshes_temp.push(Rc::new(mesh));
             }
 
-            meshes.push(meshes_temp);
+            loaded.push(meshes_temp);
         }
-        Ok(meshes)
+        Ok(loaded)
+    }
+
+    /// Models of an OBJ file, the one of a PLY file, inline triangles or a generated mesh
+    fn load_models(&self, mesh: &MeshDescription) -> Result<Vec<ModelData>> {
+        let file = match mesh {
+            MeshDescription::File(file) => self.path.with_file_name(file),
+            MeshDescription::Inline(InlineMeshDescription::Triangles {
+                positions,
+                normals,
+                indices,
+            }) => {
+                let vertex_count = positions.len() as u32;
+                if !indices.len().is_multiple_of(3) {
+                    bail!("'indices' should be a multiple of 3");
+                }
+                if let Some(index) = indices.iter().find(|&&index| index >= vertex_count) {
+                    bail!("vertex {} doesn't exist", index);
+                }
+                if !normals.is_empty() && normals.len() != positions.len() {
+                    bail!("'normals' should be as many as 'positions'");
+                }
+                return Ok(vec![ModelData {
+                    positions: positions.iter().flatten().copied().collect(),
+                    normals: normals.iter().flatten().copied().collect(),
+                    indices: indices.clone(),
+                }]);
+            }
+            MeshDescription::Inline(generator) => return load_generated(generator),
+            MeshDescription::Shaded(shaded) => {
+                let models = self.load_models(&shaded.mesh)?;
+                let crease_angle = shaded.crease_angle.unwrap_or(DEFAULT_CREASE_ANGLE);
+                return match shaded.shading.as_deref().unwrap_or("smooth") {
+                    "smooth" => Ok(models
+                        .into_iter()
+                        .map(|model| {
+                            if model.normals.is_empty() {
+                                model.smooth_normals(crease_angle)
+                            } else {
+                                model
+                            }
+                        })
+                        .collect()),
+                    "flat" => Ok(models.into_iter().map(ModelData::flat_normals).collect()),
+                    shading => bail!(
+                        "shading: unknown shading '{}', expected 'smooth' or 'flat'",
+                        shading
+                    ),
+                };
+            }
+        };
+
+        let is_ply = file
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ply"));
+        if is_ply {
+            return Ok(vec![ply::load_ply(&file)?]);
+        }
+
+        let obj_load_option = tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        };
+        let (models, _) = tobj::load_obj(&file, &obj_load_option)
+            .with_context(|| format!("failed to load '{}'", file.display()))?;
+        Ok(models
+            .into_iter()
+            .map(|model| ModelData {
+                positions: model.mesh.positions,
+                normals: model.mesh.normals,
+                indices: model.mesh.indices,
+            })
+            .collect())
     }
 
     fn load_objects(
         &self,
-        value: &serde_json::Value,
+        objects: &[ObjectDescription],
+        material_count: usize,
     ) -> Result<(Vec<Triangle>, Vec<Matrix4<f32>>)> {
-        let arr = value
-            .as_array()
-            .context("top: 'objects' should be an array")?;
         let mut triangles = vec![];
-        let mut transforms = Vec::with_capacity(arr.len());
-        for (obj_index, obj_json) in arr.iter().enumerate() {
-            let trans = load_transform(obj_json, "object", "transform")?;
-            let material = get_int_field(obj_json, "object", "material")?;
-            let mesh_index_orig = get_int_array2_field(obj_json, "object", "mesh")?;
-            let mesh = &self.meshes[mesh_index_orig[0] as usize][mesh_index_orig[1] as usize];
+        let mut transforms = Vec::with_capacity(objects.len());
+        for (obj_index, object) in objects.iter().enumerate() {
+            let trans = match &object.transform {
+                Some(transform) => load_transform(transform),
+                None => Matrix4::identity(),
+            };
+            let material = object.material;
+            if material as usize >= material_count {
+                bail!(
+                    "objects[{}].material: material {} doesn't exist",
+                    obj_index,
+                    material
+                );
+            }
+            let [file_index, model_index] = match (object.mesh, &object.shape) {
+                (Some(mesh), None) => mesh,
+                (None, Some(shape)) => {
+                    let (shape, shape_trans) = load_shape(shape)
+                        .with_context(|| format!("objects[{}].shape", obj_index))?;
+                    let trans = trans * shape_trans;
+                    let index = triangles.len() as u32;
+                    triangles.push(Triangle::analytic(
+                        shape,
+                        index,
+                        material,
+                        &trans,
+                        obj_index as u32,
+                    ));
+                    transforms.push(trans);
+                    continue;
+                }
+                _ => bail!(
+                    "objects[{}]: either 'mesh' or 'shape' should be given",
+                    obj_index
+                ),
+            };
+            let mesh = self
+                .meshes
+                .get(file_index as usize)
+                .and_then(|models| models.get(model_index as usize))
+                .context(format!(
+                    "objects[{}].mesh: mesh [{}, {}] doesn't exist",
+                    obj_index, file_index, model_index
+                ))?;
 
             let triangle_count = mesh.indices.len() / 3;
             for i in 0..triangle_count {
@@ -271,183 +491,200 @@ impl InputLoader {
         Ok((triangles, transforms))
     }
 
-    fn load_lights(&self, value: &serde_json::Value) -> Result<Vec<uniforms::Light>> {
-        let arr = value
-            .as_array()
-            .context("top: 'lights' should be an array")?;
-        let mut lights = Vec::with_capacity(arr.len());
-        for light_json in arr {
-            let ty = get_str_field(light_json, "light", "type")?;
-            let light = match ty {
-                "point" => {
-                    let position = get_float_array3_field(light_json, "light-point", "position")?;
-                    let strength = get_float_array3_field(light_json, "light-point", "strength")?;
-                    uniforms::Light::point(position, strength)
-                }
-                "directional" => {
-                    let direction =
-                        get_float_array3_field(light_json, "light-directional", "direction")?;
-                    let strength =
-                        get_float_array3_field(light_json, "light-directional", "strength")?;
-                    uniforms::Light::directional(direction, strength)
+    fn load_lights(&self, lights: &[LightDescription]) -> Vec<Light> {
+        lights.iter().map(LightDescription::build).collect()
+    }
+
+    fn load_bvh(&self, bvh: &BvhDescription, triangles: &mut Vec<Triangle>) -> Result<BvhAccel> {
+        let max_leaf_size = bvh.max_leaf_size.unwrap_or(4) as usize;
+        let bucket_number = bvh.bucket_number.unwrap_or(16) as usize;
+        let cache_path = match &bvh.cache {
+            Some(cache) => self.path.with_file_name(cache),
+            None => return Ok(BvhAccel::new(triangles, max_leaf_size, bucket_number)),
+        };
+        let key = BvhAccel::cache_key(triangles, max_leaf_size, bucket_number);
+        if cache_path.exists() {
+            match BvhAccel::load_cache(&cache_path, key, triangles) {
+                Ok(Some(bvh)) => {
+                    println!("BVH is loaded from cache '{}'", cache_path.display());
+                    return Ok(bvh);
                 }
-                _ => bail!(format!("light: unknown type '{}'", ty)),
-            };
-            lights.push(light)
+                Ok(None) => println!(
+                    "BVH cache '{}' is out of date, rebuild it",
+                    cache_path.display()
+                ),
+                Err(err) => println!(
+                    "WARNING: failed to read BVH cache '{}', rebuild it: {}",
+                    cache_path.display(),
+                    err
+                ),
+            }
         }
-        Ok(lights)
-    }
 
-    fn load_bvh(
-        &self,
-        value: &serde_json::Value,
-        triangles: &mut Vec<Triangle>,
-    ) -> Result<BvhAccel> {
-        let max_leaf_size = get_int_field_or(value, "bvh", "max_leaf_size", 4)? as usize;
-        let bucket_number = get_int_field_or(value, "bvh", "bucket_number", 16)? as usize;
-        Ok(BvhAccel::new(triangles, max_leaf_size, bucket_number))
+        let bvh = BvhAccel::new(triangles, max_leaf_size, bucket_number);
+        if let Err(err) = bvh.save_cache(&cache_path, key, triangles) {
+            println!(
+                "WARNING: failed to write BVH cache '{}': {}",
+                cache_path.display(),
+                err
+            );
+        }
+        Ok(bvh)
     }
 }
 
-fn get_bool_field(value: &serde_json::Value, env: &str, field: &str) -> Result<bool> {
-    let field_value = value
-        .get(field)
-        .context(format!("{}: no '{}' field", env, field))?;
-    field_value
-        .as_bool()
-        .context(format!("{}: '{}' should be a boolean", env, field))
-}
-
-fn get_str_field_or<'a>(
-    value: &'a serde_json::Value,
-    env: &str,
-    field: &str,
-    default: &'a str,
-) -> Result<&'a str> {
-    if let Some(_) = value.get(field) {
-        get_str_field(value, env, field)
-    } else {
-        Ok(default)
+/// "all" or a list of lobe names
+fn load_lobes(lobes: &LobesDescription) -> Result<Vec<Lobe>> {
+    let names = match lobes {
+        LobesDescription::Name(name) if name == "all" => return Ok(Lobe::ALL.to_vec()),
+        LobesDescription::Name(_) => {
+            bail!("output.lobes: should be \"all\" or an array of lobe names")
+        }
+        LobesDescription::List(names) => names,
+    };
+    let mut lobes = Vec::with_capacity(names.len());
+    for name in names {
+        let lobe =
+            Lobe::from_name(name).context(format!("output.lobes: unknown lobe '{}'", name))?;
+        if !lobes.contains(&lobe) {
+            lobes.push(lobe);
+        }
     }
+    Ok(lobes)
 }
 
-fn get_str_field<'a>(value: &'a serde_json::Value, env: &str, field: &str) -> Result<&'a str> {
-    let field_value = value
-        .get(field)
-        .context(format!("{}: no '{}' field", env, field))?;
-    field_value
-        .as_str()
-        .context(format!("{}: '{}' should be a string", env, field))
-}
-
-fn get_float_field(value: &serde_json::Value, env: &str, field: &str) -> Result<f32> {
-    let field_value = value
-        .get(field)
-        .context(format!("{}: no '{}' field", env, field))?;
-    field_value
-        .as_f64()
-        .map(|f| f as f32)
-        .context(format!("{}: '{}' should be a float", env, field))
-}
-
-fn get_int_field_or(
-    value: &serde_json::Value,
-    env: &str,
-    field: &str,
-    default: u32,
-) -> Result<u32> {
-    if let Some(_) = value.get(field) {
-        get_int_field(value, env, field)
-    } else {
-        Ok(default)
+/// A filter type name, or an object with a 'type' and the parameters to override
+fn load_filter(description: &FilterDescription) -> Result<PixelFilter> {
+    let env = "output.filter";
+    let (name, radius, sigma, b, c) = match description {
+        FilterDescription::Name(name) => (name, None, None, None, None),
+        FilterDescription::Params {
+            ty,
+            radius,
+            sigma,
+            b,
+            c,
+        } => (ty, *radius, *sigma, *b, *c),
+    };
+    let filter =
+        PixelFilter::from_name(name).context(format!("{}: unknown filter '{}'", env, name))?;
+
+    let radius = radius.unwrap_or_else(|| filter.radius());
+    if filter != PixelFilter::None && radius <= 0.0 {
+        bail!("{}.radius: should be positive", env);
     }
+    Ok(match filter {
+        PixelFilter::None => PixelFilter::None,
+        PixelFilter::Box { .. } => PixelFilter::Box { radius },
+        PixelFilter::Tent { .. } => PixelFilter::Tent { radius },
+        PixelFilter::Gaussian { sigma: default, .. } => PixelFilter::Gaussian {
+            radius,
+            sigma: sigma.unwrap_or(default),
+        },
+        PixelFilter::Mitchell {
+            b: default_b,
+            c: default_c,
+            ..
+        } => PixelFilter::Mitchell {
+            radius,
+            b: b.unwrap_or(default_b),
+            c: c.unwrap_or(default_c),
+        },
+        PixelFilter::BlackmanHarris { .. } => PixelFilter::BlackmanHarris { radius },
+    })
 }
 
-fn get_int_field(value: &serde_json::Value, env: &str, field: &str) -> Result<u32> {
-    let field_value = value
-        .get(field)
-        .context(format!("{}: no '{}' field", env, field))?;
-    field_value
-        .as_u64()
-        .map(|f| f as u32)
-        .context(format!("{}: '{}' should be an int", env, field))
+fn load_sampler(name: &str) -> Result<SamplerKind> {
+    SamplerKind::from_name(name).context(format!("sampler: unknown sampler '{}'", name))
 }
 
-fn get_int_array2_field(value: &serde_json::Value, env: &str, field: &str) -> Result<[u32; 2]> {
-    let field_value = value
-        .get(field)
-        .context(format!("{}: no '{}' field", env, field))?;
-    let error_info = format!("{}: '{}' should be an array with 2 ints", env, field);
-    let arr = field_value.as_array().context(error_info.clone())?;
-    if arr.len() == 2 {
-        let arr0 = arr[0].as_u64().context(error_info.clone())? as u32;
-        let arr1 = arr[1].as_u64().context(error_info.clone())? as u32;
-        Ok([arr0, arr1])
-    } else {
-        bail!(error_info)
-    }
+/// Models of a generated mesh
+fn load_generated(generator: &InlineMeshDescription) -> Result<Vec<ModelData>> {
+    let at_least = |value: u32, min: u32, name: &str| {
+        if value < min {
+            bail!("'{}' should be at least {}", name, min);
+        }
+        Ok(())
+    };
+    let model = match *generator {
+        InlineMeshDescription::Triangles { .. } => unreachable!(),
+        InlineMeshDescription::Plane { subdivisions } => {
+            at_least(subdivisions, 1, "subdivisions")?;
+            generate::plane(subdivisions)
+        }
+        InlineMeshDescription::Cube { subdivisions } => {
+            at_least(subdivisions, 1, "subdivisions")?;
+            generate::cube(subdivisions)
+        }
+        InlineMeshDescription::UvSphere { segments, rings } => {
+            at_least(segments, 3, "segments")?;
+            at_least(rings, 2, "rings")?;
+            generate::uv_sphere(segments, rings)
+        }
+        InlineMeshDescription::Cylinder {
+            segments,
+            rings,
+            caps,
+        } => {
+            at_least(segments, 3, "segments")?;
+            at_least(rings, 1, "rings")?;
+            generate::cylinder(segments, rings, caps)
+        }
+        InlineMeshDescription::Torus {
+            major_radius,
+            minor_radius,
+            segments,
+            sides,
+        } => {
+            at_least(segments, 3, "segments")?;
+            at_least(sides, 3, "sides")?;
+            if minor_radius <= 0.0 || major_radius <= minor_radius {
+                bail!("'major_radius' should be greater than 'minor_radius', which should be positive");
+            }
+            generate::torus(major_radius, minor_radius, segments, sides)
+        }
+        InlineMeshDescription::CornellBox { subdivisions } => {
+            at_least(subdivisions, 1, "subdivisions")?;
+            return Ok(generate::cornell_box(subdivisions));
+        }
+    };
+    Ok(vec![model])
 }
 
-fn get_float_array3_field(value: &serde_json::Value, env: &str, field: &str) -> Result<[f32; 3]> {
-    let field_value = value
-        .get(field)
-        .context(format!("{}: no '{}' field", env, field))?;
-    let error_info = format!("{}: '{}' should be an array with 3 floats", env, field);
-    let arr = field_value.as_array().context(error_info.clone())?;
-    if arr.len() == 3 {
-        let arr0 = arr[0].as_f64().context(error_info.clone())? as f32;
-        let arr1 = arr[1].as_f64().context(error_info.clone())? as f32;
-        let arr2 = arr[2].as_f64().context(error_info.clone())? as f32;
-        Ok([arr0, arr1, arr2])
-    } else {
-        bail!(error_info)
+/// Analytic shape and the transform from its unit sized shape
+fn load_shape(shape: &ShapeDescription) -> Result<(AnalyticShape, Matrix4<f32>)> {
+    let (kind, scale) = match *shape {
+        ShapeDescription::Sphere { radius } => (AnalyticShape::Sphere, [radius; 3]),
+        ShapeDescription::Disc { radius } => (AnalyticShape::Disc, [radius, radius, 1.0]),
+        ShapeDescription::Quad { size: [x, y] } => (AnalyticShape::Quad, [0.5 * x, 0.5 * y, 1.0]),
+    };
+    if scale.iter().any(|&s| s <= 0.0) {
+        bail!("the size should be positive");
     }
+    Ok((
+        kind,
+        Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]),
+    ))
 }
 
-fn load_transform(value: &serde_json::Value, env: &str, field: &str) -> Result<Matrix4<f32>> {
-    let trans_json = value.get(field);
-    if trans_json.is_none() {
-        return Ok(Matrix4::identity());
-    }
-    let trans_json = trans_json.unwrap();
-
+fn load_transform(transform: &TransformDescription) -> Matrix4<f32> {
     let mut matrix = Matrix4::identity();
-    if let Some(mat_json) = trans_json.get("matrix") {
-        let error_info = format!("{}: 'matrix' should be an array with 16 floats", env);
-        let mat_arr = mat_json.as_array().context(error_info.clone())?;
-        if mat_arr.len() != 16 {
-            bail!(error_info)
-        }
-        matrix.x.x = mat_arr[0].as_f64().context(error_info.clone())? as f32;
-        matrix.x.y = mat_arr[1].as_f64().context(error_info.clone())? as f32;
-        matrix.x.z = mat_arr[2].as_f64().context(error_info.clone())? as f32;
-        matrix.x.w = mat_arr[3].as_f64().context(error_info.clone())? as f32;
-        matrix.y.x = mat_arr[4].as_f64().context(error_info.clone())? as f32;
-        matrix.y.y = mat_arr[5].as_f64().context(error_info.clone())? as f32;
-        matrix.y.z = mat_arr[6].as_f64().context(error_info.clone())? as f32;
-        matrix.y.w = mat_arr[7].as_f64().context(error_info.clone())? as f32;
-        matrix.z.x = mat_arr[8].as_f64().context(error_info.clone())? as f32;
-        matrix.z.y = mat_arr[9].as_f64().context(error_info.clone())? as f32;
-        matrix.z.z = mat_arr[10].as_f64().context(error_info.clone())? as f32;
-        matrix.z.w = mat_arr[11].as_f64().context(error_info.clone())? as f32;
-        matrix.w.x = mat_arr[12].as_f64().context(error_info.clone())? as f32;
-        matrix.w.y = mat_arr[13].as_f64().context(error_info.clone())? as f32;
-        matrix.w.z = mat_arr[14].as_f64().context(error_info.clone())? as f32;
-        matrix.w.w = mat_arr[15].as_f64().context(error_info.clone())? as f32;
-    }
-    if let Some(_) = trans_json.get("scale") {
-        let scale = get_float_array3_field(trans_json, env, "scale")?;
+    if let Some(m) = transform.matrix {
+        matrix = Matrix4::new(
+            m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12], m[13],
+            m[14], m[15],
+        );
+    }
+    if let Some(scale) = transform.scale {
         matrix = Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]) * matrix;
     }
-    if let Some(_) = trans_json.get("rotate") {
-        let rotate = get_float_array3_field(trans_json, env, "rotate")?;
+    if let Some(rotate) = transform.rotate {
         matrix = Matrix4::from_angle_z(cgmath::Deg(rotate[2]))
             * Matrix4::from_angle_x(cgmath::Deg(rotate[0]))
             * Matrix4::from_angle_y(cgmath::Deg(rotate[1]))
             * matrix;
     }
-    if let Some(_) = trans_json.get("translate") {
-        let translate = get_float_array3_field(trans_json, env, "translate")?;
+    if let Some(translate) = transform.translate {
         matrix = Matrix4::from_translation(Vector3::new(translate[0], translate[1], translate[2]))
             * matrix;
     }
@@ -455,5 +692,180 @@ fn load_transform(value: &serde_json::Value, env: &str, field: &str) -> Result<M
         println!("WARNING: singular transform matrix found");
     }
 
-    Ok(matrix)
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use cgmath::{InnerSpace, Transform};
+    use serde_json::json;
+
+    use super::*;
+
+    fn transform_point(trans: &Matrix4<f32>, p: [f32; 3]) -> Point3<f32> {
+        trans.transform_point(Point3::new(p[0], p[1], p[2]))
+    }
+
+    fn assert_near(a: Point3<f32>, b: [f32; 3]) {
+        let b = Point3::new(b[0], b[1], b[2]);
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn transform(value: serde_json::Value) -> serde_json::Result<Matrix4<f32>> {
+        serde_json::from_value(value).map(|transform| load_transform(&transform))
+    }
+
+    fn filter(value: serde_json::Value) -> Result<PixelFilter> {
+        load_filter(&serde_json::from_value(value)?)
+    }
+
+    fn lobes(value: serde_json::Value) -> Result<Vec<Lobe>> {
+        load_lobes(&serde_json::from_value(value)?)
+    }
+
+    #[test]
+    fn empty_transform_is_identity() {
+        assert_eq!(transform(json!({})).unwrap(), Matrix4::identity());
+    }
+
+    #[test]
+    fn scale_rotate_then_translate() {
+        let trans = transform(json!({
+            "translate": [1.0, 2.0, 3.0],
+            "rotate": [0.0, 90.0, 0.0],
+            "scale": [2.0, 1.0, 1.0]
+        }))
+        .unwrap();
+        assert_near(transform_point(&trans, [1.0, 0.0, 0.0]), [1.0, 2.0, 1.0]);
+        assert_near(transform_point(&trans, [0.0, 1.0, 0.0]), [1.0, 3.0, 3.0]);
+    }
+
+    #[test]
+    fn matrix_is_column_major() {
+        let trans = transform(json!({
+            "matrix": [
+                1.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0, 0.0,
+                4.0, 5.0, 6.0, 1.0
+            ],
+            "translate": [1.0, 0.0, 0.0]
+        }))
+        .unwrap();
+        assert_near(transform_point(&trans, [0.0, 0.0, 0.0]), [5.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn invalid_transform_is_an_error() {
+        assert!(transform(json!({ "matrix": [1.0, 0.0, 0.0] })).is_err());
+        assert!(transform(json!({ "scale": [1.0, "2"] })).is_err());
+    }
+
+    #[test]
+    fn shapes_scale_the_unit_shapes() {
+        let shape = |value| load_shape(&serde_json::from_value(value).unwrap());
+        let (kind, trans) = shape(json!({ "type": "sphere" })).unwrap();
+        assert_eq!((kind, trans), (AnalyticShape::Sphere, Matrix4::identity()));
+        let (kind, trans) = shape(json!({ "type": "quad", "size": [2.0, 4.0] })).unwrap();
+        assert_eq!(kind, AnalyticShape::Quad);
+        assert_near(transform_point(&trans, [1.0, 1.0, 0.0]), [1.0, 2.0, 0.0]);
+        assert!(shape(json!({ "type": "disc", "radius": 0.0 })).is_err());
+    }
+
+    #[test]
+    fn inline_triangles_are_checked() {
+        let loader = InputLoader::new("scene.json");
+        let models = |value| loader.load_models(&serde_json::from_value(value).unwrap());
+        let positions = json!([[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        let triangles =
+            |indices| json!({ "type": "triangles", "positions": positions, "indices": indices });
+        assert_eq!(
+            models(triangles(json!([0, 1, 2]))).unwrap()[0].indices,
+            [0, 1, 2]
+        );
+        let err = models(triangles(json!([0, 1, 2, 0]))).unwrap_err();
+        assert_eq!(err.to_string(), "'indices' should be a multiple of 3");
+        let err = models(triangles(json!([0, 1, 3]))).unwrap_err();
+        assert_eq!(err.to_string(), "vertex 3 doesn't exist");
+    }
+
+    #[test]
+    fn oversized_scenes_are_errors() {
+        let light =
+            json!({ "type": "point", "position": [0.0, 1.0, 0.0], "strength": [1.0, 1.0, 1.0] });
+        let description = json!({
+            "camera": { "eye": [0.0, 0.0, 5.0], "forward": [0.0, 0.0, -1.0], "up": [0.0, 1.0, 0.0], "fov": 45.0 },
+            "max_depth": 4,
+            "output": { "width": 8, "height": 8 },
+            "materials": [{ "albedo": [1.0, 1.0, 1.0] }],
+            "meshes": [{ "type": "plane" }],
+            "objects": [{ "mesh": [0, 0], "material": 0 }],
+            "lights": vec![light; 2000]
+        });
+        let description = serde_json::from_value(description).unwrap();
+        let err = build("scene.json", &description, &[]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "scene: 2000 lights are more than the 1024 the renderer supports"
+        );
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            filter(json!("tent")).unwrap(),
+            PixelFilter::Tent { radius: 1.0 }
+        );
+        let gaussian = filter(json!({ "type": "gaussian", "sigma": 0.3 })).unwrap();
+        assert_eq!(
+            gaussian,
+            PixelFilter::Gaussian {
+                radius: 1.5,
+                sigma: 0.3
+            }
+        );
+        assert!(filter(json!("lanczos")).is_err());
+        assert!(filter(json!({ "radius": 1.0 })).is_err());
+        assert!(filter(json!({ "type": "box", "radius": 0.0 })).is_err());
+    }
+
+    #[test]
+    fn lobes_by_name() {
+        assert_eq!(lobes(json!("all")).unwrap(), Lobe::ALL.to_vec());
+        let list = lobes(json!(["emission", "direct_diffuse", "emission"])).unwrap();
+        assert_eq!(list, vec![Lobe::Emission, Lobe::DirectDiffuse]);
+        assert!(lobes(json!(["diffuse"])).is_err());
+        assert!(lobes(json!("direct_diffuse")).is_err());
+    }
+
+    #[test]
+    fn environment_averages() {
+        let dir = std::env::temp_dir();
+
+        // 8-bit images are sRGB encoded
+        let path = dir.join(format!("spt-env-{}.png", uuid::Uuid::new_v4()));
+        image::RgbImage::from_fn(2, 2, |x, _| {
+            image::Rgb([if x == 0 { 255 } else { 0 }, 188, 0])
+        })
+        .save(&path)
+        .unwrap();
+        let average = environment_average(&path, false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((average[0] - 0.5).abs() < 1e-5, "{:?}", average);
+        assert!((average[1] - 0.5).abs() < 0.01, "{:?}", average);
+        assert_eq!(average[2], 0.0);
+
+        // the rows near the poles of lat-long images cover less solid angle
+        let path = dir.join(format!("spt-env-{}.exr", uuid::Uuid::new_v4()));
+        image::Rgb32FImage::from_fn(4, 4, |_, y| image::Rgb([if y == 0 { 1.0 } else { 0.0 }; 3]))
+            .save(&path)
+            .unwrap();
+        let average = environment_average(&path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let (pole, equator) = ((PI / 8.0).sin(), (3.0 * PI / 8.0).sin());
+        let expected = pole / (2.0 * pole + 2.0 * equator);
+        assert!((average[0] - expected).abs() < 1e-5, "{:?}", average);
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+
+use super::ModelData;
+
+impl ModelData {
+    fn position(&self, index: u32) -> Vector3<f32> {
+        let i = index as usize * 3;
+        Vector3::new(
+            self.positions[i],
+            self.positions[i + 1],
+            self.positions[i + 2],
+        )
+    }
+
+    /// Normalized normals of the triangles, zero for degenerate ones
+    fn face_normals(&self) -> Vec<Vector3<f32>> {
+        self.indices
+            .chunks_exact(3)
+            .map(|face| {
+                let [p0, p1, p2] = [0, 1, 2].map(|k| self.position(face[k]));
+                let normal = (p1 - p0).cross(p2 - p0);
+                if normal.magnitude2() > 0.0 {
+                    normal.normalize()
+                } else {
+                    normal
+                }
+            })
+            .collect()
+    }
+
+    /// Every triangle gets its own vertices with the normal of the triangle
+    pub(super) fn flat_normals(self) -> ModelData {
+        let face_normals = self.face_normals();
+        let mut flat = ModelData {
+            positions: Vec::with_capacity(self.indices.len() * 3),
+            normals: Vec::with_capacity(self.indices.len() * 3),
+            indices: (0..self.indices.len() as u32).collect(),
+        };
+        for (face, normal) in self.indices.chunks_exact(3).zip(face_normals) {
+            let normal = if normal.magnitude2() > 0.0 {
+                normal
+            } else {
+                Vector3::unit_z()
+            };
+            for &index in face {
+                let i = index as usize * 3;
+                flat.positions.extend_from_slice(&self.positions[i..i + 3]);
+                flat.normals.extend([normal.x, normal.y, normal.z]);
+            }
+        }
+        flat
+    }
+
+    /// Normals averaged over the triangles around each vertex, weighted by their angles at it.
+    /// Triangles meeting at more than `crease_angle` degrees don't share normals, so vertices
+    /// on creases are split. Vertices at the same position are smoothed together, as OBJ files
+    /// split them at texture seams.
+    pub(super) fn smooth_normals(self, crease_angle: f32) -> ModelData {
+        let cos_crease = crease_angle.to_radians().cos();
+        let face_normals = self.face_normals();
+        let corner_angles: Vec<f32> = self
+            .indices
+            .chunks_exact(3)
+            .flat_map(|face| {
+                let p = [0, 1, 2].map(|k| self.position(face[k]));
+                [0, 1, 2].map(|k| {
+                    let (a, b) = (p[(k + 1) % 3] - p[k], p[(k + 2) % 3] - p[k]);
+                    if a.magnitude2() > 0.0 && b.magnitude2() > 0.0 {
+                        a.angle(b).0
+                    } else {
+                        0.0
+                    }
+                })
+            })
+            .collect();
+
+        let key = |index: u32| {
+            let i = index as usize * 3;
+            [0, 1, 2].map(|k| self.positions[i + k].to_bits())
+        };
+        let mut corners_at: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+        for (corner, &index) in self.indices.iter().enumerate() {
+            corners_at.entry(key(index)).or_default().push(corner);
+        }
+
+        let mut smooth = ModelData {
+            positions: vec![],
+            normals: vec![],
+            indices: Vec::with_capacity(self.indices.len()),
+        };
+        let mut vertices = HashMap::new();
+        for (corner, &index) in self.indices.iter().enumerate() {
+            let face_normal = face_normals[corner / 3];
+            let normal = corners_at[&key(index)]
+                .iter()
+                .filter(|&&other| face_normals[other / 3].dot(face_normal) >= cos_crease)
+                .map(|&other| face_normals[other / 3] * corner_angles[other])
+                .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+            let normal = if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                Vector3::unit_z()
+            };
+            let vertex = *vertices
+                .entry((key(index), [normal.x, normal.y, normal.z].map(f32::to_bits)))
+                .or_insert_with(|| {
+                    let i = index as usize * 3;
+                    smooth
+                        .positions
+                        .extend_from_slice(&self.positions[i..i + 3]);
+                    smooth.normals.extend([normal.x, normal.y, normal.z]);
+                    smooth.positions.len() as u32 / 3 - 1
+                });
+            smooth.indices.push(vertex);
+        }
+        smooth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A roof: two triangles meeting at a right angle along the x axis
+    fn roof() -> ModelData {
+        ModelData {
+            positions: vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 1.0, 1.0,
+                0.0, 0.0,
+            ],
+            normals: vec![],
+            indices: vec![0, 1, 2, 3, 4, 5],
+        }
+    }
+
+    fn normal(model: &ModelData, index: u32) -> Vector3<f32> {
+        let i = index as usize * 3;
+        Vector3::new(model.normals[i], model.normals[i + 1], model.normals[i + 2])
+    }
+
+    #[test]
+    fn smooth_normals_respect_creases() {
+        let smooth = roof().smooth_normals(120.0);
+        // the ridge vertices are welded and point up, the eaves keep the face normals
+        assert_eq!(smooth.positions.len() / 3, 4);
+        let up = normal(&smooth, smooth.indices[0]);
+        assert!((up - Vector3::unit_y()).magnitude() < 1e-6);
+        assert_eq!(smooth.indices[0], smooth.indices[3]);
+        assert!(normal(&smooth, smooth.indices[2]).dot(Vector3::unit_y()) < 1.0 - 1e-3);
+
+        let creased = roof().smooth_normals(60.0);
+        assert_eq!(creased.positions.len() / 3, 6);
+        for (k, &index) in creased.indices.iter().enumerate() {
+            let face = roof().face_normals()[k / 3];
+            assert!((normal(&creased, index) - face).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn flat_normals_split_vertices() {
+        let mut quad = roof();
+        quad.positions[7] = 0.0;
+        quad.positions[12..15].copy_from_slice(&[0.0, 0.0, 1.0]);
+        let flat = quad.flat_normals();
+        assert_eq!(flat.indices, vec![0, 1, 2, 3, 4, 5]);
+        for index in 0..6 {
+            assert_eq!(normal(&flat, index), Vector3::unit_y());
+        }
+    }
+}
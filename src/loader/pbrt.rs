@@ -0,0 +1,1106 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use super::{
+    conductor_reflectance, environment_average, BvhDescription, CameraDescription,
+    FilterDescription, InlineMeshDescription, LightDescription, MaterialDescription,
+    MeshDescription, ObjectDescription, OutputDescription, SceneDescription, TransformDescription,
+    METALS,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Num(f64),
+    Open,
+    Close,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Arg {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+    List(Vec<Arg>),
+}
+
+struct Statement {
+    directive: String,
+    line: usize,
+    args: Vec<Arg>,
+}
+
+/// `"type name" value` or `"type name" [values]` after the positional arguments
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Arg>,
+}
+
+struct Params(Vec<Param>);
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix4<f32>,
+    material: MaterialDescription,
+    /// emission of the following shapes, set by `AreaLightSource`
+    area_light: [f32; 3],
+    reverse_orientation: bool,
+}
+
+/// Shape of an object instance definition: mesh, material and transform
+type InstanceShape = (u32, u32, Matrix4<f32>);
+
+struct Converter {
+    /// directory of the scene file, included files and meshes are relative to it
+    dir: PathBuf,
+    /// files being included, to find include cycles
+    includes: Vec<PathBuf>,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    coordinate_systems: HashMap<String, Matrix4<f32>>,
+    named_materials: HashMap<String, MaterialDescription>,
+    world_from_camera: Matrix4<f32>,
+    fov: f32,
+    width: u32,
+    height: u32,
+    file: Option<String>,
+    filter: Option<FilterDescription>,
+    sampler: Option<String>,
+    max_depth: u32,
+    materials: Vec<MaterialDescription>,
+    meshes: Vec<MeshDescription>,
+    mesh_files: HashMap<String, u32>,
+    objects: Vec<ObjectDescription>,
+    lights: Vec<LightDescription>,
+    /// sum of the infinite lights
+    environment: Option<[f32; 3]>,
+    instances: HashMap<String, Vec<InstanceShape>>,
+    instance: Option<(String, Vec<InstanceShape>)>,
+    warnings: BTreeSet<String>,
+}
+
+/// Converts a pbrt-v4 scene file, things that can't be rendered are printed as warnings
+pub(super) fn load_pbrt(path: &Path) -> Result<SceneDescription> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("scene: failed to open '{}'", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let (description, warnings) = convert(&text, dir)
+        .with_context(|| format!("scene: failed to parse '{}'", path.display()))?;
+    for warning in warnings {
+        println!("WARNING: {} in '{}'", warning, path.display());
+    }
+    Ok(description)
+}
+
+fn convert(text: &str, dir: PathBuf) -> Result<(SceneDescription, BTreeSet<String>)> {
+    let mut converter = Converter::new(dir);
+    converter.run(text)?;
+    Ok(converter.finish())
+}
+
+/// pbrt uses left handed coordinates, mirroring the scene and the camera along x gives the
+/// same image with the right handed camera of the renderer
+fn world_flip() -> Matrix4<f32> {
+    Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)
+}
+
+fn default_material() -> MaterialDescription {
+    MaterialDescription {
+        albedo: [0.5, 0.5, 0.5],
+        ior: 1.5,
+        roughness: 1.0,
+        metallic: 0.0,
+        is_translucent: false,
+        emission: [0.0; 3],
+    }
+}
+
+impl Converter {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            includes: vec![],
+            state: GraphicsState {
+                ctm: Matrix4::identity(),
+                material: default_material(),
+                area_light: [0.0; 3],
+                reverse_orientation: false,
+            },
+            stack: vec![],
+            coordinate_systems: HashMap::new(),
+            named_materials: HashMap::new(),
+            world_from_camera: Matrix4::identity(),
+            fov: 90.0,
+            width: 1280,
+            height: 720,
+            file: None,
+            filter: None,
+            sampler: None,
+            max_depth: 5,
+            materials: vec![],
+            meshes: vec![],
+            mesh_files: HashMap::new(),
+            objects: vec![],
+            lights: vec![],
+            environment: None,
+            instances: HashMap::new(),
+            instance: None,
+            warnings: BTreeSet::new(),
+        }
+    }
+
+    fn run(&mut self, text: &str) -> Result<()> {
+        for statement in statements(tokenize(text)?)? {
+            let Statement {
+                directive,
+                line,
+                args,
+            } = statement;
+            self.statement(&directive, &args)
+                .with_context(|| format!("line {}: '{}'", line, directive))?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, directive: &str, args: &[Arg]) -> Result<()> {
+        match directive {
+            "Identity" => self.state.ctm = Matrix4::identity(),
+            "Translate" => {
+                let v = numbers(args, 3)?;
+                self.state.ctm =
+                    self.state.ctm * Matrix4::from_translation(Vector3::new(v[0], v[1], v[2]));
+            }
+            "Scale" => {
+                let v = numbers(args, 3)?;
+                self.state.ctm = self.state.ctm * Matrix4::from_nonuniform_scale(v[0], v[1], v[2]);
+            }
+            "Rotate" => {
+                let v = numbers(args, 4)?;
+                let axis = Vector3::new(v[1], v[2], v[3]);
+                if axis == Vector3::new(0.0, 0.0, 0.0) {
+                    bail!("the axis should not be zero");
+                }
+                self.state.ctm =
+                    self.state.ctm * Matrix4::from_axis_angle(axis.normalize(), Deg(v[0]));
+            }
+            "LookAt" => {
+                let v = numbers(args, 9)?;
+                let camera_from_world = Matrix4::look_at_lh(
+                    Point3::new(v[0], v[1], v[2]),
+                    Point3::new(v[3], v[4], v[5]),
+                    Vector3::new(v[6], v[7], v[8]),
+                );
+                self.state.ctm = self.state.ctm * camera_from_world;
+            }
+            "Transform" | "ConcatTransform" => {
+                let m = numbers(args, 16)?;
+                let matrix = Matrix4::new(
+                    m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11],
+                    m[12], m[13], m[14], m[15],
+                );
+                self.state.ctm = if directive == "Transform" {
+                    matrix
+                } else {
+                    self.state.ctm * matrix
+                };
+            }
+            "CoordinateSystem" => {
+                let name = string_arg(args)?;
+                self.coordinate_systems.insert(name, self.state.ctm);
+            }
+            "CoordSysTransform" => {
+                let name = string_arg(args)?;
+                self.state.ctm = *self
+                    .coordinate_systems
+                    .get(&name)
+                    .with_context(|| format!("unknown coordinate system '{}'", name))?;
+            }
+            "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" | "TransformEnd" => {
+                let state = self.stack.pop().context("no matching begin")?;
+                if directive == "AttributeEnd" {
+                    self.state = state;
+                } else {
+                    self.state.ctm = state.ctm;
+                }
+            }
+            "WorldBegin" => {
+                self.state.ctm = Matrix4::identity();
+                self.coordinate_systems
+                    .insert("world".to_string(), Matrix4::identity());
+            }
+            "Camera" => {
+                let (ty, params) = typed(args)?;
+                if ty != "perspective" {
+                    self.warn(format!("'{}' camera is rendered as 'perspective'", ty));
+                }
+                self.fov = params.float("fov").unwrap_or(90.0);
+                self.world_from_camera = self
+                    .state
+                    .ctm
+                    .invert()
+                    .context("the camera transform should be invertible")?;
+                self.coordinate_systems
+                    .insert("camera".to_string(), self.world_from_camera);
+            }
+            "Film" => {
+                let (_, params) = typed(args)?;
+                self.width = params.float("xresolution").unwrap_or(1280.0) as u32;
+                self.height = params.float("yresolution").unwrap_or(720.0) as u32;
+                self.file = params.string("filename").map(str::to_string);
+            }
+            "PixelFilter" => {
+                let (ty, params) = typed(args)?;
+                self.filter = self.filter(&ty, &params);
+            }
+            "Sampler" => {
+                let (ty, _) = typed(args)?;
+                self.sampler = match ty.as_str() {
+                    "independent" => Some("pcg".to_string()),
+                    "halton" | "sobol" | "paddedsobol" | "zsobol" => Some("sobol".to_string()),
+                    "pmj02bn" => Some("blue_noise".to_string()),
+                    _ => None,
+                };
+            }
+            "Integrator" => {
+                let (_, params) = typed(args)?;
+                self.max_depth = params.float("maxdepth").unwrap_or(5.0) as u32;
+            }
+            "Material" => {
+                let (ty, params) = typed(args)?;
+                self.state.material = self.material(&ty, &params);
+            }
+            "MakeNamedMaterial" => {
+                let (name, params) = typed(args)?;
+                let ty = params.string("type").unwrap_or("diffuse").to_string();
+                let material = self.material(&ty, &params);
+                self.named_materials.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let name = string_arg(args)?;
+                self.state.material = self
+                    .named_materials
+                    .get(&name)
+                    .with_context(|| format!("unknown material '{}'", name))?
+                    .clone();
+            }
+            "Texture" => self.warn("textures are ignored".to_string()),
+            "LightSource" => {
+                let (ty, params) = typed(args)?;
+                self.light(&ty, &params);
+            }
+            "AreaLightSource" => {
+                let (ty, params) = typed(args)?;
+                self.area_light(&ty, &params);
+            }
+            "Shape" => {
+                let (ty, params) = typed(args)?;
+                self.shape(&ty, &params)?;
+            }
+            "ObjectBegin" => {
+                let name = string_arg(args)?;
+                self.stack.push(self.state.clone());
+                self.instance = Some((name, vec![]));
+            }
+            "ObjectEnd" => {
+                let (name, shapes) = self.instance.take().context("no matching 'ObjectBegin'")?;
+                self.instances.insert(name, shapes);
+                self.state = self.stack.pop().context("no matching 'ObjectBegin'")?;
+            }
+            "ObjectInstance" => {
+                let name = string_arg(args)?;
+                let shapes = self
+                    .instances
+                    .get(&name)
+                    .with_context(|| format!("unknown object '{}'", name))?
+                    .clone();
+                for (mesh, material, transform) in shapes {
+                    self.add_object(mesh, material, self.state.ctm * transform);
+                }
+            }
+            "Include" | "Import" => {
+                let file = self.dir.join(string_arg(args)?);
+                let key = file.canonicalize().unwrap_or_else(|_| file.clone());
+                if self.includes.contains(&key) {
+                    bail!("'{}' includes itself", file.display());
+                }
+                let text = std::fs::read_to_string(&file)
+                    .with_context(|| format!("failed to open '{}'", file.display()))?;
+                self.includes.push(key);
+                let result = self
+                    .run(&text)
+                    .with_context(|| format!("in '{}'", file.display()));
+                self.includes.pop();
+                result?;
+            }
+            "Accelerator" | "Attribute" | "ColorSpace" | "MakeNamedMedium" | "MediumInterface"
+            | "Option" | "TransformTimes" | "ActiveTransform" => {}
+            "ReverseOrientation" => {
+                self.state.reverse_orientation = !self.state.reverse_orientation
+            }
+            _ => bail!("unknown directive"),
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, warning: String) {
+        self.warnings.insert(warning);
+    }
+
+    /// RGB of a spectrum parameter, textures aren't supported
+    fn spectrum(&mut self, params: &Params, name: &str) -> Option<[f32; 3]> {
+        let param = params.find(name)?;
+        let values = param.numbers();
+        match (param.ty.as_str(), values.as_slice()) {
+            ("rgb", &[r, g, b]) => Some([r, g, b]),
+            ("blackbody", &[temperature]) => Some(blackbody(temperature)),
+            ("float", &[value]) => Some([value; 3]),
+            // sampled spectrum as wavelength and value pairs
+            ("spectrum", values) if values.len() >= 2 && values.len() % 2 == 0 => {
+                let sum: f32 = values.iter().skip(1).step_by(2).sum();
+                Some([sum / (values.len() / 2) as f32; 3])
+            }
+            ("spectrum", _) => {
+                self.warn(format!("named spectrum of '{}' is taken as white", name));
+                Some([1.0; 3])
+            }
+            ("texture", _) => {
+                self.warn(format!("textured '{}' uses the default value", name));
+                None
+            }
+            (ty, _) => {
+                self.warn(format!("'{} {}' is ignored", ty, name));
+                None
+            }
+        }
+    }
+
+    fn material(&mut self, ty: &str, params: &Params) -> MaterialDescription {
+        let reflectance = |converter: &mut Self, default: f32| {
+            converter
+                .spectrum(params, "reflectance")
+                .unwrap_or([default; 3])
+        };
+        match ty {
+            "diffuse" | "diffusetransmission" => MaterialDescription {
+                albedo: reflectance(self, 0.5),
+                ..default_material()
+            },
+            "coateddiffuse" => MaterialDescription {
+                albedo: reflectance(self, 0.5),
+                roughness: roughness(params),
+                ..default_material()
+            },
+            "conductor" => MaterialDescription {
+                albedo: self.conductor_reflectance(params),
+                roughness: roughness(params),
+                metallic: 1.0,
+                ..default_material()
+            },
+            "dielectric" | "thindielectric" => MaterialDescription {
+                albedo: [1.0; 3],
+                ior: params.float("eta").unwrap_or(1.5),
+                roughness: roughness(params),
+                metallic: 0.0,
+                is_translucent: true,
+                ..default_material()
+            },
+            _ => {
+                self.warn(format!("'{}' material is rendered as gray 'diffuse'", ty));
+                default_material()
+            }
+        }
+    }
+
+    fn conductor_reflectance(&mut self, params: &Params) -> [f32; 3] {
+        if let Some(reflectance) = self.spectrum(params, "reflectance") {
+            return reflectance;
+        }
+        let (eta, k) = match (params.find("eta"), params.find("k")) {
+            (None, None) => return METALS[3].1,
+            (Some(eta), _) if eta.ty == "spectrum" && eta.numbers().is_empty() => {
+                let name = eta.string().unwrap_or_default();
+                let metal = name
+                    .strip_prefix("metal-")
+                    .and_then(|name| name.strip_suffix("-eta"));
+                return match METALS.iter().find(|(m, _)| Some(*m) == metal) {
+                    Some((_, reflectance)) => *reflectance,
+                    None => {
+                        self.warn(format!("conductor '{}' is rendered as copper", name));
+                        METALS[3].1
+                    }
+                };
+            }
+            _ => (
+                self.spectrum(params, "eta").unwrap_or([1.0; 3]),
+                self.spectrum(params, "k").unwrap_or([0.0; 3]),
+            ),
+        };
+        conductor_reflectance(eta, k)
+    }
+
+    fn filter(&mut self, ty: &str, params: &Params) -> Option<FilterDescription> {
+        let name = match ty {
+            "box" => "box",
+            "gaussian" => "gaussian",
+            "mitchell" => "mitchell",
+            "triangle" => "tent",
+            _ => {
+                self.warn(format!("'{}' filter is ignored", ty));
+                return None;
+            }
+        };
+        Some(FilterDescription::Params {
+            ty: name.to_string(),
+            radius: params.float("xradius"),
+            sigma: params.float("sigma"),
+            b: params.float("B"),
+            c: params.float("C"),
+        })
+    }
+
+    fn light(&mut self, ty: &str, params: &Params) {
+        let transform = world_flip() * self.state.ctm;
+        let scale = params.float("scale").unwrap_or(1.0);
+        let from = params.point("from").unwrap_or([0.0; 3]);
+        match ty {
+            "point" => {
+                let intensity = self.spectrum(params, "I").unwrap_or([1.0; 3]);
+                let mut strength = intensity.map(|x| x * scale);
+                if let Some(power) = params.float("power") {
+                    // the power is of the normalized spectrum
+                    let luminance = luminance(intensity);
+                    if luminance > 0.0 {
+                        let k = power / (4.0 * std::f32::consts::PI * luminance);
+                        strength = strength.map(|x| x * k);
+                    }
+                }
+                let position = transform.transform_point(from.into());
+                self.lights.push(LightDescription::Point {
+                    position: position.into(),
+                    strength,
+                });
+            }
+            "distant" => {
+                let radiance = self.spectrum(params, "L").unwrap_or([1.0; 3]);
+                if params.find("illuminance").is_some() {
+                    self.warn("'illuminance' of distant lights is ignored".to_string());
+                }
+                let to = params.point("to").unwrap_or([0.0, 0.0, 1.0]);
+                let direction = Vector3::from(to) - Vector3::from(from);
+                let direction = transform.transform_vector(direction);
+                if direction == Vector3::new(0.0, 0.0, 0.0) {
+                    self.warn("distant light without a direction is ignored".to_string());
+                    return;
+                }
+                self.lights.push(LightDescription::Directional {
+                    direction: direction.into(),
+                    strength: radiance.map(|x| x * scale),
+                });
+            }
+            "infinite" => {
+                let radiance = match params.string("filename") {
+                    Some(file) => {
+                        if self.spectrum(params, "L").is_some() {
+                            self.warn("'L' of image infinite lights is ignored".to_string());
+                        }
+                        // pbrt images are equal-area octahedral maps
+                        match environment_average(&self.dir.join(file), false) {
+                            Ok(average) => {
+                                self.warn(format!(
+                                    "infinite light '{}' is rendered with its mean radiance",
+                                    file
+                                ));
+                                average
+                            }
+                            Err(err) => {
+                                self.warn(format!("infinite light is ignored: {:#}", err));
+                                return;
+                            }
+                        }
+                    }
+                    None => self.spectrum(params, "L").unwrap_or([1.0; 3]),
+                };
+                if params.find("illuminance").is_some() {
+                    self.warn("'illuminance' of infinite lights is ignored".to_string());
+                }
+                let environment = self.environment.get_or_insert([0.0; 3]);
+                for (env, radiance) in environment.iter_mut().zip(radiance) {
+                    *env += radiance * scale;
+                }
+            }
+            _ => self.warn(format!("'{}' lights are ignored", ty)),
+        }
+    }
+
+    /// Shapes after a diffuse area light emit its radiance on the side of their normals
+    fn area_light(&mut self, ty: &str, params: &Params) {
+        if ty != "diffuse" {
+            self.warn(format!("'{}' area lights are ignored", ty));
+            return;
+        }
+        if params.bool("twosided").unwrap_or(false) {
+            self.warn("two-sided area lights only emit on the side of their normals".to_string());
+        }
+        for name in ["filename", "power"] {
+            if params.find(name).is_some() {
+                self.warn(format!("'{}' of area lights is ignored", name));
+            }
+        }
+        let radiance = self.spectrum(params, "L").unwrap_or([1.0; 3]);
+        let scale = params.float("scale").unwrap_or(1.0);
+        self.state.area_light = radiance.map(|x| x * scale);
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params) -> Result<()> {
+        let mesh = match ty {
+            "trianglemesh" | "loopsubdiv" | "bilinearmesh" => {
+                if ty == "loopsubdiv" {
+                    self.warn("'loopsubdiv' shapes aren't subdivided".to_string());
+                }
+                let positions: Vec<[f32; 3]> = params
+                    .find("P")
+                    .context("'P' is needed")?
+                    .numbers()
+                    .chunks_exact(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect();
+                let mut indices: Vec<u32> = match params.find("indices") {
+                    Some(indices) => indices.numbers().iter().map(|&i| i as u32).collect(),
+                    None if positions.len() == 3 => vec![0, 1, 2],
+                    None if positions.len() == 4 && ty == "bilinearmesh" => vec![0, 1, 2, 3],
+                    None => bail!("'indices' is needed"),
+                };
+                if ty == "bilinearmesh" {
+                    if !indices.len().is_multiple_of(4) {
+                        bail!("'indices' should be a multiple of 4");
+                    }
+                    // vertices of a patch are p00, p10, p01 and p11
+                    indices = indices
+                        .chunks_exact(4)
+                        .flat_map(|q| [q[0], q[1], q[3], q[0], q[3], q[2]])
+                        .collect();
+                }
+                let normals = match params.find("N") {
+                    Some(normals) => normals
+                        .numbers()
+                        .chunks_exact(3)
+                        .map(|n| [n[0], n[1], n[2]])
+                        .collect(),
+                    None => vec![],
+                };
+                self.meshes
+                    .push(MeshDescription::Inline(InlineMeshDescription::Triangles {
+                        positions,
+                        normals,
+                        indices,
+                    }));
+                self.meshes.len() as u32 - 1
+            }
+            "plymesh" => {
+                let file = params
+                    .string("filename")
+                    .context("'filename' is needed")?
+                    .to_string();
+                match self.mesh_files.get(&file) {
+                    Some(&mesh) => mesh,
+                    None => {
+                        self.meshes.push(MeshDescription::File(file.clone()));
+                        let mesh = self.meshes.len() as u32 - 1;
+                        self.mesh_files.insert(file, mesh);
+                        mesh
+                    }
+                }
+            }
+            _ => {
+                self.warn(format!("'{}' shapes are ignored", ty));
+                return Ok(());
+            }
+        };
+
+        if self.state.reverse_orientation && self.state.area_light != [0.0; 3] {
+            self.warn("'ReverseOrientation' of area lights is ignored".to_string());
+        }
+        let shape_material = MaterialDescription {
+            emission: self.state.area_light,
+            ..self.state.material.clone()
+        };
+        let material = match self
+            .materials
+            .iter()
+            .position(|material| *material == shape_material)
+        {
+            Some(index) => index as u32,
+            None => {
+                self.materials.push(shape_material);
+                self.materials.len() as u32 - 1
+            }
+        };
+        match &mut self.instance {
+            Some((_, shapes)) => shapes.push((mesh, material, self.state.ctm)),
+            None => self.add_object(mesh, material, self.state.ctm),
+        }
+        Ok(())
+    }
+
+    fn add_object(&mut self, mesh: u32, material: u32, transform: Matrix4<f32>) {
+        let matrix: [f32; 16] = *(world_flip() * transform).as_ref();
+        self.objects.push(ObjectDescription {
+            transform: Some(TransformDescription {
+                matrix: Some(matrix),
+                ..Default::default()
+            }),
+            mesh: Some([mesh, 0]),
+            shape: None,
+            material,
+        });
+    }
+
+    fn finish(self) -> (SceneDescription, BTreeSet<String>) {
+        let camera_to_world = world_flip() * self.world_from_camera;
+        let eye = camera_to_world.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let forward = camera_to_world.transform_vector(Vector3::unit_z());
+        let up = camera_to_world.transform_vector(Vector3::unit_y());
+        // `fov` of pbrt is of the shorter side
+        let fov = if self.height > self.width {
+            let tan = (self.fov.to_radians() * 0.5).tan() * self.height as f32 / self.width as f32;
+            2.0 * tan.atan().to_degrees()
+        } else {
+            self.fov
+        };
+
+        let description = SceneDescription {
+            include: vec![],
+            search_paths: vec![],
+            camera: CameraDescription {
+                eye: eye.into(),
+                forward: forward.into(),
+                up: up.into(),
+                fov,
+            },
+            bvh: BvhDescription::default(),
+            max_depth: self.max_depth,
+            sampler: self.sampler,
+            output: OutputDescription {
+                file: self.file,
+                width: self.width,
+                height: self.height,
+                scale: None,
+                render_mode: None,
+                display: None,
+                filter: self.filter,
+                denoise: None,
+                adaptive: None,
+                lobes: None,
+                workgroup_size: None,
+                tile_size: None,
+                resize: None,
+            },
+            materials: self.materials,
+            meshes: self.meshes,
+            objects: self.objects,
+            lights: self.lights,
+            environment: self.environment,
+        };
+        (description, self.warnings)
+    }
+}
+
+/// Perceptual roughness of `roughness` (or the mean of `uroughness` and `vroughness`),
+/// which is remapped like pbrt unless `remaproughness` is false
+fn roughness(params: &Params) -> f32 {
+    let roughness = params.float("roughness").unwrap_or_else(|| {
+        let u = params.float("uroughness").unwrap_or(0.0);
+        let v = params.float("vroughness").unwrap_or(0.0);
+        0.5 * (u + v)
+    });
+    let alpha = if params.bool("remaproughness").unwrap_or(true) {
+        roughness.sqrt()
+    } else {
+        roughness
+    };
+    alpha.sqrt()
+}
+
+fn luminance(rgb: [f32; 3]) -> f32 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+/// Planck's law at a red, a green and a blue wavelength, normalized to a maximum of 1
+fn blackbody(temperature: f32) -> [f32; 3] {
+    let planck = |lambda: f64| {
+        let c2 = 1.4388e-2;
+        1.0 / (lambda.powi(5) * ((c2 / (lambda * temperature as f64)).exp() - 1.0))
+    };
+    let rgb = [planck(610e-9), planck(550e-9), planck(465e-9)];
+    let max = rgb.iter().cloned().fold(f64::MIN, f64::max);
+    if !(max > 0.0 && max.is_finite()) {
+        return [1.0; 3];
+    }
+    rgb.map(|x| (x / max) as f32)
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '[' => tokens.push((Token::Open, line)),
+            ']' => tokens.push((Token::Close, line)),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\n')) | None => bail!("line {}: unterminated string", line),
+                        Some((_, c)) => string.push(c),
+                    }
+                }
+                tokens.push((Token::Str(string), line));
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars
+                    .next_if(|&(_, c)| !c.is_whitespace() && !matches!(c, '[' | ']' | '"' | '#'))
+                {
+                    end = i + c.len_utf8();
+                }
+                let word = &text[start..end];
+                let token = match word.parse() {
+                    Ok(number) => Token::Num(number),
+                    Err(_) => Token::Word(word.to_string()),
+                };
+                tokens.push((token, line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn statements(tokens: Vec<(Token, usize)>) -> Result<Vec<Statement>> {
+    let mut statements: Vec<Statement> = vec![];
+    let mut list: Option<Vec<Arg>> = None;
+    for (token, line) in tokens {
+        let arg = match token {
+            Token::Word(word) if word == "true" || word == "false" => Arg::Bool(word == "true"),
+            Token::Word(word) => {
+                if list.is_some() {
+                    bail!("line {}: unclosed '['", line);
+                }
+                statements.push(Statement {
+                    directive: word,
+                    line,
+                    args: vec![],
+                });
+                continue;
+            }
+            Token::Str(string) => Arg::Str(string),
+            Token::Num(number) => Arg::Num(number),
+            Token::Open => {
+                if list.replace(vec![]).is_some() {
+                    bail!("line {}: nested '['", line);
+                }
+                continue;
+            }
+            Token::Close => Arg::List(
+                list.take()
+                    .with_context(|| format!("line {}: unmatched ']'", line))?,
+            ),
+        };
+        match (&mut list, statements.last_mut()) {
+            (Some(list), _) => list.push(arg),
+            (None, Some(statement)) => statement.args.push(arg),
+            (None, None) => bail!("line {}: values before any directive", line),
+        }
+    }
+    if list.is_some() {
+        bail!("unclosed '[' at the end of the file");
+    }
+    Ok(statements)
+}
+
+/// Numbers given directly or in brackets
+fn numbers(args: &[Arg], count: usize) -> Result<Vec<f32>> {
+    let mut numbers = vec![];
+    for arg in args {
+        match arg {
+            Arg::Num(number) => numbers.push(*number as f32),
+            Arg::List(list) => {
+                for arg in list {
+                    match arg {
+                        Arg::Num(number) => numbers.push(*number as f32),
+                        _ => bail!("{} numbers are needed", count),
+                    }
+                }
+            }
+            _ => bail!("{} numbers are needed", count),
+        }
+    }
+    if numbers.len() != count {
+        bail!("{} numbers are needed", count);
+    }
+    Ok(numbers)
+}
+
+fn string_arg(args: &[Arg]) -> Result<String> {
+    match args {
+        [Arg::Str(string)] => Ok(string.clone()),
+        _ => bail!("a string is needed"),
+    }
+}
+
+/// The first string argument and the parameter list after it
+fn typed(args: &[Arg]) -> Result<(String, Params)> {
+    let (ty, rest) = match args.split_first() {
+        Some((Arg::Str(ty), rest)) => (ty.clone(), rest),
+        _ => bail!("a type name is needed"),
+    };
+    let mut params = vec![];
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        let declaration = match arg {
+            Arg::Str(declaration) => declaration,
+            _ => bail!("a parameter declaration like \"float fov\" is needed"),
+        };
+        let (ty, name) = match declaration.split_whitespace().collect::<Vec<_>>()[..] {
+            [ty, name] => (ty.to_string(), name.to_string()),
+            _ => bail!("'{}' should be like \"float fov\"", declaration),
+        };
+        let values = match rest.next() {
+            Some(Arg::List(values)) => values.clone(),
+            Some(value) => vec![value.clone()],
+            None => bail!("'{}' has no value", name),
+        };
+        params.push(Param { ty, name, values });
+    }
+    Ok((ty, Params(params)))
+}
+
+impl Param {
+    fn numbers(&self) -> Vec<f32> {
+        self.values
+            .iter()
+            .filter_map(|value| match value {
+                Arg::Num(number) => Some(*number as f32),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn string(&self) -> Option<&str> {
+        match self.values.as_slice() {
+            [Arg::Str(string)] => Some(string),
+            _ => None,
+        }
+    }
+}
+
+impl Params {
+    fn find(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|param| param.name == name)
+    }
+
+    fn float(&self, name: &str) -> Option<f32> {
+        match self.find(name)?.numbers()[..] {
+            [value] => Some(value),
+            _ => None,
+        }
+    }
+
+    fn point(&self, name: &str) -> Option<[f32; 3]> {
+        match self.find(name)?.numbers()[..] {
+            [x, y, z] => Some([x, y, z]),
+            _ => None,
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.find(name)?.string()
+    }
+
+    fn bool(&self, name: &str) -> Option<bool> {
+        match self.find(name)?.values.as_slice() {
+            [Arg::Bool(value)] => Some(*value),
+            [Arg::Str(value)] => Some(value == "true"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: [f32; 3], b: [f32; 3]) {
+        let d = Vector3::from(a) - Vector3::from(b);
+        assert!(d.magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn converts_a_scene() {
+        let text = r#"
+            LookAt 0 1 -5  0 1 0  0 1 0 # looking at +z
+            Camera "perspective" "float fov" [ 45 ]
+            Film "rgb" "integer xresolution" 400 "integer yresolution" 300
+                "string filename" "out.exr"
+            Sampler "zsobol" "integer pixelsamples" 16
+            Integrator "volpath" "integer maxdepth" [ 8 ]
+            WorldBegin
+            LightSource "point" "point3 from" [ 1 2 3 ] "rgb I" [ 4 4 4 ] "float scale" 0.5
+            LightSource "distant" "point3 from" [ 0 1 0 ] "point3 to" [ 0 0 0 ]
+            LightSource "infinite" "rgb L" [ 1 1 1 ]
+            MakeNamedMaterial "gold" "string type" "conductor"
+                "spectrum eta" "metal-Au-eta" "spectrum k" "metal-Au-k" "float roughness" 0.01
+            AttributeBegin
+              Material "diffuse" "rgb reflectance" [ 0.2 0.4 0.6 ]
+              Translate 1 0 0
+              Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ 0 1 2 ]
+            AttributeEnd
+            NamedMaterial "gold"
+            Shape "plymesh" "string filename" "meshes/a.ply"
+            Shape "plymesh" "string filename" "meshes/a.ply"
+            Shape "sphere" "float radius" 1
+        "#;
+        let (desc, warnings) = convert(text, PathBuf::new()).unwrap();
+
+        // mirrored along x
+        assert_near(desc.camera.eye, [0.0, 1.0, -5.0]);
+        assert_near(desc.camera.forward, [0.0, 0.0, 1.0]);
+        assert_near(desc.camera.up, [0.0, 1.0, 0.0]);
+        assert_eq!(desc.camera.fov, 45.0);
+        assert_eq!((desc.output.width, desc.output.height), (400, 300));
+        assert_eq!(desc.output.file.as_deref(), Some("out.exr"));
+        assert_eq!(desc.sampler.as_deref(), Some("sobol"));
+        assert_eq!(desc.max_depth, 8);
+
+        assert_eq!(
+            desc.lights,
+            vec![
+                LightDescription::Point {
+                    position: [-1.0, 2.0, 3.0],
+                    strength: [2.0, 2.0, 2.0]
+                },
+                LightDescription::Directional {
+                    direction: [0.0, -1.0, 0.0],
+                    strength: [1.0, 1.0, 1.0]
+                }
+            ]
+        );
+
+        assert_eq!(desc.materials.len(), 2);
+        assert_eq!(desc.materials[0].albedo, [0.2, 0.4, 0.6]);
+        assert_eq!(desc.materials[1].albedo, METALS[2].1);
+        assert_eq!(desc.materials[1].metallic, 1.0);
+        assert!((desc.materials[1].roughness - 0.1f32.sqrt()).abs() < 1e-6);
+
+        assert_eq!(desc.meshes.len(), 2);
+        assert_eq!(
+            desc.meshes[1],
+            MeshDescription::File("meshes/a.ply".to_string())
+        );
+        let objects: Vec<_> = desc.objects.iter().map(|o| (o.mesh, o.material)).collect();
+        assert_eq!(
+            objects,
+            vec![(Some([0, 0]), 0), (Some([1, 0]), 1), (Some([1, 0]), 1)]
+        );
+        let matrix = desc.objects[0].transform.as_ref().unwrap().matrix.unwrap();
+        assert_eq!(matrix[12..15], [-1.0, 0.0, 0.0]);
+
+        assert_eq!(desc.environment, Some([1.0, 1.0, 1.0]));
+        assert!(warnings.contains("'sphere' shapes are ignored"));
+    }
+
+    #[test]
+    fn area_lights_emit() {
+        let text = r#"
+            WorldBegin
+            LightSource "infinite" "rgb L" [ 0.1 0.2 0.3 ]
+            LightSource "infinite" "rgb L" [ 0.1 0.1 0.1 ] "float scale" 2
+            AttributeBegin
+              AreaLightSource "diffuse" "rgb L" [ 4 3 2 ] "float scale" 0.5
+              Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
+            AttributeEnd
+            Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
+        "#;
+        let (desc, warnings) = convert(text, PathBuf::new()).unwrap();
+        assert_near(desc.environment.unwrap(), [0.3, 0.4, 0.5]);
+        assert_eq!(desc.materials.len(), 2);
+        assert_eq!(desc.materials[0].emission, [2.0, 1.5, 1.0]);
+        assert_eq!(desc.materials[1], default_material());
+        let materials: Vec<_> = desc.objects.iter().map(|o| o.material).collect();
+        assert_eq!(materials, vec![0, 1]);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn object_instances() {
+        let text = r#"
+            WorldBegin
+            ObjectBegin "tri"
+              Translate 0 0 1
+              Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ]
+            ObjectEnd
+            Translate 0 2 0
+            ObjectInstance "tri"
+            ObjectInstance "tri"
+        "#;
+        let (desc, _) = convert(text, PathBuf::new()).unwrap();
+        assert_eq!(desc.meshes.len(), 1);
+        assert_eq!(desc.objects.len(), 2);
+        let matrix = desc.objects[1].transform.as_ref().unwrap().matrix.unwrap();
+        assert_eq!(matrix[12..15], [0.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn errors_tell_the_line() {
+        let err = convert("WorldBegin\n\nFoo 1 2", PathBuf::new()).unwrap_err();
+        assert_eq!(format!("{:#}", err), "line 3: 'Foo': unknown directive");
+        let err = convert("Translate 1 2", PathBuf::new()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "line 1: 'Translate': 3 numbers are needed"
+        );
+        let err = convert(
+            "Shape \"bilinearmesh\" \"point3 P\" [ 0 0 0  1 0 0  0 1 0  1 1 0 ] \"integer indices\" [ 0 1 2 ]",
+            PathBuf::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "line 1: 'Shape': 'indices' should be a multiple of 4"
+        );
+        assert!(convert(
+            "Shape \"trianglemesh\" \"point3 P\" [ 0 0 0",
+            PathBuf::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = std::env::temp_dir().join(format!("spt-pbrt-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.pbrt"), "Include \"b.pbrt\"").unwrap();
+        std::fs::write(dir.join("b.pbrt"), "Include \"a.pbrt\"").unwrap();
+        let result = convert("Include \"a.pbrt\"", dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let err = result.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("includes itself"),
+            "{:#}",
+            err
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::{convert::TryInto, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+use super::ModelData;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Property {
+    Scalar(ScalarType, String),
+    /// type of the count and of the items
+    List(ScalarType, ScalarType, String),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+/// Vertex positions, normals (if all of `nx`, `ny` and `nz` are there) and faces of an
+/// ASCII or binary PLY file, faces with more than 3 vertices are split into fans
+pub(super) fn load_ply(path: &Path) -> Result<ModelData> {
+    let data =
+        std::fs::read(path).with_context(|| format!("failed to open '{}'", path.display()))?;
+    parse_ply(&data).with_context(|| format!("failed to load '{}'", path.display()))
+}
+
+fn parse_ply(data: &[u8]) -> Result<ModelData> {
+    let (elements, mut body) = parse_header(data)?;
+
+    let mut model = ModelData {
+        positions: vec![],
+        normals: vec![],
+        indices: vec![],
+    };
+    let mut vertex_count = 0;
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                vertex_count = element.count;
+                read_vertices(element, &mut body, &mut model)?;
+            }
+            "face" => read_faces(element, &mut body, &mut model)?,
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        skip_property(property, &mut body)?;
+                    }
+                }
+            }
+        }
+    }
+    if let Some(index) = model
+        .indices
+        .iter()
+        .find(|&&index| index as usize >= vertex_count)
+    {
+        bail!("vertex {} doesn't exist", index);
+    }
+    Ok(model)
+}
+
+fn parse_header(data: &[u8]) -> Result<(Vec<Element>, Body<'_>)> {
+    let mut lines = vec![];
+    let mut pos = 0;
+    loop {
+        let end = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .context("no 'end_header'")?;
+        let line = std::str::from_utf8(&data[pos..pos + end])
+            .context("header should be ASCII")?
+            .trim();
+        pos += end + 1;
+        if line == "end_header" {
+            break;
+        }
+        lines.push(line);
+    }
+
+    if lines.first() != Some(&"ply") {
+        bail!("not a PLY file");
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for line in &lines[1..] {
+        let words: Vec<_> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", format_name, _] => format = Some(*format_name),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().context("element count should be an int")?,
+                properties: vec![],
+            }),
+            ["property", "list", count_type, item_type, name] => elements
+                .last_mut()
+                .context("property before any element")?
+                .properties
+                .push(Property::List(
+                    scalar_type(count_type)?,
+                    scalar_type(item_type)?,
+                    name.to_string(),
+                )),
+            ["property", ty, name] => elements
+                .last_mut()
+                .context("property before any element")?
+                .properties
+                .push(Property::Scalar(scalar_type(ty)?, name.to_string())),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => bail!("unknown header line '{}'", line),
+        }
+    }
+
+    let body = match format {
+        Some("ascii") => Body::Ascii(
+            std::str::from_utf8(&data[pos..])
+                .context("ASCII body should be ASCII")?
+                .split_ascii_whitespace(),
+        ),
+        Some("binary_little_endian") => Body::Binary {
+            data,
+            pos,
+            big_endian: false,
+        },
+        Some("binary_big_endian") => Body::Binary {
+            data,
+            pos,
+            big_endian: true,
+        },
+        Some(format) => bail!("unknown format '{}'", format),
+        None => bail!("no 'format' line"),
+    };
+    Ok((elements, body))
+}
+
+fn scalar_type(name: &str) -> Result<ScalarType> {
+    Ok(match name {
+        "char" | "int8" => ScalarType::I8,
+        "uchar" | "uint8" => ScalarType::U8,
+        "short" | "int16" => ScalarType::I16,
+        "ushort" | "uint16" => ScalarType::U16,
+        "int" | "int32" => ScalarType::I32,
+        "uint" | "uint32" => ScalarType::U32,
+        "float" | "float32" => ScalarType::F32,
+        "double" | "float64" => ScalarType::F64,
+        _ => bail!("unknown property type '{}'", name),
+    })
+}
+
+fn read_vertices(element: &Element, body: &mut Body, model: &mut ModelData) -> Result<()> {
+    let find = |name: &str| {
+        element
+            .properties
+            .iter()
+            .position(|property| matches!(property, Property::Scalar(_, n) if n == name))
+    };
+    let position = [find("x"), find("y"), find("z")];
+    if position.iter().any(Option::is_none) {
+        bail!("vertices should have 'x', 'y' and 'z'");
+    }
+    let normal = [find("nx"), find("ny"), find("nz")];
+    let has_normals = normal.iter().all(Option::is_some);
+
+    let mut values = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (value, property) in values.iter_mut().zip(&element.properties) {
+            match property {
+                Property::Scalar(ty, _) => *value = body.read(*ty)?,
+                Property::List(..) => skip_property(property, body)?,
+            }
+        }
+        model
+            .positions
+            .extend(position.iter().map(|i| values[i.unwrap()] as f32));
+        if has_normals {
+            model
+                .normals
+                .extend(normal.iter().map(|i| values[i.unwrap()] as f32));
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(element: &Element, body: &mut Body, model: &mut ModelData) -> Result<()> {
+    let mut face = vec![];
+    for _ in 0..element.count {
+        for property in &element.properties {
+            match property {
+                Property::List(count_type, item_type, name)
+                    if name == "vertex_indices" || name == "vertex_index" =>
+                {
+                    let count = body.read(*count_type)? as usize;
+                    face.clear();
+                    for _ in 0..count {
+                        face.push(body.read(*item_type)? as u32);
+                    }
+                    for i in 1..count.saturating_sub(1) {
+                        model.indices.extend([face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => skip_property(property, body)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_property(property: &Property, body: &mut Body) -> Result<()> {
+    match property {
+        Property::Scalar(ty, _) => {
+            body.read(*ty)?;
+        }
+        Property::List(count_type, item_type, _) => {
+            let count = body.read(*count_type)? as usize;
+            for _ in 0..count {
+                body.read(*item_type)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64> {
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().context("unexpected end of file")?;
+                word.parse()
+                    .with_context(|| format!("'{}' should be a number", word))
+            }
+            Body::Binary {
+                data,
+                pos,
+                big_endian,
+            } => {
+                macro_rules! read {
+                    ($t:ty) => {{
+                        let size = std::mem::size_of::<$t>();
+                        let bytes = data
+                            .get(*pos..*pos + size)
+                            .context("unexpected end of file")?;
+                        *pos += size;
+                        let bytes = bytes.try_into().unwrap();
+                        if *big_endian {
+                            <$t>::from_be_bytes(bytes) as f64
+                        } else {
+                            <$t>::from_le_bytes(bytes) as f64
+                        }
+                    }};
+                }
+                Ok(match ty {
+                    ScalarType::I8 => read!(i8),
+                    ScalarType::U8 => read!(u8),
+                    ScalarType::I16 => read!(i16),
+                    ScalarType::U16 => read!(u16),
+                    ScalarType::I32 => read!(i32),
+                    ScalarType::U32 => read!(u32),
+                    ScalarType::F32 => read!(f32),
+                    ScalarType::F64 => read!(f64),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_quads_are_split() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   comment a unit quad\n\
+                   element vertex 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n1 0 0\n1 1 0\n0 1 0\n\
+                   4 0 1 2 3\n";
+        let model = parse_ply(ply.as_bytes()).unwrap();
+        assert_eq!(model.positions.len(), 12);
+        assert!(model.normals.is_empty());
+        assert_eq!(model.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let mut ply = b"ply\n\
+                        format binary_little_endian 1.0\n\
+                        element vertex 3\n\
+                        property float x\n\
+                        property float y\n\
+                        property float z\n\
+                        property float nx\n\
+                        property float ny\n\
+                        property float nz\n\
+                        property uchar red\n\
+                        element face 1\n\
+                        property uchar flags\n\
+                        property list uchar uint vertex_indices\n\
+                        end_header\n"
+            .to_vec();
+        for (position, red) in [
+            ([0.0f32, 0.0, 0.0], 1u8),
+            ([1.0, 0.0, 0.0], 2),
+            ([0.0, 1.0, 0.0], 3),
+        ] {
+            for x in position.iter().chain(&[0.0, 0.0, 1.0]) {
+                ply.extend(x.to_le_bytes());
+            }
+            ply.push(red);
+        }
+        ply.extend([7, 3]);
+        for index in [2u32, 1, 0] {
+            ply.extend(index.to_le_bytes());
+        }
+
+        let model = parse_ply(&ply).unwrap();
+        assert_eq!(
+            model.positions,
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        );
+        assert_eq!(model.normals[6..], [0.0, 0.0, 1.0]);
+        assert_eq!(model.indices, vec![2, 1, 0]);
+
+        ply.truncate(ply.len() - 1);
+        assert!(parse_ply(&ply).is_err());
+    }
+}
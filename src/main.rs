@@ -1,21 +1,145 @@
 mod core;
+mod cpu;
+mod gui;
 mod loader;
 mod opengl;
+mod output;
 mod renderer;
 mod uniforms;
 
 use glfw::Context;
 
+/// Distance the camera moves per key press
+const CAMERA_STEP: f32 = 0.1;
+
+/// Interval between updates of the stats in the window title
+const TITLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Keys that show the radiance and then each of `Aov::ALL` in the window
+const VIEW_KEYS: [glfw::Key; 7] = [
+    glfw::Key::Num1,
+    glfw::Key::Num2,
+    glfw::Key::Num3,
+    glfw::Key::Num4,
+    glfw::Key::Num5,
+    glfw::Key::Num6,
+    glfw::Key::Num7,
+];
+
+struct Args {
+    scene_path: String,
+    cpu_spp: Option<u32>,
+    headless_spp: Option<u32>,
+    size: Option<(u32, u32)>,
+    output: Option<String>,
+    search_paths: Vec<std::path::PathBuf>,
+}
+
+/// Returns `Ok(None)` if the usage should be printed
+fn parse_args() -> anyhow::Result<Option<Args>> {
+    let mut args = std::env::args().skip(1);
+    let mut scene_path = None;
+    let mut cpu_spp = None;
+    let mut headless_spp = None;
+    let mut size = None;
+    let mut output = None;
+    let mut search_paths = vec![];
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--cpu" => cpu_spp = Some(value()?.parse::<u32>()?),
+            "--headless" => headless_spp = Some(value()?.parse::<u32>()?),
+            "--size" => {
+                let value = value()?;
+                let (width, height) = value
+                    .split_once('x')
+                    .ok_or_else(|| anyhow::anyhow!("--size should be like '800x600'"))?;
+                size = Some((width.parse::<u32>()?, height.parse::<u32>()?));
+            }
+            "--output" => output = Some(value()?),
+            "--search-path" => search_paths.push(std::env::current_dir()?.join(value()?)),
+            _ if arg.starts_with("--") || scene_path.is_some() => return Ok(None),
+            _ => scene_path = Some(arg),
+        }
+    }
+
+    Ok(scene_path.map(|scene_path| Args {
+        scene_path,
+        cpu_spp,
+        headless_spp,
+        size,
+        output,
+        search_paths,
+    }))
+}
+
 fn main() -> anyhow::Result<()> {
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    if args.len() != 1 {
-        println!("Usage: simple-path-tracer-gl <path-to-json>");
-        return Ok(());
+    let args = match parse_args()? {
+        Some(args) => args,
+        None => {
+            println!("Usage: simple-path-tracer-gl [options] <path-to-json>");
+            println!(
+                "  --cpu <spp>       render <spp> samples per pixel with the CPU reference renderer"
+            );
+            println!(
+                "  --headless <spp>  render <spp> samples per pixel on the GPU in a hidden window"
+            );
+            println!("  --size <w>x<h>    override the output size of the scene");
+            println!("  --output <file>   override the output file of the scene");
+            println!("  --search-path <dir>  look for included files and meshes in <dir> too");
+            println!("While the window is open, press 'S' to save the image accumulated so far,");
+            println!("'Shift+S' saves the edited scene next to the scene file,");
+            println!("arrow keys and PageUp/PageDown move the camera,");
+            println!("'1' shows the image and '2' to '7' show albedo, normal, depth, position,");
+            println!("object id and material id, 'F1' shows or hides the scene editor");
+            return Ok(());
+        }
+    };
+
+    let mut description = loader::load_description(&args.scene_path)?;
+    let (mut output_config, scene) =
+        loader::build(&args.scene_path, &description, &args.search_paths)?;
+    if let Some((width, height)) = args.size {
+        output_config.width = width;
+        output_config.height = height;
+    }
+    if let Some(output) = args.output {
+        output_config.file = output;
+    }
+
+    if let Some(spp) = args.cpu_spp {
+        let start = std::time::Instant::now();
+        let cpu_renderer = cpu::CpuRenderer::new(&scene);
+        let (image, lobes) = cpu_renderer.render(
+            output_config.width,
+            output_config.height,
+            spp,
+            &output_config.filter,
+            &output_config.lobes,
+        );
+        println!("CPU rendering takes {:.3}s", start.elapsed().as_secs_f32());
+        let mut layers = cpu_renderer.render_aovs(output_config.width, output_config.height);
+        layers.extend(lobes);
+        return output::save_image(
+            output_config.file_name(&format!("cpu_{}spp", spp)),
+            output_config.width,
+            output_config.height,
+            &image,
+            &layers,
+            &output_config.display,
+        );
     }
 
-    let mut renderer = loader::load(&args[0])?;
+    let mut renderer = renderer::Renderer::new(output_config, &scene);
 
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    if args.headless_spp.is_some() {
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+    }
 
     let (mut window, events) = glfw
         .create_window(
@@ -57,24 +181,170 @@ fn main() -> anyhow::Result<()> {
     }
 
     window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_char_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_cursor_enter_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_scroll_polling(true);
     window.make_current();
 
     renderer.init();
 
+    if let Some(spp) = args.headless_spp {
+        let start = std::time::Instant::now();
+        // with adaptive sampling `spp` is the maximum, it stops once all pixels are converged
+        while renderer.samples() < spp {
+            renderer.render();
+            if renderer.converged_ratio() == Some(1.0) {
+                break;
+            }
+        }
+        let image = renderer.read_output_image();
+        println!("GPU rendering takes {:.3}s", start.elapsed().as_secs_f32());
+        if let Some(ratio) = renderer.converged_ratio() {
+            println!(
+                "{} frames are rendered, {:.1}% of the pixels are converged",
+                renderer.samples(),
+                ratio * 100.0
+            );
+        }
+        let config = &renderer.output_config;
+        return output::save_image(
+            config.file_name(&format!("{}spp", renderer.samples())),
+            config.width,
+            config.height,
+            &image,
+            &renderer.read_layers(),
+            &config.display,
+        );
+    }
+
+    let mut gui = gui::Gui::new(&mut window)?;
+    // picking traces single rays on the CPU
+    let mut picker = cpu::CpuRenderer::new(&scene);
+    let mut selection: Option<cpu::Pick> = None;
+    let mut title_time = std::time::Instant::now();
     while !window.should_close() {
         glfw.poll_events();
         window.swap_buffers();
 
         renderer.render();
+        gui.frame(&window, |context| {
+            gui::scene_editor(context, &mut renderer, selection.as_ref())
+        });
+        if title_time.elapsed() >= TITLE_INTERVAL {
+            window.set_title(&renderer.stats().title());
+            title_time = std::time::Instant::now();
+        }
 
+        // only the last size matters when the window is dragged
+        let mut framebuffer_size = None;
         for (_, event) in glfw::flush_messages(&events) {
+            if let glfw::WindowEvent::Key(glfw::Key::F1, _, glfw::Action::Press, _) = event {
+                gui.visible = !gui.visible;
+                continue;
+            }
+            if gui.handle_event(&event) {
+                continue;
+            }
             match event {
+                glfw::WindowEvent::MouseButton(glfw::MouseButtonLeft, glfw::Action::Press, _) => {
+                    // cursor positions are in screen coordinates, which may differ from pixels
+                    let (cursor_x, cursor_y) = window.get_cursor_pos();
+                    let (window_width, _) = window.get_size();
+                    let (fb_width, _) = window.get_framebuffer_size();
+                    let scale = fb_width as f64 / window_width.max(1) as f64;
+                    let config = &renderer.output_config;
+                    selection = renderer
+                        .image_pixel(cursor_x * scale, cursor_y * scale)
+                        .and_then(|(x, y)| {
+                            picker.set_camera(*renderer.camera());
+                            picker.pick(x, y, config.width, config.height)
+                        });
+                    match &selection {
+                        Some(pick) => println!(
+                            "Picked object {}, material {}, triangle {} at ({:.3}, {:.3}, {:.3}), \
+                             distance {:.3}",
+                            pick.object_index,
+                            pick.material_index,
+                            pick.triangle_index,
+                            pick.position.x,
+                            pick.position.y,
+                            pick.position.z,
+                            pick.distance
+                        ),
+                        None => println!("Nothing is picked"),
+                    }
+                    renderer.set_selected_object(selection.map(|pick| pick.object_index));
+                }
+                glfw::WindowEvent::FramebufferSize(width, height) => {
+                    framebuffer_size = Some((width.max(0) as u32, height.max(0) as u32));
+                }
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true);
                 }
+                glfw::WindowEvent::Key(glfw::Key::S, _, glfw::Action::Press, mods)
+                    if mods.contains(glfw::Modifiers::Shift) =>
+                {
+                    description.update(
+                        renderer.camera(),
+                        renderer.max_depth(),
+                        renderer.materials(),
+                        renderer.lights(),
+                    );
+                    let path = std::path::Path::new(&args.scene_path);
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    let edited_path = path.with_file_name(format!("{}_edited.json", stem));
+                    if let Err(err) = description.save(edited_path) {
+                        println!("Failed to save scene: {:#}", err);
+                    }
+                }
+                glfw::WindowEvent::Key(glfw::Key::S, _, glfw::Action::Press, _) => {
+                    let config = &renderer.output_config;
+                    let path = config.file_name(&format!("{}spp", renderer.samples()));
+                    let image = renderer.read_output_image();
+                    if let Err(err) = output::save_image(
+                        path,
+                        config.width,
+                        config.height,
+                        &image,
+                        &renderer.read_layers(),
+                        &config.display,
+                    ) {
+                        println!("Failed to save image: {}", err);
+                    }
+                }
+                glfw::WindowEvent::Key(key, _, glfw::Action::Press | glfw::Action::Repeat, _) => {
+                    if let Some(index) = VIEW_KEYS.iter().position(|&view_key| view_key == key) {
+                        let view = index.checked_sub(1).map(|i| output::Aov::ALL[i]);
+                        println!(
+                            "Showing {}",
+                            view.map_or("the rendered image", output::Aov::name)
+                        );
+                        renderer.set_view(view);
+                        continue;
+                    }
+
+                    let mut camera = *renderer.camera();
+                    let offset = match key {
+                        glfw::Key::Up => camera.forward,
+                        glfw::Key::Down => -camera.forward,
+                        glfw::Key::Right => camera.right,
+                        glfw::Key::Left => -camera.right,
+                        glfw::Key::PageUp => camera.up,
+                        glfw::Key::PageDown => -camera.up,
+                        _ => continue,
+                    };
+                    camera.eye += offset * CAMERA_STEP;
+                    renderer.set_camera(camera);
+                }
                 _ => {}
             }
         }
+        if let Some((width, height)) = framebuffer_size {
+            renderer.resize(width, height);
+        }
     }
 
     Ok(())
@@ -12,6 +12,7 @@ pub struct OpenglContext {
     shaders_map: HashMap<Uuid, OpenglShader>,
     graphics_pipeline_map: HashMap<Uuid, OpenglGraphicsPipeline>,
     compute_pipeline_map: HashMap<Uuid, OpenglComputePipeline>,
+    query_map: HashMap<Uuid, OpenglQuery>,
     state: OpenglState,
 }
 
@@ -31,6 +32,7 @@ impl OpenglContext {
             shaders_map: HashMap::new(),
             graphics_pipeline_map: HashMap::new(),
             compute_pipeline_map: HashMap::new(),
+            query_map: HashMap::new(),
             state: OpenglState::default(),
         }
     }
@@ -39,12 +41,17 @@ impl OpenglContext {
 impl OpenglContext {
     pub fn create_buffer(&mut self, info: BufferInfo, data: Option<&[u8]>) -> Rc<Buffer> {
         let mut id: GLuint = 0;
+        let flags = if info.dynamic {
+            gl::DYNAMIC_STORAGE_BIT
+        } else {
+            0
+        };
         unsafe {
             gl::CreateBuffers(1, &mut id as *mut _);
             if let Some(data) = data {
-                gl::NamedBufferStorage(id, info.size as _, data.as_ptr() as *const _, 0);
+                gl::NamedBufferStorage(id, info.size as _, data.as_ptr() as *const _, flags);
             } else {
-                gl::NamedBufferStorage(id, info.size as _, std::ptr::null(), 0);
+                gl::NamedBufferStorage(id, info.size as _, std::ptr::null(), flags);
             }
         }
 
@@ -56,6 +63,41 @@ impl OpenglContext {
         buffer
     }
 
+    pub fn update_buffer(&mut self, buf: &Rc<Buffer>, offset: u32, data: &[u8]) {
+        assert!(buf.info.dynamic, "OpenGL, buffer is not dynamic");
+        assert!(offset as usize + data.len() <= buf.info.size as usize);
+        let gl_buf = self.buffer_map.get(&buf.id).unwrap();
+
+        unsafe {
+            gl::NamedBufferSubData(
+                gl_buf.id,
+                offset as _,
+                data.len() as _,
+                data.as_ptr() as *const _,
+            );
+        }
+    }
+
+    /// Reads back `size` bytes from `offset`
+    pub fn read_buffer(&self, buf: &Rc<Buffer>, offset: u32, size: u32) -> Vec<u8> {
+        assert!(offset + size <= buf.info.size);
+        let gl_buf = self.buffer_map.get(&buf.id).unwrap();
+        let mut data = vec![0u8; size as usize];
+
+        unsafe {
+            // make storage buffer writes from compute shaders visible to the read back
+            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+            gl::GetNamedBufferSubData(
+                gl_buf.id,
+                offset as _,
+                size as _,
+                data.as_mut_ptr() as *mut _,
+            );
+        }
+
+        data
+    }
+
     pub fn create_texture(&mut self, mut info: TextureInfo) -> Rc<Texture> {
         if info.mips == 0 {
             info.mips = info.max_mips();
@@ -67,7 +109,16 @@ impl OpenglContext {
         let image_type = info.ty;
         unsafe {
             gl::CreateTextures(image_type, 1, &mut id as *mut _);
-            if info.samples == 1 {
+            if image_type == gl::TEXTURE_2D_ARRAY {
+                gl::TextureStorage3D(
+                    id,
+                    info.mips as _,
+                    internal_format,
+                    info.width as _,
+                    info.height as _,
+                    info.layers as _,
+                );
+            } else if info.samples == 1 {
                 gl::TextureStorage2D(
                     id,
                     info.mips as _,
@@ -102,6 +153,89 @@ impl OpenglContext {
         texture
     }
 
+    /// Frees the storage of a texture, it can't be used any more
+    pub fn delete_texture(&mut self, tex: &Rc<Texture>) {
+        if let Some(gl_tex) = self.texture_map.remove(&tex.id) {
+            unsafe {
+                gl::DeleteTextures(1, &gl_tex.id as *const _);
+            }
+        }
+    }
+
+    /// Uploads a whole mip level of a 2D texture, `data` is in the texture's own pixel format and type
+    pub fn update_texture(&mut self, tex: &Rc<Texture>, mip: u32, data: &[u8]) {
+        let gl_tex = self.texture_map.get(&tex.id).unwrap();
+        let width = (tex.info.width >> mip).max(1);
+        let height = (tex.info.height >> mip).max(1);
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TextureSubImage2D(
+                gl_tex.id,
+                mip as _,
+                0,
+                0,
+                width as _,
+                height as _,
+                gl_tex.pixel_format,
+                gl_tex.pixel_type,
+                data.as_ptr() as *const _,
+            );
+        }
+    }
+
+    /// Reads back a whole mip level (all layers of it) in the texture's own pixel format and type
+    pub fn read_texture(&self, tex: &Rc<Texture>, mip: u32) -> Vec<u8> {
+        let gl_tex = self.texture_map.get(&tex.id).unwrap();
+        let width = (tex.info.width >> mip).max(1) as usize;
+        let height = (tex.info.height >> mip).max(1) as usize;
+        let layers = tex.info.layers.max(1) as usize;
+        let size = width * height * layers * utils::get_pixel_size(gl_tex.internal_format);
+        let mut data = vec![0u8; size];
+
+        unsafe {
+            // make image stores from compute shaders visible to the read back
+            gl::MemoryBarrier(gl::TEXTURE_UPDATE_BARRIER_BIT);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::GetTextureImage(
+                gl_tex.id,
+                mip as _,
+                gl_tex.pixel_format,
+                gl_tex.pixel_type,
+                size as _,
+                data.as_mut_ptr() as *mut _,
+            );
+        }
+
+        data
+    }
+
+    /// Copies mip 0 (all layers of it) between textures of the same size and format
+    pub fn copy_texture(&self, src: &Rc<Texture>, dst: &Rc<Texture>) {
+        let gl_src = self.texture_map.get(&src.id).unwrap();
+        let gl_dst = self.texture_map.get(&dst.id).unwrap();
+
+        unsafe {
+            gl::CopyImageSubData(
+                gl_src.id,
+                gl_src.image_type,
+                0,
+                0,
+                0,
+                0,
+                gl_dst.id,
+                gl_dst.image_type,
+                0,
+                0,
+                0,
+                0,
+                src.info.width as _,
+                src.info.height as _,
+                src.info.layers.max(1) as _,
+            );
+        }
+    }
+
     pub fn create_sampler(&mut self, info: SamplerInfo) -> Rc<Sampler> {
         let mut id: GLuint = 0;
         unsafe {
@@ -187,6 +321,57 @@ impl OpenglContext {
         compute_pipeline
     }
 
+    pub fn create_query(&mut self, info: QueryInfo) -> Rc<Query> {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::CreateQueries(info.target, 1, &mut id as *mut _);
+        }
+
+        let query = Rc::new(Query::new(info));
+        let opengl_query = OpenglQuery { id };
+
+        self.query_map.insert(query.id, opengl_query);
+
+        query
+    }
+
+    /// Starts counting the commands that follow, only one query of a target can be active
+    pub fn begin_query(&self, query: &Rc<Query>) {
+        let gl_query = self.query_map.get(&query.id).unwrap();
+
+        unsafe {
+            gl::BeginQuery(query.info.target, gl_query.id);
+        }
+    }
+
+    pub fn end_query(&self, query: &Rc<Query>) {
+        unsafe {
+            gl::EndQuery(query.info.target);
+        }
+    }
+
+    /// Result of the last ended query (nanoseconds for `gl::TIME_ELAPSED`), `None` if the
+    /// GPU hasn't got there yet, it never waits
+    pub fn query_result(&self, query: &Rc<Query>) -> Option<u64> {
+        let gl_query = self.query_map.get(&query.id).unwrap();
+
+        let mut available: GLint = 0;
+        let mut result: GLuint64 = 0;
+        unsafe {
+            gl::GetQueryObjectiv(
+                gl_query.id,
+                gl::QUERY_RESULT_AVAILABLE,
+                &mut available as *mut _,
+            );
+            if available == 0 {
+                return None;
+            }
+            gl::GetQueryObjectui64v(gl_query.id, gl::QUERY_RESULT, &mut result as *mut _);
+        }
+
+        Some(result)
+    }
+
     pub fn bind_graphics_pipeline(&mut self, pipeline: &Rc<GraphicsPipeline>) {
         let gl_pipeline = self.graphics_pipeline_map.get(&pipeline.id).unwrap();
 
@@ -334,6 +519,21 @@ impl OpenglContext {
         }
     }
 
+    /// Area of the default framebuffer draws go to, `x` and `y` are from the bottom left
+    pub fn set_viewport(&self, x: i32, y: i32, width: u32, height: u32) {
+        unsafe {
+            gl::Viewport(x, y, width as _, height as _);
+        }
+    }
+
+    /// Clears the whole color buffer of the default framebuffer
+    pub fn clear(&self, color: [f32; 4]) {
+        unsafe {
+            gl::ClearColor(color[0], color[1], color[2], color[3]);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+    }
+
     pub fn draw(&self, num_vertices: u32) {
         let pipeline = self.state.graphics_pipeline.as_ref().unwrap();
 
@@ -362,8 +562,19 @@ impl OpenglContext {
             gl::DispatchCompute(num_groups.0, num_groups.1, num_groups.2);
 
             if wait {
-                gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+                gl::MemoryBarrier(
+                    gl::SHADER_IMAGE_ACCESS_BARRIER_BIT
+                        | gl::TEXTURE_FETCH_BARRIER_BIT
+                        | gl::TEXTURE_UPDATE_BARRIER_BIT,
+                );
             }
         }
     }
+
+    /// Submits the queued commands without waiting for them
+    pub fn flush(&self) {
+        unsafe {
+            gl::Flush();
+        }
+    }
 }
@@ -31,6 +31,8 @@ macro_rules! resource_defination {
 
 pub struct BufferInfo {
     pub size: u32,
+    /// whether the buffer can be updated with `OpenglContext::update_buffer`
+    pub dynamic: bool,
 }
 
 pub struct OpenglBuffer {
@@ -40,6 +42,8 @@ pub struct OpenglBuffer {
 pub struct TextureInfo {
     pub width: u32,
     pub height: u32,
+    /// only used by array textures, 1 otherwise
+    pub layers: u32,
     pub mips: u8,
     pub samples: u8,
     pub format: GLenum,
@@ -128,6 +132,15 @@ pub struct OpenglGraphicsPipeline {
     pub vao: GLuint,
 }
 
+pub struct QueryInfo {
+    /// e.g. `gl::TIME_ELAPSED`
+    pub target: GLenum,
+}
+
+pub struct OpenglQuery {
+    pub id: GLuint,
+}
+
 resource_defination! {
     (Buffer, BufferInfo),
     (Texture, TextureInfo),
@@ -135,6 +148,7 @@ resource_defination! {
     (Shader, ShaderInfo),
     (ComputePipeline, ComputePipelineInfo),
     (GraphicsPipeline, GraphicsPipelineInfo),
+    (Query, QueryInfo),
 }
 
 fn log2_floor(x: u32) -> u32 {
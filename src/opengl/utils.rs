@@ -44,6 +44,23 @@ pub fn get_format_and_type(internal_format: GLenum) -> (GLenum, GLenum) {
     }
 }
 
+pub fn get_pixel_size(internal_format: GLenum) -> usize {
+    let (pixel_format, pixel_type) = get_format_and_type(internal_format);
+    let channels = match pixel_format {
+        gl::RED | gl::DEPTH_COMPONENT => 1,
+        gl::RG | gl::DEPTH_STENCIL => 2,
+        gl::RGB => 3,
+        _ => 4,
+    };
+    match pixel_type {
+        gl::UNSIGNED_BYTE | gl::BYTE => channels,
+        gl::UNSIGNED_SHORT | gl::SHORT | gl::HALF_FLOAT => 2 * channels,
+        gl::UNSIGNED_INT | gl::INT | gl::FLOAT => 4 * channels,
+        gl::FLOAT_32_UNSIGNED_INT_24_8_REV => 8,
+        _ => 4, // packed 32-bit formats
+    }
+}
+
 pub fn get_vertex_attribute_size_and_type(format: VertexAttributeFormat) -> (GLint, GLenum) {
     match format {
         VertexAttributeFormat::Float => (1, gl::FLOAT),
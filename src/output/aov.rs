@@ -0,0 +1,58 @@
+use super::ImageLayer;
+
+/// First hit data saved next to the radiance, the values match `VIEW_*` - 1 in `post.frag`.
+/// Pixels where nothing is hit have a depth and ids of -1 and all other channels 0.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    Albedo = 0,
+    /// face-forwarded shading normal in world space
+    Normal = 1,
+    /// distance along the camera ray
+    Depth = 2,
+    /// world space position
+    Position = 3,
+    /// index of the object in the scene file
+    ObjectId = 4,
+    MaterialId = 5,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+
+    /// Layer name in multi-layer EXR files
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["X"],
+        }
+    }
+
+    /// `data` has the interleaved `channels` of every pixel
+    pub fn layer(self, data: Vec<f32>) -> ImageLayer {
+        ImageLayer {
+            name: self.name(),
+            channels: self.channels(),
+            data,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use cgmath::{ElementWise, Matrix, Matrix3, SquareMatrix, Vector3};
+
+/// Tone mapping curves, the values match `TONE_MAPPER_*` in `post.frag`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapper {
+    None = 0,
+    Reinhard = 1,
+    AcesFitted = 2,
+    Agx = 3,
+    Filmic = 4,
+}
+
+/// Transform from traced radiance to displayed colors, applied by `post.frag` and when
+/// saving LDR images. Keep `DisplayConfig::apply` in sync with `post.frag`.
+#[derive(Clone, Copy)]
+pub struct DisplayConfig {
+    /// exposure in EV, radiance is scaled by 2^exposure
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    /// color temperature (K) of the illuminant that is displayed as white
+    pub white_balance: Option<f32>,
+    /// encode the output with the sRGB transfer function, otherwise it stays linear
+    pub srgb: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_mapper: ToneMapper::None,
+            white_balance: None,
+            srgb: true,
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Shows the traced values as they are, used by the debug render modes
+    pub fn passthrough() -> Self {
+        Self {
+            srgb: false,
+            ..Default::default()
+        }
+    }
+
+    pub fn exposure_scale(&self) -> f32 {
+        self.exposure.exp2()
+    }
+
+    /// Linear sRGB matrix adapting `white_balance` to the white of 6504K (Bradford transform)
+    pub fn white_balance_matrix(&self) -> Matrix3<f32> {
+        let temperature = match self.white_balance {
+            Some(temperature) => temperature,
+            None => return Matrix3::identity(),
+        };
+
+        let rgb_to_xyz = from_rows([
+            [0.4124, 0.3576, 0.1805],
+            [0.2126, 0.7152, 0.0722],
+            [0.0193, 0.1192, 0.9505],
+        ]);
+        let bradford = from_rows([
+            [0.8951, 0.2664, -0.1614],
+            [-0.7502, 1.7135, 0.0367],
+            [0.0389, -0.0685, 1.0296],
+        ]);
+        let src = bradford * planckian_white(temperature);
+        let dst = bradford * planckian_white(6504.0);
+        let scale = Matrix3::from_diagonal(dst.div_element_wise(src));
+
+        let xyz_to_rgb = rgb_to_xyz.invert().unwrap();
+        let bradford_inv = bradford.invert().unwrap();
+        xyz_to_rgb * bradford_inv * scale * bradford * rgb_to_xyz
+    }
+
+    /// Maps RGBA radiance to 8-bit RGB
+    pub fn encode(&self, rgba: &[f32]) -> Vec<u8> {
+        let white_balance = self.white_balance_matrix();
+        rgba.chunks_exact(4)
+            .flat_map(|pixel| {
+                let color = self.apply(&white_balance, Vector3::new(pixel[0], pixel[1], pixel[2]));
+                [color.x, color.y, color.z]
+            })
+            .map(|v| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8)
+            .collect()
+    }
+
+    pub fn apply(&self, white_balance: &Matrix3<f32>, color: Vector3<f32>) -> Vector3<f32> {
+        let color = white_balance * color * self.exposure_scale();
+        let color = match self.tone_mapper {
+            ToneMapper::None => color,
+            ToneMapper::Reinhard => color.map(|v| v / (1.0 + v)),
+            ToneMapper::AcesFitted => aces_fitted(color),
+            ToneMapper::Agx => agx(color),
+            ToneMapper::Filmic => filmic(color),
+        };
+        let color = color.map(|v| v.clamp(0.0, 1.0));
+        if self.srgb {
+            color.map(srgb_encode)
+        } else {
+            color
+        }
+    }
+}
+
+fn from_rows(rows: [[f32; 3]; 3]) -> Matrix3<f32> {
+    Matrix3::from_cols(rows[0].into(), rows[1].into(), rows[2].into()).transpose()
+}
+
+/// XYZ (Y = 1) of a black body, from the Planckian locus approximation of Kang et al. 2002
+fn planckian_white(temperature: f32) -> Vector3<f32> {
+    let t = temperature.clamp(1667.0, 25000.0) as f64;
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / (t * t * t) - 0.2343589e6 / (t * t) + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / (t * t * t) + 2.1070379e6 / (t * t) + 0.2226347e3 / t + 0.240390
+    };
+    let y = if t <= 2222.0 {
+        -1.1063814 * x * x * x - 1.3481102 * x * x + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x * x * x - 1.37418593 * x * x + 2.09137015 * x - 0.16748867
+    } else {
+        3.081758 * x * x * x - 5.8733867 * x * x + 3.75112997 * x - 0.37001483
+    };
+    Vector3::new((x / y) as f32, 1.0, ((1.0 - x - y) / y) as f32)
+}
+
+fn srgb_encode(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// ACES fitted by Stephen Hill
+fn aces_fitted(color: Vector3<f32>) -> Vector3<f32> {
+    let input = from_rows([
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ]);
+    let output = from_rows([
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ]);
+    let color = (input * color).map(|v| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.432951) + 0.238081;
+        a / b
+    });
+    output * color
+}
+
+/// AgX base look, with the polynomial fit of the contrast curve by Benjamin Wrensch
+fn agx(color: Vector3<f32>) -> Vector3<f32> {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+    let inset = Matrix3::new(
+        0.84247906,
+        0.042328242,
+        0.042375655,
+        0.0784336,
+        0.87846864,
+        0.0784336,
+        0.079223745,
+        0.07916613,
+        0.879143,
+    );
+    let outset = Matrix3::new(
+        1.196879,
+        -0.052896852,
+        -0.052971636,
+        -0.09802088,
+        1.1519031,
+        -0.09804345,
+        -0.09902974,
+        -0.098961177,
+        1.1510737,
+    );
+
+    let color = (inset * color).map(|v| {
+        let x = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+    // the curve outputs display encoded values, return to linear for the sRGB encoding
+    (outset * color).map(|v| v.max(0.0).powf(2.2))
+}
+
+/// Uncharted 2 filmic curve by John Hable
+fn filmic(color: Vector3<f32>) -> Vector3<f32> {
+    fn curve(x: f32) -> f32 {
+        const A: f32 = 0.15;
+        const B: f32 = 0.50;
+        const C: f32 = 0.10;
+        const D: f32 = 0.20;
+        const E: f32 = 0.02;
+        const F: f32 = 0.30;
+        ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+    }
+    const WHITE: f32 = 11.2;
+    const EXPOSURE_BIAS: f32 = 2.0;
+    color.map(|v| curve(v * EXPOSURE_BIAS) / curve(WHITE))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    #[test]
+    fn reference_white_balance_is_identity() {
+        let display = DisplayConfig {
+            white_balance: Some(6504.0),
+            ..Default::default()
+        };
+        let diff = display.white_balance_matrix() - Matrix3::identity();
+        assert!(diff.x.magnitude() + diff.y.magnitude() + diff.z.magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn low_temperature_cools_the_image() {
+        let display = DisplayConfig {
+            white_balance: Some(3200.0),
+            ..Default::default()
+        };
+        let white = display.white_balance_matrix() * Vector3::new(1.0, 1.0, 1.0);
+        assert!(white.z > white.x);
+    }
+
+    #[test]
+    fn tone_mappers_are_monotonic_and_bounded() {
+        for tone_mapper in [
+            ToneMapper::None,
+            ToneMapper::Reinhard,
+            ToneMapper::AcesFitted,
+            ToneMapper::Agx,
+            ToneMapper::Filmic,
+        ] {
+            let display = DisplayConfig {
+                tone_mapper,
+                ..Default::default()
+            };
+            let mut last = -1.0;
+            for i in 0..=64 {
+                let v = (i as f32 / 4.0 - 8.0).exp2();
+                let color = display.apply(&Matrix3::identity(), Vector3::new(v, v, v));
+                assert!((0.0..=1.0).contains(&color.x));
+                assert!(color.x >= last - 1e-4);
+                last = color.x;
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_encoding() {
+        let display = DisplayConfig::default();
+        assert_eq!(display.encode(&[0.0, 1.0, 0.18, 1.0]), vec![0, 255, 118]);
+        assert_eq!(
+            DisplayConfig::passthrough().encode(&[0.5, 2.0, -1.0, 1.0]),
+            vec![128, 255, 0]
+        );
+    }
+}
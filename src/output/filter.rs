@@ -0,0 +1,221 @@
+use std::f32::consts::PI;
+
+/// Number of bins of `FilterTable`, same as `FILTER_BINS` in `ray_tracing.comp`
+pub const FILTER_BINS: usize = 64;
+
+/// Pixel reconstruction filters, applied as the same 1D filter along x and y. Camera rays
+/// are distributed like the filter (filter importance sampling) instead of splatting samples
+/// to the neighbouring pixels, so accumulation stays a plain average of weighted samples.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelFilter {
+    /// rays go through the pixel centers
+    None,
+    Box {
+        radius: f32,
+    },
+    Tent {
+        radius: f32,
+    },
+    /// Gaussian shifted down to 0 at `radius`
+    Gaussian {
+        radius: f32,
+        sigma: f32,
+    },
+    /// Mitchell-Netravali, samples in its negative lobes get negative weights
+    Mitchell {
+        radius: f32,
+        b: f32,
+        c: f32,
+    },
+    BlackmanHarris {
+        radius: f32,
+    },
+}
+
+/// Tabulated 1D filter the shader samples from, see `sample_filter` in `ray_tracing.comp`
+pub struct FilterTable {
+    /// 0 if rays go through the pixel centers
+    pub radius: f32,
+    /// integral of |f| divided by the integral of f, the magnitude of the sample weights
+    pub weight: f32,
+    /// cumulative distribution of |f| at the upper end of each bin, bins cover [-radius, radius]
+    pub cdf: [f32; FILTER_BINS],
+    /// sign of f in each bin
+    pub sign: [f32; FILTER_BINS],
+}
+
+impl PixelFilter {
+    /// Filter with the default parameters of a type name in scene files
+    pub fn from_name(name: &str) -> Option<PixelFilter> {
+        Some(match name {
+            "none" => PixelFilter::None,
+            "box" => PixelFilter::Box { radius: 0.5 },
+            "tent" => PixelFilter::Tent { radius: 1.0 },
+            "gaussian" => PixelFilter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            "mitchell" => PixelFilter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            "blackman_harris" => PixelFilter::BlackmanHarris { radius: 2.0 },
+            _ => return None,
+        })
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            PixelFilter::None => 0.0,
+            PixelFilter::Box { radius }
+            | PixelFilter::Tent { radius }
+            | PixelFilter::Gaussian { radius, .. }
+            | PixelFilter::Mitchell { radius, .. }
+            | PixelFilter::BlackmanHarris { radius } => radius,
+        }
+    }
+
+    /// Value of the 1D filter at offset `x` from the pixel center
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let radius = self.radius();
+        if x.abs() > radius {
+            return 0.0;
+        }
+        match *self {
+            PixelFilter::None => 0.0,
+            PixelFilter::Box { .. } => 1.0,
+            PixelFilter::Tent { radius } => radius - x.abs(),
+            PixelFilter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            PixelFilter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x.abs() / radius;
+                let value = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                };
+                value / 6.0
+            }
+            PixelFilter::BlackmanHarris { radius } => {
+                let t = 2.0 * PI * (x / radius + 1.0) / 2.0;
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+
+    pub fn table(&self) -> FilterTable {
+        let mut table = FilterTable {
+            radius: self.radius(),
+            weight: 1.0,
+            cdf: [1.0; FILTER_BINS],
+            sign: [1.0; FILTER_BINS],
+        };
+        if table.radius <= 0.0 {
+            return table;
+        }
+
+        // average of each bin, the sign may only flip inside a bin at a zero crossing
+        const SUB_SAMPLES: usize = 16;
+        let bin_width = 2.0 * table.radius / FILTER_BINS as f32;
+        let mut masses = [0.0; FILTER_BINS];
+        for (i, mass) in masses.iter_mut().enumerate() {
+            let sum: f32 = (0..SUB_SAMPLES)
+                .map(|j| {
+                    let t = (i as f32 + (j as f32 + 0.5) / SUB_SAMPLES as f32) / FILTER_BINS as f32;
+                    self.evaluate((2.0 * t - 1.0) * table.radius)
+                })
+                .sum();
+            *mass = sum / SUB_SAMPLES as f32 * bin_width;
+        }
+
+        let integral: f32 = masses.iter().sum();
+        let abs_integral: f32 = masses.iter().map(|m| m.abs()).sum();
+        table.weight = abs_integral / integral;
+        let mut cumulative = 0.0;
+        for (i, mass) in masses.iter().enumerate() {
+            cumulative += mass.abs();
+            table.cdf[i] = cumulative / abs_integral;
+            table.sign[i] = if *mass < 0.0 { -1.0 } else { 1.0 };
+        }
+        table.cdf[FILTER_BINS - 1] = 1.0;
+        table
+    }
+}
+
+impl FilterTable {
+    /// Offset from the pixel center and weight of a sample for `u` in [0, 1),
+    /// same as `sample_filter` in `ray_tracing.comp`
+    pub fn sample(&self, u: f32) -> (f32, f32) {
+        let (mut lo, mut hi) = (0, FILTER_BINS - 1);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if u < self.cdf[mid] {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        let lower = if lo > 0 { self.cdf[lo - 1] } else { 0.0 };
+        let t = ((u - lower) / (self.cdf[lo] - lower)).clamp(0.0, 1.0);
+        let offset = (-1.0 + 2.0 * (lo as f32 + t) / FILTER_BINS as f32) * self.radius;
+        (offset, self.sign[lo] * self.weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [&str; 5] = ["box", "tent", "gaussian", "mitchell", "blackman_harris"];
+
+    #[test]
+    fn sample_weights_average_to_one() {
+        for name in FILTERS {
+            let table = PixelFilter::from_name(name).unwrap().table();
+            let n = 4096;
+            let (mut weight_sum, mut offset_sum) = (0.0, 0.0);
+            for i in 0..n {
+                let (offset, weight) = table.sample((i as f32 + 0.5) / n as f32);
+                assert!(offset.abs() <= table.radius, "{} {}", name, offset);
+                weight_sum += weight;
+                offset_sum += offset * weight;
+            }
+            assert!((weight_sum / n as f32 - 1.0).abs() < 1e-3, "{}", name);
+            assert!(
+                (offset_sum / n as f32).abs() < 1e-3,
+                "{} is not centered",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn negative_lobes() {
+        let mitchell = PixelFilter::from_name("mitchell").unwrap();
+        assert!(mitchell.evaluate(1.5) < 0.0);
+        let table = mitchell.table();
+        assert!(table.weight > 1.0);
+        assert!(table.sign.contains(&-1.0));
+        assert_eq!(PixelFilter::from_name("box").unwrap().table().weight, 1.0);
+    }
+
+    #[test]
+    fn tent_distribution() {
+        // |offset| of a tent of radius 1 has the density 2 (1 - x) on [0, 1], its mean is 1/3
+        let table = PixelFilter::from_name("tent").unwrap().table();
+        let n = 4096;
+        let mean = (0..n)
+            .map(|i| table.sample((i as f32 + 0.5) / n as f32).0.abs())
+            .sum::<f32>()
+            / n as f32;
+        assert!((mean - 1.0 / 3.0).abs() < 1e-3, "{}", mean);
+    }
+}
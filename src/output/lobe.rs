@@ -0,0 +1,60 @@
+use super::ImageLayer;
+
+/// Parts of the radiance that can be accumulated separately, the values match `LOBE_*`
+/// in `ray_tracing.comp`. Direct light is split by the BSDF lobe that reflects it at the
+/// first hit, indirect light by the BSDF lobe sampled there. Emission is what camera rays
+/// see directly (emissive surfaces and the background), emission found after bounces is
+/// direct or indirect light. All lobes sum up to the image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    DirectDiffuse = 0,
+    DirectSpecular = 1,
+    DirectTransmission = 2,
+    IndirectDiffuse = 3,
+    IndirectSpecular = 4,
+    IndirectTransmission = 5,
+    Emission = 6,
+}
+
+impl Lobe {
+    pub const ALL: [Lobe; 7] = [
+        Lobe::DirectDiffuse,
+        Lobe::DirectSpecular,
+        Lobe::DirectTransmission,
+        Lobe::IndirectDiffuse,
+        Lobe::IndirectSpecular,
+        Lobe::IndirectTransmission,
+        Lobe::Emission,
+    ];
+
+    /// Name in scene files and layer name in multi-layer EXR files
+    pub fn name(self) -> &'static str {
+        match self {
+            Lobe::DirectDiffuse => "direct_diffuse",
+            Lobe::DirectSpecular => "direct_specular",
+            Lobe::DirectTransmission => "direct_transmission",
+            Lobe::IndirectDiffuse => "indirect_diffuse",
+            Lobe::IndirectSpecular => "indirect_specular",
+            Lobe::IndirectTransmission => "indirect_transmission",
+            Lobe::Emission => "emission",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lobe> {
+        Lobe::ALL.iter().copied().find(|lobe| lobe.name() == name)
+    }
+
+    /// `lobe_mask` of `ray_tracing.comp`
+    pub fn mask(lobes: &[Lobe]) -> u32 {
+        lobes.iter().fold(0, |mask, &lobe| mask | 1 << lobe as u32)
+    }
+
+    /// `data` has the interleaved RGB of every pixel
+    pub fn layer(self, data: Vec<f32>) -> ImageLayer {
+        ImageLayer {
+            name: self.name(),
+            channels: &["R", "G", "B"],
+            data,
+        }
+    }
+}
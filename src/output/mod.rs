@@ -0,0 +1,102 @@
+mod aov;
+mod display;
+mod filter;
+mod lobe;
+
+pub use aov::*;
+pub use display::*;
+pub use filter::*;
+pub use lobe::*;
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Extra channels saved next to the radiance in EXR files, `data` has the interleaved
+/// `channels` of every pixel with rows from top to bottom
+pub struct ImageLayer {
+    pub name: &'static str,
+    pub channels: &'static [&'static str],
+    pub data: Vec<f32>,
+}
+
+/// Saves RGBA radiance (rows from top to bottom), the alpha channel is dropped.
+/// '.exr' files keep the HDR values and get `layers` next to them,
+/// other formats go through `display` like the window does.
+pub fn save_image<P: AsRef<Path>>(
+    path: P,
+    width: u32,
+    height: u32,
+    rgba: &[f32],
+    layers: &[ImageLayer],
+    display: &DisplayConfig,
+) -> Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("exr") => save_exr(path, width, height, rgba, layers)?,
+        _ => {
+            image::RgbImage::from_raw(width, height, display.encode(rgba))
+                .context("image: size mismatch")?
+                .save(path)?;
+        }
+    }
+
+    println!("Image is saved to '{}'", path.display());
+    Ok(())
+}
+
+/// Radiance goes to the 'R', 'G', 'B' channels, layers to '<layer>.<channel>' ones
+/// like multi-layer EXR files of Blender
+fn save_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgba: &[f32],
+    layers: &[ImageLayer],
+) -> Result<()> {
+    use exr::prelude::*;
+
+    let pixel_count = (width * height) as usize;
+    if rgba.len() != pixel_count * 4 {
+        anyhow::bail!("image: size mismatch");
+    }
+    let channel = |name: &str, data: &[f32], stride: usize, offset: usize| {
+        let samples = data.iter().skip(offset).step_by(stride).copied().collect();
+        AnyChannel::new(name, FlatSamples::F32(samples))
+    };
+
+    let mut channels: Vec<_> = ["R", "G", "B"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| channel(name, rgba, 4, i))
+        .collect();
+    for layer in layers {
+        let names = layer.channels;
+        if layer.data.len() != pixel_count * names.len() {
+            anyhow::bail!("image: size mismatch of layer '{}'", layer.name);
+        }
+        for (i, name) in names.iter().enumerate() {
+            let name = format!("{}.{}", layer.name, name);
+            channels.push(channel(&name, &layer.data, names.len(), i));
+        }
+    }
+
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(channels.into()),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .with_context(|| format!("image: failed to write '{}'", path.display()))
+}
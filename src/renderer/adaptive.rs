@@ -0,0 +1,93 @@
+use std::rc::Rc;
+
+use super::WorkgroupSize;
+use crate::{opengl::*, uniforms::AdaptiveUniform};
+
+#[derive(Clone, Copy)]
+pub struct AdaptiveConfig {
+    /// samples every pixel gets before its error is trusted
+    pub min_samples: u32,
+    /// relative standard error of the mean luminance below which a pixel is converged
+    pub target_error: f32,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            target_error: 0.02,
+        }
+    }
+}
+
+/// Updates the convergence mask in the aux image after each frame, `ray_tracing.comp`
+/// skips the converged pixels
+pub struct AdaptiveSampler {
+    workgroup_size: WorkgroupSize,
+    width: u32,
+    height: u32,
+    uniform_buffer: Rc<Buffer>,
+    counter_buffer: Rc<Buffer>,
+    pipeline: Rc<ComputePipeline>,
+}
+
+impl AdaptiveSampler {
+    pub fn new(
+        context: &mut OpenglContext,
+        config: AdaptiveConfig,
+        workgroup_size: WorkgroupSize,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let uniform = AdaptiveUniform {
+            min_samples: config.min_samples as i32,
+            target_error: config.target_error,
+            _pad: [0.0; 2],
+        };
+        let info = BufferInfo {
+            size: std::mem::size_of::<AdaptiveUniform>() as u32,
+            dynamic: false,
+        };
+        let uniform_buffer = context.create_buffer(info, Some(bytemuck::bytes_of(&uniform)));
+        let info = BufferInfo {
+            size: std::mem::size_of::<u32>() as u32,
+            dynamic: true,
+        };
+        let counter_buffer = context.create_buffer(info, None);
+
+        let info = ShaderInfo {
+            source: workgroup_size.shader_source(include_str!("../../shaders/adaptive.comp")),
+            stage: gl::COMPUTE_SHADER,
+        };
+        let cs = context.create_shader(info);
+        let info = ComputePipelineInfo { shader: cs };
+        let pipeline = context.create_compute_pipeline(info);
+
+        Self {
+            workgroup_size,
+            width,
+            height,
+            uniform_buffer,
+            counter_buffer,
+            pipeline,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Marks the converged pixels and returns how many of them there are
+    pub fn update_mask(&self, context: &mut OpenglContext, aux_img: &Rc<Texture>) -> u32 {
+        context.update_buffer(&self.counter_buffer, 0, bytemuck::bytes_of(&0u32));
+        context.bind_compute_pipeline(&self.pipeline);
+        context.bind_image(0, aux_img, 0, None, gl::READ_WRITE);
+        context.bind_uniform_buffer(4, &self.uniform_buffer, None);
+        context.bind_shader_storage_buffer(5, &self.counter_buffer, None);
+        context.dispatch_compute(self.workgroup_size.groups(self.width, self.height), true);
+
+        let count = context.read_buffer(&self.counter_buffer, 0, 4);
+        bytemuck::pod_read_unaligned(&count)
+    }
+}
@@ -0,0 +1,218 @@
+use std::rc::Rc;
+
+use super::WorkgroupSize;
+use crate::{
+    opengl::*,
+    uniforms::{Camera, DenoiseUniform, TemporalUniform},
+};
+
+#[derive(Clone, Copy)]
+pub struct DenoiseConfig {
+    /// number of a-trous passes, the filter footprint doubles with each of them
+    pub iterations: u32,
+    /// edge-stopping strength of the luminance, in standard deviations
+    pub sigma_color: f32,
+    /// exponent of the normal weight
+    pub sigma_normal: f32,
+    /// edge-stopping strength of the depth, relative to the local depth gradient
+    pub sigma_depth: f32,
+    /// blend with the reprojected previous output while accumulation restarts
+    pub temporal: bool,
+    /// minimum weight of the current frame in the temporal blend
+    pub temporal_alpha: f32,
+}
+
+impl Default for DenoiseConfig {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 4.0,
+            sigma_normal: 128.0,
+            sigma_depth: 1.0,
+            temporal: false,
+            temporal_alpha: 0.2,
+        }
+    }
+}
+
+/// Edge-avoiding a-trous filter (SVGF style) on the accumulated image, guided by the
+/// first hit albedo, normal and depth written to the aux image by `ray_tracing.comp`
+pub struct Denoiser {
+    config: DenoiseConfig,
+    workgroup_size: WorkgroupSize,
+    width: u32,
+    height: u32,
+    denoise_uniform_buffer: Rc<Buffer>,
+    temporal_uniform_buffer: Rc<Buffer>,
+    atrous_imgs: [Rc<Texture>; 2],
+    temporal_img: Rc<Texture>,
+    history_img: Rc<Texture>,
+    atrous_pipeline: Rc<ComputePipeline>,
+    temporal_pipeline: Rc<ComputePipeline>,
+    history_valid: bool,
+}
+
+impl Denoiser {
+    pub fn new(
+        context: &mut OpenglContext,
+        config: DenoiseConfig,
+        workgroup_size: WorkgroupSize,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let info = BufferInfo {
+            size: std::mem::size_of::<DenoiseUniform>() as u32,
+            dynamic: true,
+        };
+        let denoise_uniform_buffer = context.create_buffer(info, None);
+        let info = BufferInfo {
+            size: std::mem::size_of::<TemporalUniform>() as u32,
+            dynamic: true,
+        };
+        let temporal_uniform_buffer = context.create_buffer(info, None);
+
+        let atrous_imgs = [
+            create_image(context, width, height),
+            create_image(context, width, height),
+        ];
+        let temporal_img = create_image(context, width, height);
+        let history_img = create_image(context, width, height);
+
+        let info = ShaderInfo {
+            source: workgroup_size.shader_source(include_str!("../../shaders/denoise_atrous.comp")),
+            stage: gl::COMPUTE_SHADER,
+        };
+        let atrous_cs = context.create_shader(info);
+        let info = ComputePipelineInfo { shader: atrous_cs };
+        let atrous_pipeline = context.create_compute_pipeline(info);
+
+        let info = ShaderInfo {
+            source: workgroup_size
+                .shader_source(include_str!("../../shaders/denoise_temporal.comp")),
+            stage: gl::COMPUTE_SHADER,
+        };
+        let temporal_cs = context.create_shader(info);
+        let info = ComputePipelineInfo {
+            shader: temporal_cs,
+        };
+        let temporal_pipeline = context.create_compute_pipeline(info);
+
+        Self {
+            config,
+            workgroup_size,
+            width,
+            height,
+            denoise_uniform_buffer,
+            temporal_uniform_buffer,
+            atrous_imgs,
+            temporal_img,
+            history_img,
+            atrous_pipeline,
+            temporal_pipeline,
+            history_valid: false,
+        }
+    }
+
+    /// Reallocates the images at a new size, the history is dropped
+    pub fn resize(&mut self, context: &mut OpenglContext, width: u32, height: u32) {
+        let images = self
+            .atrous_imgs
+            .iter_mut()
+            .chain([&mut self.temporal_img, &mut self.history_img]);
+        for img in images {
+            context.delete_texture(img);
+            *img = create_image(context, width, height);
+        }
+        self.width = width;
+        self.height = height;
+        self.history_valid = false;
+    }
+
+    /// Returns the denoised image, `camera` is the one the traced image is rendered with
+    pub fn denoise(
+        &mut self,
+        context: &mut OpenglContext,
+        traced_img: &Rc<Texture>,
+        aux_img: &Rc<Texture>,
+        samples: u32,
+        camera: &Camera,
+        prev_camera: &Camera,
+    ) -> Rc<Texture> {
+        let num_groups = self.workgroup_size.groups(self.width, self.height);
+
+        // pass 0 prepares the demodulated color, the others are the a-trous iterations
+        let pass_count = self.config.iterations + 1;
+        context.bind_compute_pipeline(&self.atrous_pipeline);
+        context.bind_image(2, aux_img, 0, None, gl::READ_ONLY);
+        for pass_index in 0..pass_count {
+            let uniform = DenoiseUniform {
+                pass_index: pass_index as i32,
+                pass_count: pass_count as i32,
+                sigma_color: self.config.sigma_color,
+                sigma_normal: self.config.sigma_normal,
+                sigma_depth: self.config.sigma_depth,
+                _pad: [0.0; 3],
+            };
+            context.update_buffer(
+                &self.denoise_uniform_buffer,
+                0,
+                bytemuck::bytes_of(&uniform),
+            );
+            context.bind_uniform_buffer(4, &self.denoise_uniform_buffer, None);
+
+            let input = if pass_index == 0 {
+                traced_img
+            } else {
+                &self.atrous_imgs[(pass_index as usize - 1) % 2]
+            };
+            let output = &self.atrous_imgs[pass_index as usize % 2];
+            context.bind_image(0, input, 0, Some(0), gl::READ_ONLY);
+            context.bind_image(1, output, 0, Some(0), gl::WRITE_ONLY);
+            context.dispatch_compute(num_groups, true);
+        }
+        let denoised = &self.atrous_imgs[(pass_count as usize - 1) % 2];
+
+        if !self.config.temporal {
+            return denoised.clone();
+        }
+
+        let uniform = TemporalUniform {
+            camera: *camera,
+            prev_camera: *prev_camera,
+            temporal_alpha: self.config.temporal_alpha,
+            samples: samples as i32,
+            history_valid: self.history_valid as i32,
+            _pad: 0.0,
+        };
+        context.update_buffer(
+            &self.temporal_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&uniform),
+        );
+        context.bind_compute_pipeline(&self.temporal_pipeline);
+        context.bind_image(0, denoised, 0, Some(0), gl::READ_ONLY);
+        context.bind_image(1, &self.temporal_img, 0, Some(0), gl::WRITE_ONLY);
+        context.bind_image(2, aux_img, 0, None, gl::READ_ONLY);
+        context.bind_image(3, &self.history_img, 0, Some(0), gl::READ_ONLY);
+        context.bind_uniform_buffer(4, &self.temporal_uniform_buffer, None);
+        context.dispatch_compute(num_groups, true);
+
+        context.copy_texture(&self.temporal_img, &self.history_img);
+        self.history_valid = true;
+
+        self.temporal_img.clone()
+    }
+}
+
+fn create_image(context: &mut OpenglContext, width: u32, height: u32) -> Rc<Texture> {
+    let info = TextureInfo {
+        width,
+        height,
+        layers: 1,
+        mips: 1,
+        samples: 1,
+        format: gl::RGBA32F,
+        ty: gl::TEXTURE_2D,
+    };
+    context.create_texture(info)
+}
@@ -1,15 +1,71 @@
-use std::{cell::RefCell, rc::Rc};
+mod adaptive;
+mod denoiser;
+mod stats;
+mod viewport;
+mod workgroup;
+
+pub use adaptive::*;
+pub use denoiser::*;
+pub use stats::*;
+pub use viewport::*;
+pub use workgroup::*;
+
+use std::{
+    cell::RefCell,
+    convert::TryInto,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use crate::{
+    core::{self, BlueNoise, SamplerKind, Scene, BLUE_NOISE_SIZE},
     opengl::*,
-    uniforms::{SceneUniform, VariableUniform},
+    output::{Aov, DisplayConfig, ImageLayer, Lobe, PixelFilter},
+    uniforms::{self, Camera, FilterUniform, PostUniform, SceneUniform, VariableUniform},
 };
 
+/// Layers of the aux image, see `AUX_*` in `ray_tracing.comp`
+const AUX_LAYERS: u32 = 6;
+const AUX_ALBEDO: usize = 0;
+const AUX_NORMAL_DEPTH: usize = 1;
+const AUX_POSITION: usize = 3;
+const AUX_IDS: usize = 4;
+const AUX_SAMPLES: usize = 5;
+
 pub struct OutputConfig {
     pub file: String,
     pub width: u32,
     pub height: u32,
     pub scale: u32,
+    pub render_mode: RenderMode,
+    pub display: DisplayConfig,
+    /// pixel reconstruction filter the camera rays are distributed by
+    pub filter: PixelFilter,
+    pub denoise: Option<DenoiseConfig>,
+    /// stops tracing pixels once their noise is below a target
+    pub adaptive: Option<AdaptiveConfig>,
+    /// parts of the radiance that are accumulated separately and saved to EXR files
+    pub lobes: Vec<Lobe>,
+    pub workgroup_size: WorkgroupSize,
+    /// images are traced in square tiles of this size, one dispatch each
+    pub tile_size: u32,
+    pub resize: ResizeMode,
+}
+
+/// What `ray_tracing.comp` writes to the traced image, the debug modes show a heatmap of
+/// the traversal work done for the primary ray
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    Default = 0,
+    BvhNodes = 1,
+    TriangleTests = 2,
+}
+
+impl OutputConfig {
+    /// Output file name with '{}' replaced by `tag`
+    pub fn file_name(&self, tag: &str) -> String {
+        self.file.replace("{}", tag)
+    }
 }
 
 pub struct Renderer {
@@ -17,29 +73,88 @@ pub struct Renderer {
     pub output_config: OutputConfig,
     scene_uniform: Box<SceneUniform>,
     variable_uniform: VariableUniform,
+    camera: core::Camera,
+    /// materials and lights of the scene as they are edited, the scene uniform has them too
+    materials: Vec<core::Material>,
+    lights: Vec<core::Light>,
+    /// camera of the previous frame, used to reproject the denoiser history
+    prev_camera: Camera,
+    /// AOV shown in the window instead of the radiance
+    view: Option<Aov>,
+    /// object highlighted in the window
+    selected_object: Option<u32>,
+    sampler: SamplerKind,
+    /// converged pixels after the last frame, always 0 without adaptive sampling
+    converged_pixels: u32,
+    /// size of the window in pixels
+    framebuffer_size: (u32, u32),
+    triangle_count: usize,
+    bvh_node_count: usize,
+    /// pixels traced in the last frame
+    traced_pixels: u32,
+    last_frame: Option<Instant>,
+    frame_time: Option<Duration>,
+    gpu_time: Option<Duration>,
+    /// whether the timer query is ended but its result isn't read yet
+    timer_pending: bool,
     gl_resources: Option<GlResources>,
 }
 
 struct GlResources {
     pub scene_uniform_buffer: Rc<Buffer>,
     pub variable_uniform_buffer: Rc<Buffer>,
+    pub post_uniform_buffer: Rc<Buffer>,
+    pub filter_uniform_buffer: Rc<Buffer>,
     pub traced_img: Rc<Texture>,
     pub traced_img_sampler: Rc<Sampler>,
+    pub aux_img: Rc<Texture>,
+    /// a layer for each lobe in `lobe_mask`, in the order of `Lobe::ALL`
+    pub lobe_img: Rc<Texture>,
+    /// mask of `SamplerKind::BlueNoise`, 1x1 for other samplers
+    pub blue_noise_img: Rc<Texture>,
+    /// GPU time of a whole frame
+    pub timer_query: Rc<Query>,
+    /// image shown by the post pipeline in the last frame
+    pub display_img: Rc<Texture>,
+    pub denoiser: Option<Denoiser>,
+    pub adaptive: Option<AdaptiveSampler>,
     pub trace_pipeline: Rc<ComputePipeline>,
     pub post_pipeline: Rc<GraphicsPipeline>,
 }
 
 impl Renderer {
-    pub fn new(
-        output_config: OutputConfig,
-        scene_uniform: Box<SceneUniform>,
-        variable_uniform: VariableUniform,
-    ) -> Self {
+    pub fn new(output_config: OutputConfig, scene: &Scene) -> Self {
+        let scene_uniform = SceneUniform::new(scene);
+        let variable_uniform = VariableUniform::new(
+            scene,
+            output_config.render_mode as u32,
+            Lobe::mask(&output_config.lobes),
+        );
+        let framebuffer_size = (
+            output_config.width * output_config.scale,
+            output_config.height * output_config.scale,
+        );
         Self {
             context: RefCell::new(OpenglContext::new()),
             output_config,
             scene_uniform,
             variable_uniform,
+            camera: scene.camera,
+            materials: scene.materials.clone(),
+            lights: scene.lights.clone(),
+            prev_camera: variable_uniform.camera,
+            view: None,
+            selected_object: None,
+            sampler: scene.sampler,
+            converged_pixels: 0,
+            framebuffer_size,
+            triangle_count: scene.triangles.len(),
+            bvh_node_count: scene.bvh.stats().node_count,
+            traced_pixels: 0,
+            last_frame: None,
+            frame_time: None,
+            gpu_time: None,
+            timer_pending: false,
             gl_resources: None,
         }
     }
@@ -47,6 +162,7 @@ impl Renderer {
     pub fn init(&mut self) {
         let info = BufferInfo {
             size: std::mem::size_of::<SceneUniform>() as u32,
+            dynamic: true,
         };
         let scene_uniform_buffer = self
             .context
@@ -55,21 +171,80 @@ impl Renderer {
 
         let info = BufferInfo {
             size: std::mem::size_of::<VariableUniform>() as u32,
+            dynamic: true,
         };
         let variable_uniform_buffer = self
             .context
             .borrow_mut()
             .create_buffer(info, Some(bytemuck::bytes_of(&self.variable_uniform)));
 
+        let post_uniform =
+            PostUniform::new(&self.output_config.display, self.view, self.selected_object);
+        let info = BufferInfo {
+            size: std::mem::size_of::<PostUniform>() as u32,
+            dynamic: true,
+        };
+        let post_uniform_buffer = self
+            .context
+            .borrow_mut()
+            .create_buffer(info, Some(bytemuck::bytes_of(&post_uniform)));
+
+        let filter_uniform = FilterUniform::new(&self.output_config.filter.table());
+        let info = BufferInfo {
+            size: std::mem::size_of::<FilterUniform>() as u32,
+            dynamic: false,
+        };
+        let filter_uniform_buffer = self
+            .context
+            .borrow_mut()
+            .create_buffer(info, Some(bytemuck::bytes_of(&filter_uniform)));
+
+        let [traced_img, aux_img, lobe_img] = self.create_images();
+
+        let info = QueryInfo {
+            target: gl::TIME_ELAPSED,
+        };
+        let timer_query = self.context.borrow_mut().create_query(info);
+
+        let blue_noise = (self.sampler == SamplerKind::BlueNoise).then(BlueNoise::new);
+        let blue_noise_size = blue_noise.as_ref().map_or(1, |_| BLUE_NOISE_SIZE);
         let info = TextureInfo {
-            width: self.output_config.width,
-            height: self.output_config.height,
+            width: blue_noise_size,
+            height: blue_noise_size,
+            layers: 1,
             mips: 1,
             samples: 1,
-            format: gl::RGBA32F,
+            format: gl::R32F,
             ty: gl::TEXTURE_2D,
         };
-        let traced_img = self.context.borrow_mut().create_texture(info);
+        let blue_noise_img = self.context.borrow_mut().create_texture(info);
+        if let Some(blue_noise) = &blue_noise {
+            self.context.borrow_mut().update_texture(
+                &blue_noise_img,
+                0,
+                bytemuck::cast_slice(&blue_noise.values),
+            );
+        }
+
+        let denoiser = self.output_config.denoise.map(|config| {
+            Denoiser::new(
+                &mut self.context.borrow_mut(),
+                config,
+                self.output_config.workgroup_size,
+                self.output_config.width,
+                self.output_config.height,
+            )
+        });
+
+        let adaptive = self.output_config.adaptive.map(|config| {
+            AdaptiveSampler::new(
+                &mut self.context.borrow_mut(),
+                config,
+                self.output_config.workgroup_size,
+                self.output_config.width,
+                self.output_config.height,
+            )
+        });
 
         let info = SamplerInfo {
             filter_min: gl::LINEAR,
@@ -81,7 +256,10 @@ impl Renderer {
         let traced_img_sampler = self.context.borrow_mut().create_sampler(info);
 
         let info = ShaderInfo {
-            source: include_str!("../../shaders/ray_tracing.comp").to_owned(),
+            source: self
+                .output_config
+                .workgroup_size
+                .shader_source(include_str!("../../shaders/ray_tracing.comp")),
             stage: gl::COMPUTE_SHADER,
         };
         let trace_cs = self.context.borrow_mut().create_shader(info);
@@ -112,14 +290,42 @@ impl Renderer {
         self.gl_resources = Some(GlResources {
             scene_uniform_buffer,
             variable_uniform_buffer,
+            post_uniform_buffer,
+            filter_uniform_buffer,
+            display_img: traced_img.clone(),
             traced_img,
             traced_img_sampler,
+            aux_img,
+            lobe_img,
+            blue_noise_img,
+            timer_query,
+            denoiser,
+            adaptive,
             trace_pipeline,
             post_pipeline,
         });
     }
 
-    pub fn render(&self) {
+    /// Traces one more sample for every pixel that is not converged yet and accumulates it
+    /// into the traced image
+    pub fn render(&mut self) {
+        let now = Instant::now();
+        self.frame_time = self.last_frame.map(|last| now - last);
+        self.last_frame = Some(now);
+
+        // the query is only reused once its result is read, so reading it never stalls
+        let timer_query = self.resource().timer_query.clone();
+        if self.timer_pending {
+            if let Some(ns) = self.context.borrow().query_result(&timer_query) {
+                self.gpu_time = Some(Duration::from_nanos(ns));
+                self.timer_pending = false;
+            }
+        }
+        let timed = !self.timer_pending;
+        if timed {
+            self.context.borrow().begin_query(&timer_query);
+        }
+
         self.context
             .borrow_mut()
             .bind_compute_pipeline(&self.resource().trace_pipeline);
@@ -128,7 +334,20 @@ impl Renderer {
             &self.resource().traced_img,
             0,
             Some(0),
-            gl::WRITE_ONLY,
+            gl::READ_WRITE,
+        );
+        self.context
+            .borrow_mut()
+            .bind_image(1, &self.resource().aux_img, 0, None, gl::READ_WRITE);
+        self.context
+            .borrow_mut()
+            .bind_image(2, &self.resource().lobe_img, 0, None, gl::READ_WRITE);
+        self.context.borrow_mut().bind_image(
+            3,
+            &self.resource().blue_noise_img,
+            0,
+            Some(0),
+            gl::READ_ONLY,
         );
         self.context.borrow_mut().bind_shader_storage_buffer(
             1,
@@ -140,25 +359,383 @@ impl Renderer {
             &self.resource().variable_uniform_buffer,
             None,
         );
-        self.context.borrow().dispatch_compute(
-            (
-                self.output_config.width / 8,
-                self.output_config.height / 8,
-                1,
-            ),
-            true,
+        self.context.borrow_mut().bind_shader_storage_buffer(
+            6,
+            &self.resource().filter_uniform_buffer,
+            None,
         );
+        // every tile is flushed on its own, so that no single submission runs long enough
+        // to trip the driver watchdog on large images
+        let (width, height) = (self.output_config.width, self.output_config.height);
+        let tile_size = self.output_config.tile_size;
+        for tile_y in (0..height).step_by(tile_size as usize) {
+            for tile_x in (0..width).step_by(tile_size as usize) {
+                self.variable_uniform.tile_offset = [tile_x as i32, tile_y as i32];
+                self.context.borrow_mut().update_buffer(
+                    &self.resource().variable_uniform_buffer,
+                    0,
+                    bytemuck::bytes_of(&self.variable_uniform),
+                );
+                let groups = self.output_config.workgroup_size.groups(
+                    tile_size.min(width - tile_x),
+                    tile_size.min(height - tile_y),
+                );
+                self.context.borrow().dispatch_compute(groups, true);
+                self.context.borrow().flush();
+            }
+        }
+
+        self.traced_pixels = width * height - self.converged_pixels;
+        let resources = self.resource();
+        if let Some(adaptive) = &resources.adaptive {
+            let count = adaptive.update_mask(&mut self.context.borrow_mut(), &resources.aux_img);
+            self.converged_pixels = count;
+        }
+
+        let resources = self.gl_resources.as_mut().unwrap();
+        resources.display_img = match resources.denoiser.as_mut() {
+            Some(denoiser) => denoiser.denoise(
+                &mut self.context.borrow_mut(),
+                &resources.traced_img,
+                &resources.aux_img,
+                self.variable_uniform.frame_index + 1,
+                &self.variable_uniform.camera,
+                &self.prev_camera,
+            ),
+            None => resources.traced_img.clone(),
+        };
+        self.prev_camera = self.variable_uniform.camera;
 
+        let (fb_width, fb_height) = self.framebuffer_size;
+        let viewport = Viewport::letterbox(
+            (self.output_config.width, self.output_config.height),
+            self.framebuffer_size,
+        );
+        self.context
+            .borrow()
+            .set_viewport(0, 0, fb_width, fb_height);
+        self.context.borrow().clear([0.0, 0.0, 0.0, 1.0]);
+        self.context
+            .borrow()
+            .set_viewport(viewport.x, viewport.y, viewport.width, viewport.height);
         self.context
             .borrow_mut()
             .bind_graphics_pipeline(&self.resource().post_pipeline);
         self.context
             .borrow_mut()
-            .bind_texture(0, &self.resource().traced_img);
+            .bind_texture(0, &self.resource().display_img);
         self.context
             .borrow_mut()
             .bind_sampler(0, &self.resource().traced_img_sampler);
+        self.context
+            .borrow_mut()
+            .bind_texture(1, &self.resource().aux_img);
+        self.context
+            .borrow_mut()
+            .bind_sampler(1, &self.resource().traced_img_sampler);
+        self.context.borrow_mut().bind_uniform_buffer(
+            3,
+            &self.resource().post_uniform_buffer,
+            None,
+        );
         self.context.borrow().draw(3);
+
+        if timed {
+            self.context.borrow().end_query(&timer_query);
+            self.timer_pending = true;
+        }
+
+        self.variable_uniform.frame_index += 1;
+    }
+
+    /// Number of samples per pixel accumulated so far
+    pub fn samples(&self) -> u32 {
+        self.variable_uniform.frame_index
+    }
+
+    /// Share of the pixels adaptive sampling stopped tracing, `None` if it is disabled
+    pub fn converged_ratio(&self) -> Option<f32> {
+        self.output_config.adaptive.map(|_| {
+            self.converged_pixels as f32
+                / (self.output_config.width * self.output_config.height) as f32
+        })
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        RenderStats {
+            samples: self.samples(),
+            width: self.output_config.width,
+            height: self.output_config.height,
+            frame_time: self.frame_time,
+            gpu_time: self.gpu_time,
+            traced_pixels: self.traced_pixels,
+            triangles: self.triangle_count,
+            bvh_nodes: self.bvh_node_count,
+        }
+    }
+
+    pub fn camera(&self) -> &core::Camera {
+        &self.camera
+    }
+
+    /// Moves the camera, accumulation restarts from the next frame
+    pub fn set_camera(&mut self, camera: core::Camera) {
+        self.camera = camera;
+        self.variable_uniform.camera = Camera::new(&camera);
+        self.restart_accumulation();
+    }
+
+    pub fn materials(&self) -> &[core::Material] {
+        &self.materials
+    }
+
+    /// Replaces a material of the scene, accumulation restarts from the next frame
+    pub fn set_material(&mut self, index: usize, material: core::Material) {
+        self.materials[index] = material;
+        self.scene_uniform.materials[index] = uniforms::Material::new(&material);
+        let offset = std::mem::offset_of!(SceneUniform, materials)
+            + index * std::mem::size_of::<uniforms::Material>();
+        self.update_scene_uniform(offset, &self.scene_uniform.materials[index]);
+        self.restart_accumulation();
+    }
+
+    pub fn lights(&self) -> &[core::Light] {
+        &self.lights
+    }
+
+    /// Replaces a light of the scene, accumulation restarts from the next frame
+    pub fn set_light(&mut self, index: usize, light: core::Light) {
+        self.lights[index] = light;
+        self.scene_uniform.lights[index] = uniforms::Light::new(&light);
+        let offset = std::mem::offset_of!(SceneUniform, lights)
+            + index * std::mem::size_of::<uniforms::Light>();
+        self.update_scene_uniform(offset, &self.scene_uniform.lights[index]);
+        self.restart_accumulation();
+    }
+
+    pub fn max_depth(&self) -> u32 {
+        self.scene_uniform.max_depth
+    }
+
+    /// Changes the maximum path length, accumulation restarts from the next frame
+    pub fn set_max_depth(&mut self, max_depth: u32) {
+        self.scene_uniform.max_depth = max_depth;
+        let offset = std::mem::offset_of!(SceneUniform, max_depth);
+        self.update_scene_uniform(offset, &self.scene_uniform.max_depth);
+        self.restart_accumulation();
+    }
+
+    /// Handles a new window size according to `OutputConfig::resize`, a zero size (a
+    /// minimized window) is ignored
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.framebuffer_size = (width, height);
+        if self.output_config.resize != ResizeMode::Reallocate {
+            return;
+        }
+
+        let scale = self.output_config.scale;
+        let (width, height) = ((width / scale).max(1), (height / scale).max(1));
+        if (width, height) == (self.output_config.width, self.output_config.height) {
+            return;
+        }
+        self.output_config.width = width;
+        self.output_config.height = height;
+
+        let [traced_img, aux_img, lobe_img] = self.create_images();
+        let mut context = self.context.borrow_mut();
+        let resources = self.gl_resources.as_mut().unwrap();
+        for img in [
+            &resources.traced_img,
+            &resources.aux_img,
+            &resources.lobe_img,
+        ] {
+            context.delete_texture(img);
+        }
+        resources.display_img = traced_img.clone();
+        resources.traced_img = traced_img;
+        resources.aux_img = aux_img;
+        resources.lobe_img = lobe_img;
+        if let Some(denoiser) = resources.denoiser.as_mut() {
+            denoiser.resize(&mut context, width, height);
+        }
+        if let Some(adaptive) = resources.adaptive.as_mut() {
+            adaptive.resize(width, height);
+        }
+        drop(context);
+
+        self.restart_accumulation();
+    }
+
+    /// Shows `view` instead of the radiance in the window, `None` shows the radiance
+    pub fn set_view(&mut self, view: Option<Aov>) {
+        self.view = view;
+        self.update_post_uniform();
+    }
+
+    /// Highlights an object (by its index in the scene file) in the window
+    pub fn set_selected_object(&mut self, object: Option<u32>) {
+        self.selected_object = object;
+        self.update_post_uniform();
+    }
+
+    /// Pixel of the traced image (rows from top to bottom) shown at a position of the window
+    /// in framebuffer pixels from the top left, `None` outside of the image
+    pub fn image_pixel(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let image = (self.output_config.width, self.output_config.height);
+        Viewport::letterbox(image, self.framebuffer_size).image_pixel(
+            self.framebuffer_size.1,
+            (x, y),
+            image,
+        )
+    }
+
+    /// Radiance shown in the last frame (denoised if the denoiser is enabled) as RGBA,
+    /// rows from top to bottom
+    pub fn read_output_image(&self) -> Vec<f32> {
+        self.read_texture_f32(&self.resource().display_img)
+    }
+
+    /// AOVs and lobes of the last frame, the layers saved next to the image
+    pub fn read_layers(&self) -> Vec<ImageLayer> {
+        let mut layers = self.read_aovs();
+        layers.extend(self.read_lobes());
+        layers
+    }
+
+    /// AOVs of the last frame and the sample count of every pixel with adaptive sampling,
+    /// taken from the aux image
+    fn read_aovs(&self) -> Vec<ImageLayer> {
+        let data = self.read_texture_f32(&self.resource().aux_img);
+        let layer_size = (self.output_config.width * self.output_config.height * 4) as usize;
+        let layer =
+            |index: usize| data[index * layer_size..(index + 1) * layer_size].chunks_exact(4);
+
+        let mut layers: Vec<_> = Aov::ALL
+            .iter()
+            .map(|&aov| {
+                let data = match aov {
+                    Aov::Albedo => layer(AUX_ALBEDO).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+                    Aov::Normal => layer(AUX_NORMAL_DEPTH)
+                        .flat_map(|p| [p[0], p[1], p[2]])
+                        .collect(),
+                    Aov::Depth => layer(AUX_NORMAL_DEPTH).map(|p| p[3]).collect(),
+                    Aov::Position => layer(AUX_POSITION)
+                        .flat_map(|p| [p[0], p[1], p[2]])
+                        .collect(),
+                    Aov::ObjectId => layer(AUX_IDS).map(|p| p[0]).collect(),
+                    Aov::MaterialId => layer(AUX_IDS).map(|p| p[1]).collect(),
+                };
+                aov.layer(data)
+            })
+            .collect();
+        if self.output_config.adaptive.is_some() {
+            layers.push(ImageLayer {
+                name: "samples",
+                channels: &["X"],
+                data: layer(AUX_SAMPLES).map(|p| p[0]).collect(),
+            });
+        }
+        layers
+    }
+
+    /// Separately accumulated lobes of the last frame, in the order of `Lobe::ALL`
+    fn read_lobes(&self) -> Vec<ImageLayer> {
+        if self.lobe_layers() == 0 {
+            return vec![];
+        }
+
+        let data = self.read_texture_f32(&self.resource().lobe_img);
+        let layer_size = (self.output_config.width * self.output_config.height * 4) as usize;
+        Lobe::ALL
+            .iter()
+            .filter(|&&lobe| self.variable_uniform.lobe_mask & 1 << lobe as u32 != 0)
+            .zip(data.chunks_exact(layer_size))
+            .map(|(&lobe, layer)| {
+                let data = layer
+                    .chunks_exact(4)
+                    .flat_map(|p| [p[0], p[1], p[2]])
+                    .collect();
+                lobe.layer(data)
+            })
+            .collect()
+    }
+
+    fn update_post_uniform(&self) {
+        let post_uniform =
+            PostUniform::new(&self.output_config.display, self.view, self.selected_object);
+        self.context.borrow_mut().update_buffer(
+            &self.resource().post_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&post_uniform),
+        );
+    }
+
+    fn restart_accumulation(&mut self) {
+        self.variable_uniform.frame_index = 0;
+        self.converged_pixels = 0;
+    }
+
+    /// Uploads `field`, which is at `offset` in `self.scene_uniform`, to the scene uniform buffer
+    fn update_scene_uniform<T: bytemuck::Pod>(&self, offset: usize, field: &T) {
+        debug_assert!(offset + std::mem::size_of::<T>() <= std::mem::size_of::<SceneUniform>());
+        self.context.borrow_mut().update_buffer(
+            &self.resource().scene_uniform_buffer,
+            offset as u32,
+            bytemuck::bytes_of(field),
+        );
+    }
+
+    /// Traced, aux and lobe images at the output size
+    fn create_images(&self) -> [Rc<Texture>; 3] {
+        let mut context = self.context.borrow_mut();
+        let info = TextureInfo {
+            width: self.output_config.width,
+            height: self.output_config.height,
+            layers: 1,
+            mips: 1,
+            samples: 1,
+            format: gl::RGBA32F,
+            ty: gl::TEXTURE_2D,
+        };
+        let traced_img = context.create_texture(info);
+
+        let info = TextureInfo {
+            width: self.output_config.width,
+            height: self.output_config.height,
+            layers: AUX_LAYERS,
+            mips: 1,
+            samples: 1,
+            format: gl::RGBA32F,
+            ty: gl::TEXTURE_2D_ARRAY,
+        };
+        let aux_img = context.create_texture(info);
+
+        let info = TextureInfo {
+            width: self.output_config.width,
+            height: self.output_config.height,
+            layers: self.lobe_layers().max(1),
+            mips: 1,
+            samples: 1,
+            format: gl::RGBA32F,
+            ty: gl::TEXTURE_2D_ARRAY,
+        };
+        let lobe_img = context.create_texture(info);
+
+        [traced_img, aux_img, lobe_img]
+    }
+
+    fn lobe_layers(&self) -> u32 {
+        self.variable_uniform.lobe_mask.count_ones()
+    }
+
+    fn read_texture_f32(&self, tex: &Rc<Texture>) -> Vec<f32> {
+        let data = self.context.borrow().read_texture(tex, 0);
+        data.chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect()
     }
 
     fn resource(&self) -> &GlResources {
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// Progress and performance of the GPU renderer, shown in the window title
+pub struct RenderStats {
+    pub samples: u32,
+    pub width: u32,
+    pub height: u32,
+    /// wall clock time between the last two frames
+    pub frame_time: Option<Duration>,
+    /// `GL_TIME_ELAPSED` of the latest frame whose query is finished
+    pub gpu_time: Option<Duration>,
+    /// pixels traced in the last frame, fewer than all of them with adaptive sampling
+    pub traced_pixels: u32,
+    pub triangles: usize,
+    pub bvh_nodes: usize,
+}
+
+impl RenderStats {
+    /// Camera rays traced per second, each of them is a whole path so the actual number
+    /// of rays is larger
+    pub fn rays_per_second(&self) -> Option<f64> {
+        let time = self.gpu_time.or(self.frame_time)?.as_secs_f64();
+        (time > 0.0).then(|| self.traced_pixels as f64 / time)
+    }
+
+    pub fn title(&self) -> String {
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        let mut parts = vec![format!("{} spp", self.samples)];
+        match (self.frame_time, self.gpu_time) {
+            (Some(frame), Some(gpu)) => {
+                parts.push(format!("{:.1} ms (GPU {:.1} ms)", ms(frame), ms(gpu)))
+            }
+            (Some(frame), None) => parts.push(format!("{:.1} ms", ms(frame))),
+            _ => {}
+        }
+        if let Some(rays) = self.rays_per_second() {
+            parts.push(format!("{:.1} Mrays/s", rays / 1e6));
+        }
+        parts.push(format!("{}x{}", self.width, self.height));
+        parts.push(format!("{} triangles", self.triangles));
+        parts.push(format!("{} BVH nodes", self.bvh_nodes));
+        format!("simple-path-tracer-gl - {}", parts.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_lists_the_stats() {
+        let mut stats = RenderStats {
+            samples: 16,
+            width: 800,
+            height: 600,
+            frame_time: Some(Duration::from_millis(20)),
+            gpu_time: Some(Duration::from_millis(16)),
+            traced_pixels: 480000,
+            triangles: 1234,
+            bvh_nodes: 567,
+        };
+        assert_eq!(
+            stats.title(),
+            "simple-path-tracer-gl - 16 spp | 20.0 ms (GPU 16.0 ms) | 30.0 Mrays/s | 800x600 \
+             | 1234 triangles | 567 BVH nodes"
+        );
+
+        stats.frame_time = None;
+        stats.gpu_time = None;
+        assert_eq!(stats.rays_per_second(), None);
+        assert_eq!(
+            stats.title(),
+            "simple-path-tracer-gl - 16 spp | 800x600 | 1234 triangles | 567 BVH nodes"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+/// What happens to the traced image when the window is resized
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ResizeMode {
+    /// the image keeps its size and is scaled to fit the window, preserving its aspect
+    #[default]
+    Letterbox,
+    /// the image is reallocated at the window size divided by `OutputConfig::scale` and
+    /// accumulation restarts
+    Reallocate,
+}
+
+/// Area of the window the image is drawn to, in pixels from the bottom left
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    /// Largest area of `framebuffer` with the aspect of `image`, centered with black bars
+    /// at the sides or at the top and bottom
+    pub fn letterbox(image: (u32, u32), framebuffer: (u32, u32)) -> Self {
+        let (image_width, image_height) = (image.0 as u64, image.1 as u64);
+        let (fb_width, fb_height) = (framebuffer.0 as u64, framebuffer.1 as u64);
+        let (width, height) = if fb_width * image_height > fb_height * image_width {
+            (fb_height * image_width / image_height, fb_height)
+        } else {
+            (fb_width, fb_width * image_height / image_width)
+        };
+        Self {
+            x: ((fb_width - width) / 2) as i32,
+            y: ((fb_height - height) / 2) as i32,
+            width: width as u32,
+            height: height as u32,
+        }
+    }
+
+    /// Pixel of a `image` sized image (rows from top to bottom) shown in the viewport at a
+    /// framebuffer position (from the top left, like cursor positions), `None` if the
+    /// position is outside of the viewport
+    pub fn image_pixel(
+        &self,
+        framebuffer_height: u32,
+        position: (f64, f64),
+        image: (u32, u32),
+    ) -> Option<(u32, u32)> {
+        // `y` of the viewport is from the bottom
+        let top = framebuffer_height as f64 - self.y as f64 - self.height as f64;
+        let (x, y) = (position.0 - self.x as f64, position.1 - top);
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        let pixel_x = (x / self.width as f64 * image.0 as f64) as u32;
+        let pixel_y = (y / self.height as f64 * image.1 as f64) as u32;
+        Some((pixel_x.min(image.0 - 1), pixel_y.min(image.1 - 1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterbox_keeps_the_aspect() {
+        let viewport = |x, y, width, height| Viewport {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            Viewport::letterbox((800, 600), (800, 600)),
+            viewport(0, 0, 800, 600)
+        );
+        assert_eq!(
+            Viewport::letterbox((800, 600), (1600, 1200)),
+            viewport(0, 0, 1600, 1200)
+        );
+        // wider window, bars at the sides
+        assert_eq!(
+            Viewport::letterbox((800, 600), (1000, 600)),
+            viewport(100, 0, 800, 600)
+        );
+        // taller window, bars at the top and bottom
+        assert_eq!(
+            Viewport::letterbox((800, 600), (400, 600)),
+            viewport(0, 150, 400, 300)
+        );
+        assert_eq!(
+            Viewport::letterbox((800, 600), (0, 0)),
+            viewport(0, 0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn image_pixels_of_positions() {
+        // 400x300 image shown at 800x600 with bars at the top and bottom of a 800x800 window
+        let viewport = Viewport::letterbox((400, 300), (800, 800));
+        let pixel = |x, y| viewport.image_pixel(800, (x, y), (400, 300));
+        assert_eq!(pixel(0.0, 100.0), Some((0, 0)));
+        assert_eq!(pixel(799.9, 699.9), Some((399, 299)));
+        assert_eq!(pixel(401.0, 400.5), Some((200, 150)));
+        assert_eq!(pixel(400.0, 99.0), None);
+        assert_eq!(pixel(400.0, 700.0), None);
+    }
+}
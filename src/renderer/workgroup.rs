@@ -0,0 +1,60 @@
+/// Workgroup size of the compute shaders, defined as `WORKGROUP_SIZE_X` and
+/// `WORKGROUP_SIZE_Y` when they are compiled
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WorkgroupSize {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Default for WorkgroupSize {
+    fn default() -> Self {
+        Self { x: 8, y: 8 }
+    }
+}
+
+impl WorkgroupSize {
+    /// `source` with the size defined right after its '#version' line
+    pub fn shader_source(&self, source: &str) -> String {
+        let (version, rest) = match source.find('\n') {
+            Some(end) if source.starts_with("#version") => source.split_at(end + 1),
+            _ => ("", source),
+        };
+        // '#line' keeps the line numbers of compile errors those of the file
+        format!(
+            "{}#define WORKGROUP_SIZE_X {}\n#define WORKGROUP_SIZE_Y {}\n#line {}\n{}",
+            version,
+            self.x,
+            self.y,
+            if version.is_empty() { 1 } else { 2 },
+            rest
+        )
+    }
+
+    /// Number of groups covering `width` x `height` pixels, the shaders skip pixels outside
+    pub fn groups(&self, width: u32, height: u32) -> (u32, u32, u32) {
+        (width.div_ceil(self.x), height.div_ceil(self.y), 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_cover_the_image() {
+        let size = WorkgroupSize { x: 8, y: 4 };
+        assert_eq!(size.groups(800, 600), (100, 150, 1));
+        assert_eq!(size.groups(801, 601), (101, 151, 1));
+        assert_eq!(size.groups(1, 1), (1, 1, 1));
+    }
+
+    #[test]
+    fn defines_follow_the_version() {
+        let size = WorkgroupSize { x: 16, y: 2 };
+        let source = size.shader_source("#version 450\r\n\r\nvoid main() {}\r\n");
+        assert_eq!(
+            source,
+            "#version 450\r\n#define WORKGROUP_SIZE_X 16\n#define WORKGROUP_SIZE_Y 2\n#line 2\n\r\nvoid main() {}\r\n"
+        );
+    }
+}
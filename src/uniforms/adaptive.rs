@@ -0,0 +1,7 @@
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct AdaptiveUniform {
+    pub min_samples: i32,
+    pub target_error: f32,
+    pub _pad: [f32; 2],
+}
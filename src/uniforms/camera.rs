@@ -1,4 +1,4 @@
-use cgmath::{InnerSpace, Vector3};
+use crate::core;
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -13,19 +13,22 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(eye: Vector3<f32>, forward: Vector3<f32>, up: Vector3<f32>, fov_deg: f32) -> Self {
-        let forward = forward.normalize();
-        let right = forward.cross(up).normalize();
-        let up = right.cross(forward);
-        let fov = fov_deg * std::f32::consts::PI / 180.0;
+    pub fn new(camera: &core::Camera) -> Self {
+        let core::Camera {
+            eye,
+            forward,
+            up,
+            right,
+            fov_deg,
+        } = *camera;
 
         Self {
             eye: [eye.x, eye.y, eye.z, 1.0],
             forward: [forward.x, forward.y, forward.z, 0.0],
             up: [up.x, up.y, up.z, 0.0],
             right: [right.x, right.y, right.z, 0.0],
-            fov,
-            half_cot_half_fov: 0.5 / (fov * 0.5).tan(),
+            fov: fov_deg.to_radians(),
+            half_cot_half_fov: camera.half_cot_half_fov(),
             _pad: [0.0, 0.0],
         }
     }
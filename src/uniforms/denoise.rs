@@ -0,0 +1,23 @@
+use super::Camera;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DenoiseUniform {
+    pub pass_index: i32,
+    pub pass_count: i32,
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_depth: f32,
+    pub _pad: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TemporalUniform {
+    pub camera: Camera,
+    pub prev_camera: Camera,
+    pub temporal_alpha: f32,
+    pub samples: i32,
+    pub history_valid: i32,
+    pub _pad: f32,
+}
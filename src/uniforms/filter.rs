@@ -0,0 +1,23 @@
+use crate::output::{FilterTable, FILTER_BINS};
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FilterUniform {
+    pub radius: f32,
+    pub weight: f32,
+    _pad: [f32; 2],
+    pub cdf: [f32; FILTER_BINS],
+    pub sign: [f32; FILTER_BINS],
+}
+
+impl FilterUniform {
+    pub fn new(table: &FilterTable) -> Self {
+        Self {
+            radius: table.radius,
+            weight: table.weight,
+            _pad: [0.0; 2],
+            cdf: table.cdf,
+            sign: table.sign,
+        }
+    }
+}
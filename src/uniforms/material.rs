@@ -1,3 +1,5 @@
+use crate::core;
+
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Material {
@@ -6,22 +8,20 @@ pub struct Material {
     metallic: f32,
     is_translucent: i32,
     _pad: f32,
+    emission: [f32; 4],
 }
 
 impl Material {
-    pub fn new(
-        alebdo: [f32; 3],
-        ior: f32,
-        roughness: f32,
-        metallic: f32,
-        is_translucent: bool,
-    ) -> Self {
+    pub fn new(material: &core::Material) -> Self {
+        let albedo = material.albedo;
+        let emission = material.emission;
         Self {
-            albedo_ior: [alebdo[0], alebdo[1], alebdo[2], ior],
-            roughness,
-            metallic,
-            is_translucent: is_translucent as _,
+            albedo_ior: [albedo[0], albedo[1], albedo[2], material.ior],
+            roughness: material.roughness,
+            metallic: material.metallic,
+            is_translucent: material.is_translucent as _,
             _pad: 0.0,
+            emission: [emission[0], emission[1], emission[2], 0.0],
         }
     }
 }
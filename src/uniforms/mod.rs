@@ -1,17 +1,29 @@
+mod adaptive;
 mod bbox;
 mod bvhnode;
 mod camera;
+mod denoise;
+mod filter;
 mod light;
 mod material;
 mod object;
+mod post;
 mod triangle;
 
+use anyhow::{bail, Result};
+
+use crate::core::{Scene, Shape};
+
+pub use adaptive::*;
 pub use bbox::*;
 pub use bvhnode::*;
 pub use camera::*;
+pub use denoise::*;
+pub use filter::*;
 pub use light::*;
 pub use material::*;
 pub use object::*;
+pub use post::*;
 pub use triangle::*;
 
 /*
@@ -41,17 +53,145 @@ pub struct SceneUniform {
     pub lights: [Light; LIGHTS_COUNT],
     pub lights_count: u32,
     pub max_depth: u32,
-    _pad: [f32; 2],
+    pub sampler_kind: u32,
+    _pad: f32,
+    /// constant radiance of rays leaving the scene, w is 0 if the scene has none
+    pub environment: [f32; 4],
 }
 
 unsafe impl bytemuck::Zeroable for SceneUniform {}
 
 unsafe impl bytemuck::Pod for SceneUniform {}
 
+impl SceneUniform {
+    /// Fails if the scene has more of something than the uniform has room for
+    pub fn check_capacity(scene: &Scene) -> Result<()> {
+        let vertex_count = scene
+            .meshes
+            .iter()
+            .flatten()
+            .map(|mesh| mesh.vertices.len())
+            .sum();
+        let counts = [
+            ("BVH nodes", scene.bvh.stats().node_count, BVH_NODES_COUNT),
+            ("vertices", vertex_count, VERTICES_COUNT),
+            ("triangles", scene.triangles.len(), TRIANGLES_COUNT),
+            ("objects", scene.transforms.len(), OBJECTS_COUNT),
+            ("materials", scene.materials.len(), MATERIALS_COUNT),
+            ("lights", scene.lights.len(), LIGHTS_COUNT),
+        ];
+        for (name, count, max_count) in counts {
+            if count > max_count {
+                bail!(
+                    "scene: {} {} are more than the {} the renderer supports",
+                    count,
+                    name,
+                    max_count
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The scene should pass `check_capacity`
+    pub fn new(scene: &Scene) -> Box<Self> {
+        // too large for the stack, so it is allocated on the heap directly
+        let mut scene_uniform = unsafe {
+            let layout = std::alloc::Layout::new::<SceneUniform>();
+            let prt = std::alloc::alloc_zeroed(layout) as *mut SceneUniform;
+            Box::from_raw(prt)
+        };
+
+        scene_uniform.max_depth = scene.max_depth;
+        scene_uniform.sampler_kind = scene.sampler as u32;
+        if let Some([r, g, b]) = scene.environment {
+            scene_uniform.environment = [r, g, b, 1.0];
+        }
+        // bvh nodes
+        scene.bvh.fill_in_uniform(&mut scene_uniform);
+        // mesh vertices
+        let mut vertex_index = 0;
+        let mut index_offsets = vec![0; scene.meshes.iter().map(|model| model.len()).sum()];
+        let mut index_offset = 0;
+        for model in &scene.meshes {
+            for mesh in model {
+                for vert in &mesh.vertices {
+                    assert!(
+                        vertex_index < scene_uniform.vertices.len(),
+                        "too many vertices"
+                    );
+                    scene_uniform.vertices[vertex_index] = Vertex::new(vert.position, vert.normal);
+                    vertex_index += 1;
+                }
+                index_offsets[mesh.mesh_index as usize] = index_offset;
+                index_offset += mesh.vertices.len();
+            }
+        }
+        // objects & object triangles
+        for (index, tri) in scene.triangles.iter().enumerate() {
+            let indices = match &tri.shape {
+                Shape::Mesh { mesh, indices } => {
+                    let offset = index_offsets[mesh.mesh_index as usize];
+                    [
+                        indices[0] + offset,
+                        indices[1] + offset,
+                        indices[2] + offset,
+                    ]
+                }
+                Shape::Analytic(_) => [0; 3],
+            };
+            assert!(index < scene_uniform.triangles.len(), "too many triangles");
+            assert!(
+                (tri.trans_index as usize) < scene_uniform.objects.len(),
+                "too many objects"
+            );
+            scene_uniform.triangles[index] =
+                Triangle::new(indices, tri.material, tri.trans_index, tri.shape.kind());
+            scene_uniform.objects[tri.trans_index as usize] =
+                SceneObject::new(scene.transforms[tri.trans_index as usize]);
+        }
+        //materials
+        for (index, mat) in scene.materials.iter().enumerate() {
+            assert!(index < scene_uniform.materials.len(), "too many materials");
+            scene_uniform.materials[index] = Material::new(mat);
+        }
+        // lights
+        scene_uniform.lights_count = scene.lights.len() as u32;
+        for (index, light) in scene.lights.iter().enumerate() {
+            assert!(index < scene_uniform.lights.len(), "too many lights");
+            scene_uniform.lights[index] = Light::new(light);
+        }
+
+        scene_uniform
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct VariableUniform {
     pub camera: Camera,
-    pub curr_light_index: u32,
-    _pad: [f32; 3],
+    pub render_mode: u32,
+    /// number of frames accumulated in the traced image, converged pixels of adaptive
+    /// sampling have fewer samples
+    pub frame_index: u32,
+    /// `Lobe::mask` of the lobes accumulated separately
+    pub lobe_mask: u32,
+    _pad: u32,
+    /// pixel of the traced image at the first invocation of the current tile
+    pub tile_offset: [i32; 2],
+    _pad2: [f32; 2],
+}
+
+impl VariableUniform {
+    pub fn new(scene: &Scene, render_mode: u32, lobe_mask: u32) -> Self {
+        Self {
+            camera: Camera::new(&scene.camera),
+            render_mode,
+            frame_index: 0,
+            lobe_mask,
+            _pad: 0,
+            tile_offset: [0; 2],
+            _pad2: [0.0; 2],
+        }
+    }
 }
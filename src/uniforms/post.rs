@@ -0,0 +1,36 @@
+use cgmath::Matrix3;
+
+use crate::output::{Aov, DisplayConfig};
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PostUniform {
+    white_balance: [[f32; 4]; 3],
+    exposure_scale: f32,
+    tone_mapper: i32,
+    srgb: i32,
+    view: i32,
+    /// object highlighted in the window, -1 for none
+    selected_object: i32,
+    _pad: [f32; 3],
+}
+
+impl PostUniform {
+    /// `view` is the AOV to show instead of the radiance
+    pub fn new(display: &DisplayConfig, view: Option<Aov>, selected_object: Option<u32>) -> Self {
+        let white_balance: Matrix3<f32> = display.white_balance_matrix();
+        Self {
+            white_balance: [
+                white_balance.x.extend(0.0).into(),
+                white_balance.y.extend(0.0).into(),
+                white_balance.z.extend(0.0).into(),
+            ],
+            exposure_scale: display.exposure_scale(),
+            tone_mapper: display.tone_mapper as i32,
+            srgb: display.srgb as i32,
+            view: view.map_or(0, |aov| aov as i32 + 1),
+            selected_object: selected_object.map_or(-1, |object| object as i32),
+            _pad: [0.0; 3],
+        }
+    }
+}
@@ -22,16 +22,19 @@ pub struct Triangle {
     indices: [u32; 4],
     material_index: u32,
     object_index: u32,
-    _pad: [f32; 2],
+    /// `Shape::kind`, `indices` are unused by analytic shapes
+    kind: u32,
+    _pad: f32,
 }
 
 impl Triangle {
-    pub fn new(indices: [usize; 3], material_index: u32, object_index: u32) -> Self {
+    pub fn new(indices: [usize; 3], material_index: u32, object_index: u32, kind: u32) -> Self {
         Self {
             indices: [indices[0] as u32, indices[1] as u32, indices[2] as u32, 0],
             material_index,
             object_index,
-            _pad: [0.0, 0.0],
+            kind,
+            _pad: 0.0,
         }
     }
 }
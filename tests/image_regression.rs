@@ -0,0 +1,251 @@
+// Renders the scenes in `scenes/` with a fixed sample count and size and compares them
+// with the references in `tests/references/`.
+//
+// The GPU tests need an OpenGL 4.5 context (Mesa llvmpipe works, e.g. under `xvfb-run`),
+// so they are ignored by default: `cargo test --test image_regression -- --include-ignored`.
+// Set `UPDATE_REFERENCES=1` to overwrite the references with the new renders.
+//
+// The random sequence only depends on the pixel and the sample index, so the CPU and the GPU
+// renderer produce the same image up to floating point differences and share the references.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const SPP: u32 = 16;
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+/// Root mean square error of the radiance, clamped to keep a few fireflies from dominating
+const MAX_RMSE: f32 = 0.02;
+/// Mean CIELAB ΔE of the displayable (clamped) colors
+const MAX_MEAN_DELTA_E: f32 = 1.0;
+/// Share of pixels whose ΔE is above `BAD_PIXEL_DELTA_E`
+const MAX_BAD_PIXEL_RATIO: f32 = 0.01;
+const BAD_PIXEL_DELTA_E: f32 = 10.0;
+
+struct Image {
+    width: u32,
+    height: u32,
+    rgb: Vec<f32>,
+}
+
+struct Metrics {
+    rmse: f32,
+    mean_delta_e: f32,
+    bad_pixel_ratio: f32,
+    delta_e: Vec<f32>,
+}
+
+#[test]
+fn cpu_test_scene_00() {
+    check_scene("test_scene_00", Renderer::Cpu);
+}
+
+#[test]
+fn cpu_test_scene_01() {
+    check_scene("test_scene_01", Renderer::Cpu);
+}
+
+#[test]
+#[ignore = "needs an OpenGL 4.5 context"]
+fn gpu_test_scene_00() {
+    check_scene("test_scene_00", Renderer::Gpu);
+}
+
+#[test]
+#[ignore = "needs an OpenGL 4.5 context"]
+fn gpu_test_scene_01() {
+    check_scene("test_scene_01", Renderer::Gpu);
+}
+
+#[derive(Clone, Copy)]
+enum Renderer {
+    Cpu,
+    Gpu,
+}
+
+impl Renderer {
+    fn name(self) -> &'static str {
+        match self {
+            Renderer::Cpu => "cpu",
+            Renderer::Gpu => "gpu",
+        }
+    }
+
+    fn flag(self) -> &'static str {
+        match self {
+            Renderer::Cpu => "--cpu",
+            Renderer::Gpu => "--headless",
+        }
+    }
+}
+
+fn check_scene(scene: &str, renderer: Renderer) {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("image_regression");
+    let rendered_path = out_dir.join(format!("{}_{}.exr", scene, renderer.name()));
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/references")
+        .join(format!("{}.exr", scene));
+
+    render(scene, renderer, &rendered_path);
+    let rendered = load_exr(&rendered_path);
+
+    if std::env::var_os("UPDATE_REFERENCES").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        std::fs::copy(&rendered_path, &reference_path).unwrap();
+        println!("Reference '{}' is updated", reference_path.display());
+        return;
+    }
+
+    assert!(
+        reference_path.exists(),
+        "reference '{}' is missing, run with UPDATE_REFERENCES=1 to create it",
+        reference_path.display()
+    );
+    let reference = load_exr(&reference_path);
+    assert!(
+        rendered.width == reference.width && rendered.height == reference.height,
+        "size mismatch: rendered {}x{}, reference {}x{}",
+        rendered.width,
+        rendered.height,
+        reference.width,
+        reference.height
+    );
+
+    let metrics = compare(&rendered, &reference);
+    println!(
+        "{} ({}): rmse {:.5}, mean ΔE {:.4}, bad pixels {:.3}%",
+        scene,
+        renderer.name(),
+        metrics.rmse,
+        metrics.mean_delta_e,
+        metrics.bad_pixel_ratio * 100.0
+    );
+
+    if metrics.rmse > MAX_RMSE
+        || metrics.mean_delta_e > MAX_MEAN_DELTA_E
+        || metrics.bad_pixel_ratio > MAX_BAD_PIXEL_RATIO
+    {
+        let diff_path = out_dir.join(format!("{}_{}_diff.png", scene, renderer.name()));
+        save_diff_image(&diff_path, &reference, &metrics.delta_e);
+        panic!(
+            "{} ({}) differs from the reference: rmse {:.5} (max {}), mean ΔE {:.4} (max {}), \
+             bad pixels {:.3}% (max {}%); see '{}' and '{}'",
+            scene,
+            renderer.name(),
+            metrics.rmse,
+            MAX_RMSE,
+            metrics.mean_delta_e,
+            MAX_MEAN_DELTA_E,
+            metrics.bad_pixel_ratio * 100.0,
+            MAX_BAD_PIXEL_RATIO * 100.0,
+            rendered_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn render(scene: &str, renderer: Renderer, output: &Path) {
+    let scene_path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "scenes",
+        &format!("{}.json", scene),
+    ]
+    .iter()
+    .collect();
+    let status = Command::new(env!("CARGO_BIN_EXE_simple-path-tracer-gl"))
+        .arg(renderer.flag())
+        .arg(SPP.to_string())
+        .arg("--size")
+        .arg(format!("{}x{}", WIDTH, HEIGHT))
+        .arg("--output")
+        .arg(output)
+        .arg(&scene_path)
+        .status()
+        .expect("failed to run simple-path-tracer-gl");
+    assert!(status.success(), "rendering {} failed", scene);
+}
+
+fn load_exr(path: &Path) -> Image {
+    let image = image::open(path)
+        .unwrap_or_else(|err| panic!("failed to open '{}': {}", path.display(), err))
+        .into_rgb32f();
+    Image {
+        width: image.width(),
+        height: image.height(),
+        rgb: image.into_raw(),
+    }
+}
+
+fn compare(rendered: &Image, reference: &Image) -> Metrics {
+    let mut squared_error = 0.0;
+    let mut delta_e = Vec::with_capacity(reference.rgb.len() / 3);
+    for (a, b) in rendered
+        .rgb
+        .chunks_exact(3)
+        .zip(reference.rgb.chunks_exact(3))
+    {
+        for (x, y) in a.iter().zip(b) {
+            let diff = x.clamp(0.0, 16.0) - y.clamp(0.0, 16.0);
+            squared_error += (diff * diff) as f64;
+        }
+        let lab_a = linear_to_lab(a);
+        let lab_b = linear_to_lab(b);
+        let dist = (0..3).map(|i| (lab_a[i] - lab_b[i]).powi(2)).sum::<f32>();
+        delta_e.push(dist.sqrt());
+    }
+
+    let pixel_count = delta_e.len().max(1) as f32;
+    Metrics {
+        rmse: (squared_error / reference.rgb.len().max(1) as f64).sqrt() as f32,
+        mean_delta_e: delta_e.iter().sum::<f32>() / pixel_count,
+        bad_pixel_ratio: delta_e.iter().filter(|&&e| e > BAD_PIXEL_DELTA_E).count() as f32
+            / pixel_count,
+        delta_e,
+    }
+}
+
+/// CIELAB (D65) of a linear sRGB color clamped to [0, 1]
+fn linear_to_lab(rgb: &[f32]) -> [f32; 3] {
+    let [r, g, b] = [0, 1, 2].map(|i| rgb[i].clamp(0.0, 1.0));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Dimmed reference with the per-pixel ΔE on top, from unchanged (no difference) to red
+/// and yellow (ΔE of `2 * BAD_PIXEL_DELTA_E` or more)
+fn save_diff_image(path: &Path, reference: &Image, delta_e: &[f32]) {
+    let rgb = reference
+        .rgb
+        .chunks_exact(3)
+        .zip(delta_e)
+        .flat_map(|(pixel, &e)| {
+            let heat = (e / (2.0 * BAD_PIXEL_DELTA_E)).clamp(0.0, 1.0);
+            let base = pixel.iter().map(|v| v.clamp(0.0, 1.0) * 0.25).sum::<f32>() / 3.0;
+            [
+                base + (1.0 - base) * (heat * 2.0).min(1.0),
+                base + (1.0 - base) * (heat * 2.0 - 1.0).max(0.0),
+                base * (1.0 - heat),
+            ]
+        })
+        .map(|v| (v * 255.0 + 0.5) as u8)
+        .collect();
+    image::RgbImage::from_raw(reference.width, reference.height, rgb)
+        .unwrap()
+        .save(path)
+        .unwrap();
+    println!("Diff image is saved to '{}'", path.display());
+}